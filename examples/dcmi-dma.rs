@@ -0,0 +1,126 @@
+//! Continuous capture of a camera sensor with the DCMI into two DMA buffers.
+//!
+//! The DMA fills one buffer while the other one is processed, they are swapped at every
+//! transfer complete interrupt. The camera must already be configured, usually over I2C,
+//! to output 8-bit data synchronised by HSYNC and VSYNC.
+
+#![no_main]
+#![no_std]
+
+// Halt on panic
+use panic_halt as _;
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+use stm32f4xx_hal::pac::interrupt;
+use stm32f4xx_hal::{
+    dcmi::{CaptureMode, Config, Data8, Dcmi},
+    dma::{
+        config::{BurstMode, DmaConfig, FifoThreshold},
+        DMAError, DmaFlag, PeripheralToMemory, Stream1, StreamsTuple, Transfer,
+    },
+    pac::{self, DMA2},
+    prelude::*,
+};
+
+const BUFFER_WORDS: usize = 1024;
+
+type Buffer = &'static mut [u32; BUFFER_WORDS];
+type DcmiDma = Transfer<Stream1<DMA2>, 1, Dcmi<Data8>, PeripheralToMemory, Buffer>;
+
+static G_TRANSFER: Mutex<RefCell<Option<DcmiDma>>> = Mutex::new(RefCell::new(None));
+static G_SPARE: Mutex<RefCell<Option<Buffer>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Some(dp) = pac::Peripherals::take() {
+        let mut rcc = dp.RCC.freeze(
+            stm32f4xx_hal::rcc::Config::hse(8.MHz())
+                .sysclk(168.MHz())
+                .hclk(168.MHz()),
+        );
+
+        let gpioa = dp.GPIOA.split(&mut rcc);
+        let gpiob = dp.GPIOB.split(&mut rcc);
+        let gpioc = dp.GPIOC.split(&mut rcc);
+        let gpioe = dp.GPIOE.split(&mut rcc);
+
+        let dcmi = Dcmi::new(
+            dp.DCMI,
+            (gpioa.pa6, gpioa.pa4, gpiob.pb7),
+            (
+                gpioc.pc6, gpioc.pc7, gpioc.pc8, gpioc.pc9, gpioe.pe4, gpiob.pb6, gpioe.pe5,
+                gpioe.pe6,
+            ),
+            Config::default().mode(CaptureMode::Continuous),
+            &mut rcc,
+        );
+
+        let first = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+        let second = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+        let spare = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+
+        let streams = StreamsTuple::new(dp.DMA2, &mut rcc);
+        let config = DmaConfig::default()
+            .memory_increment(true)
+            .double_buffer(true)
+            .transfer_complete_interrupt(true)
+            .fifo_enable(true)
+            .fifo_threshold(FifoThreshold::Full)
+            .memory_burst(BurstMode::Burst4);
+        let mut transfer =
+            Transfer::init_peripheral_to_memory(streams.1, dcmi, first, Some(second), config);
+
+        transfer.start(|dcmi| dcmi.capture());
+
+        // Hand off transfer and spare buffer to interrupt handler
+        cortex_m::interrupt::free(|cs| {
+            *G_TRANSFER.borrow(cs).borrow_mut() = Some(transfer);
+            *G_SPARE.borrow(cs).borrow_mut() = Some(spare);
+        });
+        // Enable interrupt
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(pac::Interrupt::DMA2_STREAM1);
+        }
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM1() {
+    static mut TRANSFER: Option<DcmiDma> = None;
+    static mut SPARE: Option<Buffer> = None;
+
+    let transfer = TRANSFER.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| G_TRANSFER.borrow(cs).replace(None).unwrap())
+    });
+    if SPARE.is_none() {
+        *SPARE = cortex_m::interrupt::free(|cs| G_SPARE.borrow(cs).replace(None));
+    }
+
+    let flags = transfer.flags();
+    // Its important to clear fifo errors as the transfer is paused until it is cleared
+    transfer.clear_flags(DmaFlag::FifoError | DmaFlag::TransferComplete);
+    if flags.is_transfer_complete() {
+        if let Some(buffer) = SPARE.take() {
+            // Give the spare buffer to the DMA and get back the one just filled
+            match transfer.next_transfer(buffer) {
+                Ok((filled, _)) => {
+                    // Process the captured pixels here, then keep the buffer for the next swap
+                    *SPARE = Some(filled);
+                }
+                Err(DMAError::NotReady(buffer) | DMAError::SmallBuffer(buffer)) => {
+                    *SPARE = Some(buffer);
+                }
+                Err(_) => {
+                    // The buffer was not swapped in time and the captured data was overwritten
+                    panic!("DMA overrun");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Capture of a PDM microphone with the DFSDM into two DMA buffers.
+//!
+//! The microphone is clocked by the DFSDM clock output on PC2 and its data goes to channel 1
+//! on PA4. Filter 0 decimates the 2 MHz bit stream into 31.25 kHz samples, which the DMA
+//! writes alternately into two buffers. The level of each buffer is computed in the
+//! transfer complete interrupt.
+
+#![no_main]
+#![no_std]
+
+// Halt on panic
+use panic_halt as _;
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+use stm32f4xx_hal::pac::interrupt;
+use stm32f4xx_hal::{
+    dfsdm::{self, ChannelConfig, ClockSource, DfsdmExt, FilterConfig, SincOrder, SpiClock, FLT},
+    dma::{
+        config::DmaConfig, DMAError, DmaFlag, PeripheralToMemory, Stream0, StreamsTuple, Transfer,
+    },
+    pac::{self, DFSDM1, DMA2},
+    prelude::*,
+    rcc::Config,
+};
+
+const BUFFER_WORDS: usize = 512;
+
+type Buffer = &'static mut [u32; BUFFER_WORDS];
+type PdmDma = Transfer<Stream0<DMA2>, 7, FLT<DFSDM1, 0>, PeripheralToMemory, Buffer>;
+
+static G_TRANSFER: Mutex<RefCell<Option<PdmDma>>> = Mutex::new(RefCell::new(None));
+static G_SPARE: Mutex<RefCell<Option<Buffer>>> = Mutex::new(RefCell::new(None));
+
+/// Peak amplitude of the last buffer
+static LEVEL: AtomicU32 = AtomicU32::new(0);
+
+#[entry]
+fn main() -> ! {
+    if let Some(dp) = pac::Peripherals::take() {
+        // The clock output is divided from APB2
+        let mut rcc = dp.RCC.freeze(Config::hse(8.MHz()).sysclk(48.MHz()));
+
+        let gpioa = dp.GPIOA.split(&mut rcc);
+        let gpioc = dp.GPIOC.split(&mut rcc);
+
+        let (mut dfsdm, (_ch0, mut ch1, ..), (mut flt0, ..)) = dp.DFSDM1.split(&mut rcc);
+        // 48 MHz / 24 = 2 MHz PDM clock
+        dfsdm.set_clock_output(gpioc.pc2, ClockSource::System, 24);
+        dfsdm.enable();
+
+        ch1.datin(gpioa.pa4);
+        ch1.configure(ChannelConfig::default().spi_clock(SpiClock::Ckout));
+        ch1.enable();
+
+        // 2 MHz / 64 = 31.25 kHz
+        flt0.configure(
+            FilterConfig::default()
+                .order(SincOrder::Sinc4)
+                .oversampling(64),
+        );
+        flt0.set_regular_channel(&ch1, true);
+        flt0.set_regular_dma(true);
+        flt0.enable();
+
+        let first = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+        let second = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+        let spare = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+
+        let streams = StreamsTuple::new(dp.DMA2, &mut rcc);
+        let config = DmaConfig::default()
+            .memory_increment(true)
+            .double_buffer(true)
+            .transfer_complete_interrupt(true);
+        let mut transfer =
+            Transfer::init_peripheral_to_memory(streams.0, flt0, first, Some(second), config);
+
+        transfer.start(|flt0| flt0.start_regular());
+
+        // Hand off transfer and spare buffer to interrupt handler
+        cortex_m::interrupt::free(|cs| {
+            *G_TRANSFER.borrow(cs).borrow_mut() = Some(transfer);
+            *G_SPARE.borrow(cs).borrow_mut() = Some(spare);
+        });
+        // Enable interrupt
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(pac::Interrupt::DMA2_STREAM0);
+        }
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    static mut TRANSFER: Option<PdmDma> = None;
+    static mut SPARE: Option<Buffer> = None;
+
+    let transfer = TRANSFER.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| G_TRANSFER.borrow(cs).replace(None).unwrap())
+    });
+    if SPARE.is_none() {
+        *SPARE = cortex_m::interrupt::free(|cs| G_SPARE.borrow(cs).replace(None));
+    }
+
+    let flags = transfer.flags();
+    transfer.clear_flags(DmaFlag::FifoError | DmaFlag::TransferComplete);
+    if flags.is_transfer_complete() {
+        if let Some(buffer) = SPARE.take() {
+            // Give the spare buffer to the DMA and get back the one just filled
+            match transfer.next_transfer(buffer) {
+                Ok((filled, _)) => {
+                    let level = filled
+                        .iter()
+                        .map(|&word| dfsdm::sample(word).unsigned_abs())
+                        .max()
+                        .unwrap_or(0);
+                    LEVEL.store(level, Ordering::Relaxed);
+                    *SPARE = Some(filled);
+                }
+                Err(DMAError::NotReady(buffer) | DMAError::SmallBuffer(buffer)) => {
+                    *SPARE = Some(buffer);
+                }
+                Err(_) => {
+                    // The buffer was not swapped in time and the samples were overwritten
+                    panic!("DMA overrun");
+                }
+            }
+        }
+    }
+}
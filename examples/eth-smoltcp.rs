@@ -0,0 +1,84 @@
+//! Answers ARP and ping on 192.168.1.100 with smoltcp over the Ethernet MAC.
+//!
+//! The PHY is connected through the RMII, like on the STM32F4x7 evaluation boards, and
+//! must supply the 50 MHz reference clock. Its link is assumed to be 100 Mbit/s full
+//! duplex, read the negotiated mode over MDIO and call `EthernetMac::set_speed` otherwise.
+
+#![no_main]
+#![no_std]
+
+// Halt on panic
+use panic_halt as _;
+
+use core::ptr::addr_of_mut;
+use cortex_m_rt::entry;
+use smoltcp::iface::{self, Interface, SocketSet, SocketStorage};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr};
+use stm32f4xx_hal::{
+    eth::{self, RxRingEntry, TxRingEntry},
+    pac,
+    prelude::*,
+    rcc::Config,
+};
+
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x11, 0x22, 0x33, 0x44];
+
+static mut RX_RING: [RxRingEntry; 4] = [RxRingEntry::INIT; 4];
+static mut TX_RING: [TxRingEntry; 2] = [TxRingEntry::INIT; 2];
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    // The MAC needs HCLK above 25 MHz
+    let mut rcc = dp.RCC.freeze(Config::hse(8.MHz()).sysclk(168.MHz()));
+
+    let gpioa = dp.GPIOA.split(&mut rcc);
+    let gpiob = dp.GPIOB.split(&mut rcc);
+    let gpioc = dp.GPIOC.split(&mut rcc);
+    let gpiog = dp.GPIOG.split(&mut rcc);
+
+    let mut syscfg = dp.SYSCFG.constrain(&mut rcc);
+    let eth::Parts { mut dma, .. } = eth::new(
+        (
+            dp.ETHERNET_MAC,
+            dp.ETHERNET_MMC,
+            dp.ETHERNET_DMA,
+            dp.ETHERNET_PTP,
+        ),
+        (
+            gpioa.pa1, gpioa.pa7, gpiog.pg11, gpiog.pg13, gpiob.pb13, gpioc.pc4, gpioc.pc5,
+        ),
+        unsafe { &mut *addr_of_mut!(RX_RING) },
+        unsafe { &mut *addr_of_mut!(TX_RING) },
+        eth::Config::default().mac_address(MAC_ADDRESS),
+        &mut syscfg,
+        &mut rcc,
+    );
+
+    // 32-bit microsecond counter, extended to 64 bits below
+    let mut timer = dp.TIM2.counter_us(&mut rcc);
+    timer.start(u32::MAX.micros()).unwrap();
+    let mut last = timer.now().ticks();
+    let mut micros = 0u64;
+
+    let config = iface::Config::new(EthernetAddress(MAC_ADDRESS).into());
+    let mut iface = Interface::new(config, &mut dma, Instant::ZERO);
+    iface.update_ip_addrs(|addrs| {
+        addrs
+            .push(IpCidr::new(IpAddress::v4(192, 168, 1, 100), 24))
+            .unwrap();
+    });
+
+    // ICMP echo requests are answered by the interface itself, no socket is needed
+    let mut storage: [SocketStorage; 0] = [];
+    let mut sockets = SocketSet::new(&mut storage[..]);
+
+    loop {
+        let now = timer.now().ticks();
+        micros += now.wrapping_sub(last) as u64;
+        last = now;
+
+        iface.poll(Instant::from_micros(micros as i64), &mut dma, &mut sockets);
+    }
+}
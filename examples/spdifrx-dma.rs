@@ -0,0 +1,110 @@
+//! Reception of an SPDIF stream on PD7 into two DMA buffers.
+//!
+//! The DMA fills one buffer while the other one is processed, they are swapped at every
+//! transfer complete interrupt. Each buffer holds left and right 24-bit samples
+//! alternately. The SPDIFRX is clocked by the `R` output of the main PLL, 180 MHz here.
+
+#![no_main]
+#![no_std]
+
+// Halt on panic
+use panic_halt as _;
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+use stm32f4xx_hal::pac::interrupt;
+use stm32f4xx_hal::{
+    dma::{
+        config::DmaConfig, DMAError, DmaFlag, PeripheralToMemory, Stream1, StreamsTuple, Transfer,
+    },
+    pac::{self, DMA1},
+    prelude::*,
+    rcc::Config,
+    spdifrx::{self, Spdifrx},
+};
+
+const BUFFER_WORDS: usize = 512;
+
+type Buffer = &'static mut [u32; BUFFER_WORDS];
+type SpdifDma = Transfer<Stream1<DMA1>, 0, Spdifrx<0>, PeripheralToMemory, Buffer>;
+
+static G_TRANSFER: Mutex<RefCell<Option<SpdifDma>>> = Mutex::new(RefCell::new(None));
+static G_SPARE: Mutex<RefCell<Option<Buffer>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Some(dp) = pac::Peripherals::take() {
+        let mut rcc = dp.RCC.freeze(Config::hse(8.MHz()).sysclk(180.MHz()));
+
+        let gpiod = dp.GPIOD.split(&mut rcc);
+
+        let mut spdifrx =
+            Spdifrx::<0>::new(dp.SPDIFRX, gpiod.pd7, spdifrx::Config::default(), &mut rcc);
+        spdifrx.set_dma(true);
+
+        let first = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+        let second = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+        let spare = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+
+        let streams = StreamsTuple::new(dp.DMA1, &mut rcc);
+        let config = DmaConfig::default()
+            .memory_increment(true)
+            .double_buffer(true)
+            .transfer_complete_interrupt(true);
+        let mut transfer =
+            Transfer::init_peripheral_to_memory(streams.1, spdifrx, first, Some(second), config);
+
+        transfer.start(|spdifrx| spdifrx.start());
+
+        // Hand off transfer and spare buffer to interrupt handler
+        cortex_m::interrupt::free(|cs| {
+            *G_TRANSFER.borrow(cs).borrow_mut() = Some(transfer);
+            *G_SPARE.borrow(cs).borrow_mut() = Some(spare);
+        });
+        // Enable interrupt
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(pac::Interrupt::DMA1_STREAM1);
+        }
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[interrupt]
+fn DMA1_STREAM1() {
+    static mut TRANSFER: Option<SpdifDma> = None;
+    static mut SPARE: Option<Buffer> = None;
+
+    let transfer = TRANSFER.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| G_TRANSFER.borrow(cs).replace(None).unwrap())
+    });
+    if SPARE.is_none() {
+        *SPARE = cortex_m::interrupt::free(|cs| G_SPARE.borrow(cs).replace(None));
+    }
+
+    let flags = transfer.flags();
+    transfer.clear_flags(DmaFlag::FifoError | DmaFlag::TransferComplete);
+    if flags.is_transfer_complete() {
+        if let Some(buffer) = SPARE.take() {
+            // Give the spare buffer to the DMA and get back the one just filled
+            match transfer.next_transfer(buffer) {
+                Ok((filled, _)) => {
+                    for _frame in filled.chunks_exact(2) {
+                        // Process the left and right samples here
+                    }
+                    *SPARE = Some(filled);
+                }
+                Err(DMAError::NotReady(buffer) | DMAError::SmallBuffer(buffer)) => {
+                    *SPARE = Some(buffer);
+                }
+                Err(_) => {
+                    // The buffer was not swapped in time and the samples were overwritten
+                    panic!("DMA overrun");
+                }
+            }
+        }
+    }
+}
@@ -140,9 +140,26 @@ use crate::{
 use core::fmt;
 use core::ops::Deref;
 
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_interrupt, AdcAsync};
 pub mod config;
 mod f4;
 
+/// ADC error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A conversion was lost because DMA didn't read the previous one in time
+    Overrun,
+    /// DMA transfer error
+    Dma,
+    /// The buffer length doesn't match the sequence length
+    BufferLength,
+}
+
 /// Vref internal signal, used for calibration
 pub struct Vref;
 
@@ -156,6 +173,9 @@ pub struct Temperature;
 pub trait Instance:
     crate::Sealed + Deref<Target = pac::adc1::RegisterBlock> + rcc::Enable + rcc::Reset
 {
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn waker() -> &'static atomic_waker::AtomicWaker;
 }
 
 #[doc(hidden)]
@@ -164,11 +184,23 @@ pub trait Calibrate {
     fn calibrate(&mut self) {}
 }
 
-impl Instance for pac::ADC1 {}
+macro_rules! adc {
+    ($ADC:ty) => {
+        impl Instance for $ADC {
+            #[cfg(feature = "async")]
+            fn waker() -> &'static atomic_waker::AtomicWaker {
+                static WAKER: atomic_waker::AtomicWaker = atomic_waker::AtomicWaker::new();
+                &WAKER
+            }
+        }
+    };
+}
+
+adc!(pac::ADC1);
 #[cfg(feature = "adc2")]
-impl Instance for pac::ADC2 {}
+adc!(pac::ADC2);
 #[cfg(feature = "adc3")]
-impl Instance for pac::ADC3 {}
+adc!(pac::ADC3);
 
 /// Analog to Digital Converter
 #[derive(Clone, Copy)]
@@ -0,0 +1,213 @@
+//! Async conversions completed from the ADC interrupt.
+//!
+//! All ADCs share one interrupt, [`on_interrupt`] must be called from its handler:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn ADC() {
+//!     adc::on_interrupt();
+//! }
+//! ```
+
+use core::{
+    future::poll_fn,
+    ops::{Deref, DerefMut},
+    task::Poll,
+};
+
+use super::{config, Adc, Error, Instance};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, OneShot, PeripheralToMemory,
+};
+use crate::pac;
+
+/// Wakes the tasks waiting for conversions.
+///
+/// Must be called from the `ADC` interrupt handler when an ADC is used asynchronously.
+/// Interrupts of ADCs with pending events are disabled, the woken tasks enable them again
+/// if they still have to wait.
+pub fn on_interrupt() {
+    wake::<pac::ADC1>(unsafe { &*pac::ADC1::ptr() });
+    #[cfg(feature = "adc2")]
+    wake::<pac::ADC2>(unsafe { &*pac::ADC2::ptr() });
+    #[cfg(feature = "adc3")]
+    wake::<pac::ADC3>(unsafe { &*pac::ADC3::ptr() });
+}
+
+fn wake<ADC: Instance>(adc: &pac::adc1::RegisterBlock) {
+    let sr = adc.sr().read();
+    let cr1 = adc.cr1().read();
+    if (sr.eoc().bit_is_set() && cr1.eocie().bit_is_set())
+        || (sr.ovr().bit_is_set() && cr1.ovrie().bit_is_set())
+    {
+        adc.cr1().modify(|_, w| {
+            w.eocie().clear_bit();
+            w.ovrie().clear_bit()
+        });
+        ADC::waker().wake();
+    }
+}
+
+impl<ADC: Instance> Adc<ADC> {
+    /// Converts a single sample, waiting for the end of conversion interrupt.
+    /// Like [`convert`](Self::convert) it reconfigures the adc sequence and doesn't restore it
+    pub async fn convert_async<PIN>(&mut self, pin: &PIN, sample_time: config::SampleTime) -> u16
+    where
+        PIN: embedded_hal_02::adc::Channel<ADC, ID = u8>,
+    {
+        self.adc_reg.cr2().modify(|_, w| {
+            //Disable dma
+            w.dma().clear_bit();
+            //Disable continuous mode
+            w.cont().clear_bit();
+            //Disable trigger
+            w.exten().set(config::TriggerMode::Disabled.into());
+            //EOC is set at the end of the sequence
+            w.eocs().clear_bit()
+        });
+        self.adc_reg.cr1().modify(|_, w| w.scan().clear_bit());
+
+        self.reset_sequence();
+        self.configure_channel(pin, config::Sequence::One, sample_time);
+        self.start_conversion();
+
+        let restore = Restore(self);
+        let adc = &restore.0.adc_reg;
+        poll_fn(|cx| {
+            ADC::waker().register(cx.waker());
+            if adc.sr().read().eoc().bit_is_set() {
+                Poll::Ready(adc.dr().read().data().bits())
+            } else {
+                adc.cr1().modify(|_, w| w.eocie().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Converts [`Adc`] to [`AdcAsync`] that uses `stream` to read conversion sequences
+    pub fn into_async<STREAM, const CH: u8>(self, stream: STREAM) -> AdcAsync<ADC, STREAM, CH>
+    where
+        STREAM: Stream,
+        ChannelX<CH>: Channel,
+        Adc<ADC>: DMASet<STREAM, CH, PeripheralToMemory>,
+    {
+        AdcAsync { adc: self, stream }
+    }
+}
+
+/// Adc that reads conversion sequences with DMA
+///
+/// Channels are configured on the inner [`Adc`] with
+/// [`configure_channel`](Adc::configure_channel), then [`read_sequence`](Self::read_sequence)
+/// converts them all. A client must:
+/// * Enable the ADC interrupt and call [`on_interrupt`] in it.
+/// * Enable the interrupt of the DMA stream and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in it.
+pub struct AdcAsync<ADC: Instance, STREAM, const CH: u8> {
+    adc: Adc<ADC>,
+    stream: STREAM,
+}
+
+impl<ADC, STREAM, const CH: u8> AdcAsync<ADC, STREAM, CH>
+where
+    ADC: Instance,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Returns the [`Adc`] and the DMA stream
+    pub fn release(self) -> (Adc<ADC>, STREAM) {
+        (self.adc, self.stream)
+    }
+
+    /// Converts the configured sequence once and stores one sample per channel in `buffer`.
+    ///
+    /// Returns [`Error::BufferLength`] if `buffer` length is not equal to the
+    /// [`sequence_length`](Adc::sequence_length).
+    pub async fn read_sequence(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        let len = self.adc.sequence_length();
+        if buffer.len() != usize::from(len) {
+            return Err(Error::BufferLength);
+        }
+
+        let adc = &self.adc.adc_reg;
+        adc.cr2().modify(|_, w| {
+            //DMA must be set again to restart requests after the previous transfer
+            w.dma().clear_bit();
+            w.cont().clear_bit();
+            w.exten().set(config::TriggerMode::Disabled.into());
+            w.eocs().clear_bit()
+        });
+        adc.cr2().modify(|_, w| {
+            w.dds().clear_bit();
+            w.dma().set_bit()
+        });
+        adc.cr1().modify(|_, w| w.scan().bit(len > 1));
+        adc.sr().modify(|_, w| w.ovr().clear_bit());
+
+        let restore = Restore(&mut self.adc);
+        let dr = restore.0.data_register_address();
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.stream,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::PeripheralToMemory,
+                dr,
+                buffer.as_mut_ptr() as *const u16,
+                u16::from(len),
+                true,
+            )
+        };
+        restore.0.start_conversion();
+
+        let adc = &restore.0.adc_reg;
+        poll_fn(|cx| {
+            ADC::waker().register(cx.waker());
+            if adc.sr().read().ovr().bit_is_set() {
+                adc.sr().modify(|_, w| w.ovr().clear_bit());
+                return Poll::Ready(Err(Error::Overrun));
+            }
+            match transfer.poll(cx) {
+                Poll::Ready(result) => Poll::Ready(result.map_err(|_| Error::Dma)),
+                Poll::Pending => {
+                    adc.cr1().modify(|_, w| w.ovrie().set_bit());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+/// Disables the ADC interrupts and restores the configuration of the [`Adc`] when a
+/// conversion ends or its future is dropped
+struct Restore<'a, ADC: Instance>(&'a mut Adc<ADC>);
+
+impl<ADC: Instance> Drop for Restore<'_, ADC> {
+    fn drop(&mut self) {
+        self.0.adc_reg.cr1().modify(|_, w| {
+            w.eocie().clear_bit();
+            w.ovrie().clear_bit()
+        });
+        //Clear the conversion started flag
+        self.0.adc_reg.sr().modify(|_, w| w.strt().clear_bit());
+        //Reset the config
+        self.0.apply_config(self.0.config);
+    }
+}
+
+impl<ADC: Instance, STREAM, const CH: u8> Deref for AdcAsync<ADC, STREAM, CH> {
+    type Target = Adc<ADC>;
+
+    fn deref(&self) -> &Adc<ADC> {
+        &self.adc
+    }
+}
+
+impl<ADC: Instance, STREAM, const CH: u8> DerefMut for AdcAsync<ADC, STREAM, CH> {
+    fn deref_mut(&mut self) -> &mut Adc<ADC> {
+        &mut self.adc
+    }
+}
@@ -0,0 +1,812 @@
+//! AES coprocessor of the STM32F423
+//!
+//! Encrypts and decrypts with 128 or 256-bit keys in ECB, CBC, CTR, GCM and CCM modes.
+//! GMAC is GCM with associated data only.
+//!
+//! A [`Session`] loads the key and IV of a cipher, data is then fed in one or more calls:
+//!
+//! ```ignore
+//! let mut aes = Aes::new(dp.AES, &mut rcc);
+//!
+//! let cipher = AesGcm::new(&key, &nonce);
+//! let mut session = aes.start(&cipher, Direction::Encrypt);
+//! session.aad(&header)?;
+//! session.payload(&plaintext, &mut ciphertext)?;
+//! let tag = session.finish();
+//! ```
+//!
+//! A session can be suspended between two calls to use the coprocessor for something more
+//! urgent, and resumed later. ECB and CBC decryption keys are derived from the key with the
+//! key derivation mode of the coprocessor.
+//!
+//! With the `async` feature, [`Aes::into_async`] moves the payload with DMA2 stream 6
+//! (input) and stream 5 (output).
+//!
+//! With the `cipher` and `aead` features, the adapters of the [`crypto`](crate::crypto)
+//! module implement the RustCrypto traits.
+
+use core::marker::PhantomData;
+
+use crate::crypto::{Blocks, Framing, Misuse, PHASE_HEADER};
+use crate::pac::AES;
+use crate::rcc::{Enable, Rcc, Reset};
+
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{AesAsync, SessionAsync};
+
+/// AES errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The output is shorter than the input, or the input is not a whole number of
+    /// blocks for ECB and CBC modes
+    Length,
+    /// Associated data after the payload, or payload after a partial last block
+    Phase,
+    /// DMA transfer error
+    Dma,
+}
+
+impl Error {
+    fn misuse(misuse: Misuse) -> Self {
+        match misuse {
+            Misuse::Length => Self::Length,
+            Misuse::Phase => Self::Phase,
+        }
+    }
+}
+
+/// Direction of a [`Session`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+const BLOCK: usize = 16;
+
+/// `DATATYPE` of byte strings, the coprocessor swaps the bytes of every word
+const DATATYPE_BYTES: u8 = 0b10;
+
+/// `MODE` values
+const MODE_ENCRYPT: u8 = 0b00;
+const MODE_KEY_DERIVATION: u8 = 0b01;
+const MODE_DECRYPT: u8 = 0b10;
+
+/// `GCMPH` of the init phase, the other phases are shared with the CRYP
+const PHASE_INIT: u8 = 0b00;
+
+/// Chaining modes, bit 2 is `CHMOD2`
+const CHMOD_ECB: u8 = 0b000;
+const CHMOD_CBC: u8 = 0b001;
+const CHMOD_CTR: u8 = 0b010;
+const CHMOD_GCM: u8 = 0b011;
+const CHMOD_CCM: u8 = 0b100;
+
+mod sealed {
+    pub trait Cipher {
+        /// Chaining mode of the cipher
+        const CHMOD: u8;
+        /// The last block of the payload may be partial
+        const PARTIAL: bool;
+        /// The decryption key must be derived from the key first
+        const PREPARE_KEY: bool = false;
+        /// Associated data is authenticated
+        const AEAD: bool = false;
+
+        fn key(&self) -> &[u8];
+
+        fn iv(&self) -> [u8; 16] {
+            [0; 16]
+        }
+
+        /// Formatted associated data length written before the associated data
+        fn aad_prefix(&self, _prefix: &mut [u8; 6]) -> usize {
+            0
+        }
+
+        /// Counter block of payload block `n`
+        fn counter(&self, _n: u64) -> [u8; 16] {
+            [0; 16]
+        }
+
+        /// The tag covers the output of the payload in this direction
+        fn tag_covers_output(&self, _decrypt: bool) -> bool {
+            false
+        }
+
+        /// Block written in the final phase of AEAD modes
+        fn final_block(&self, _aad_len: u64, _payload_len: u64) -> [u8; 16] {
+            [0; 16]
+        }
+    }
+}
+
+/// A cipher and its key, that a [`Session`] is started with
+pub trait Cipher: sealed::Cipher {}
+
+/// A cipher that authenticates associated data and the payload with a tag
+pub trait AeadCipher: Cipher {}
+
+fn check_key(key: &[u8]) {
+    assert!(matches!(key.len(), 16 | 32), "AES keys are 16 or 32 bytes");
+}
+
+macro_rules! cipher {
+    ($(#[$attr:meta])* $Cipher:ident, $chmod:expr, $partial:expr, $prepare:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy)]
+        pub struct $Cipher<'k> {
+            key: &'k [u8],
+            iv: [u8; 16],
+        }
+
+        impl sealed::Cipher for $Cipher<'_> {
+            const CHMOD: u8 = $chmod;
+            const PARTIAL: bool = $partial;
+            const PREPARE_KEY: bool = $prepare;
+
+            fn key(&self) -> &[u8] {
+                self.key
+            }
+
+            fn iv(&self) -> [u8; 16] {
+                self.iv
+            }
+        }
+
+        impl Cipher for $Cipher<'_> {}
+    };
+}
+
+cipher!(
+    /// AES in electronic codebook mode
+    AesEcb, CHMOD_ECB, false, true
+);
+cipher!(
+    /// AES in cipher block chaining mode
+    AesCbc, CHMOD_CBC, false, true
+);
+cipher!(
+    /// AES in counter mode
+    AesCtr, CHMOD_CTR, true, false
+);
+
+#[cfg(feature = "cipher")]
+crate::crypto::modes! {
+    AesEcb: EcbMode, U16;
+    AesCbc: CbcMode, U16;
+    AesCtr: CtrMode, U16;
+}
+
+impl<'k> AesEcb<'k> {
+    /// # Panics
+    ///
+    /// If the key is not 16 or 32 bytes long.
+    pub fn new(key: &'k [u8]) -> Self {
+        check_key(key);
+        Self { key, iv: [0; 16] }
+    }
+}
+
+impl<'k> AesCbc<'k> {
+    /// # Panics
+    ///
+    /// If the key is not 16 or 32 bytes long.
+    pub fn new(key: &'k [u8], iv: &[u8; 16]) -> Self {
+        check_key(key);
+        Self { key, iv: *iv }
+    }
+}
+
+impl<'k> AesCtr<'k> {
+    /// Creates the cipher with the initial counter block `iv`.
+    ///
+    /// The coprocessor increments the last 32 bits of the counter block.
+    ///
+    /// # Panics
+    ///
+    /// If the key is not 16 or 32 bytes long.
+    pub fn new(key: &'k [u8], iv: &[u8; 16]) -> Self {
+        check_key(key);
+        Self { key, iv: *iv }
+    }
+}
+
+/// AES in Galois/counter mode
+#[derive(Clone, Copy)]
+pub struct AesGcm<'k> {
+    key: &'k [u8],
+    nonce: [u8; 12],
+}
+
+/// GMAC, AES-GCM that only authenticates associated data
+pub type AesGmac<'k> = AesGcm<'k>;
+
+impl<'k> AesGcm<'k> {
+    /// Creates the cipher with a 96-bit `nonce`.
+    ///
+    /// # Panics
+    ///
+    /// If the key is not 16 or 32 bytes long.
+    pub fn new(key: &'k [u8], nonce: &[u8; 12]) -> Self {
+        check_key(key);
+        Self { key, nonce: *nonce }
+    }
+}
+
+impl sealed::Cipher for AesGcm<'_> {
+    const CHMOD: u8 = CHMOD_GCM;
+    const PARTIAL: bool = true;
+    const AEAD: bool = true;
+
+    fn key(&self) -> &[u8] {
+        self.key
+    }
+
+    fn iv(&self) -> [u8; 16] {
+        self.counter(0)
+    }
+
+    fn counter(&self, n: u64) -> [u8; 16] {
+        // Counter 1 encrypts the tag
+        let mut block = [0; 16];
+        block[..12].copy_from_slice(&self.nonce);
+        block[12..].copy_from_slice(&(n as u32).wrapping_add(2).to_be_bytes());
+        block
+    }
+
+    fn tag_covers_output(&self, decrypt: bool) -> bool {
+        !decrypt
+    }
+
+    fn final_block(&self, aad_len: u64, payload_len: u64) -> [u8; 16] {
+        crate::crypto::gcm_final_block(aad_len, payload_len)
+    }
+}
+
+impl Cipher for AesGcm<'_> {}
+impl AeadCipher for AesGcm<'_> {}
+
+/// AES in counter with CBC-MAC mode
+///
+/// The lengths of the associated data and of the payload are part of the first
+/// authenticated block, so they are given upfront.
+#[derive(Clone, Copy)]
+pub struct AesCcm<'k> {
+    key: &'k [u8],
+    b0: [u8; 16],
+    aad_len: usize,
+}
+
+impl<'k> AesCcm<'k> {
+    /// Creates the cipher for `aad_len` bytes of associated data and `payload_len` bytes
+    /// of payload, with a `tag_len` bytes long tag.
+    ///
+    /// # Panics
+    ///
+    /// If the key is not 16 or 32 bytes long, the nonce is not 7 to 13 bytes long, the tag
+    /// length is not even and between 4 and 16 or the payload length does not fit in the
+    /// bytes left by the nonce.
+    pub fn new(
+        key: &'k [u8],
+        nonce: &[u8],
+        aad_len: usize,
+        payload_len: usize,
+        tag_len: usize,
+    ) -> Self {
+        check_key(key);
+        let b0 = crate::crypto::ccm_b0(nonce, aad_len, payload_len, tag_len);
+        Self { key, b0, aad_len }
+    }
+}
+
+impl sealed::Cipher for AesCcm<'_> {
+    const CHMOD: u8 = CHMOD_CCM;
+    const PARTIAL: bool = true;
+    const AEAD: bool = true;
+
+    fn key(&self) -> &[u8] {
+        self.key
+    }
+
+    fn iv(&self) -> [u8; 16] {
+        // The init phase starts from B0
+        self.b0
+    }
+
+    fn aad_prefix(&self, prefix: &mut [u8; 6]) -> usize {
+        crate::crypto::ccm_aad_prefix(self.aad_len, prefix)
+    }
+
+    fn counter(&self, n: u64) -> [u8; 16] {
+        // Counter 0 encrypts the tag
+        let q = (self.b0[0] & 0x07) as usize + 1;
+        let mut block = self.b0;
+        block[0] &= 0x07;
+        block[16 - q..].copy_from_slice(&(n + 1).to_be_bytes()[8 - q..]);
+        block
+    }
+
+    fn tag_covers_output(&self, decrypt: bool) -> bool {
+        decrypt
+    }
+
+    fn final_block(&self, _aad_len: u64, _payload_len: u64) -> [u8; 16] {
+        let mut block = self.counter(0);
+        block[15] = 0;
+        block
+    }
+}
+
+impl Cipher for AesCcm<'_> {}
+impl AeadCipher for AesCcm<'_> {}
+
+/// Converts a big endian byte string to words, the last word first
+fn words<const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut words = [0; N];
+    for (w, chunk) in words.iter_mut().rev().zip(bytes.chunks_exact(4)) {
+        *w = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    words
+}
+
+fn write_key(aes: &AES, key: &[u8]) {
+    let k: [u32; 8] = if key.len() == 16 {
+        let k: [u32; 4] = words(key);
+        [k[0], k[1], k[2], k[3], 0, 0, 0, 0]
+    } else {
+        words(key)
+    };
+    aes.keyr0().write(|w| w.set(k[0]));
+    aes.keyr1().write(|w| w.set(k[1]));
+    aes.keyr2().write(|w| w.set(k[2]));
+    aes.keyr3().write(|w| w.set(k[3]));
+    aes.keyr4().write(|w| w.set(k[4]));
+    aes.keyr5().write(|w| w.set(k[5]));
+    aes.keyr6().write(|w| w.set(k[6]));
+    aes.keyr7().write(|w| w.set(k[7]));
+}
+
+fn write_iv(aes: &AES, iv: [u32; 4]) {
+    aes.ivr0().write(|w| w.set(iv[0]));
+    aes.ivr1().write(|w| w.set(iv[1]));
+    aes.ivr2().write(|w| w.set(iv[2]));
+    aes.ivr3().write(|w| w.set(iv[3]));
+}
+
+fn read_iv(aes: &AES) -> [u32; 4] {
+    [
+        aes.ivr0().read().bits(),
+        aes.ivr1().read().bits(),
+        aes.ivr2().read().bits(),
+        aes.ivr3().read().bits(),
+    ]
+}
+
+fn write_susp(aes: &AES, susp: [u32; 8]) {
+    aes.susp0r().write(|w| w.set(susp[0]));
+    aes.susp1r().write(|w| w.set(susp[1]));
+    aes.susp2r().write(|w| w.set(susp[2]));
+    aes.susp3r().write(|w| w.set(susp[3]));
+    aes.susp4r().write(|w| w.set(susp[4]));
+    aes.susp5r().write(|w| w.set(susp[5]));
+    aes.susp6r().write(|w| w.set(susp[6]));
+    aes.susp7r().write(|w| w.set(susp[7]));
+}
+
+fn read_susp(aes: &AES) -> [u32; 8] {
+    [
+        aes.susp0r().read().bits(),
+        aes.susp1r().read().bits(),
+        aes.susp2r().read().bits(),
+        aes.susp3r().read().bits(),
+        aes.susp4r().read().bits(),
+        aes.susp5r().read().bits(),
+        aes.susp6r().read().bits(),
+        aes.susp7r().read().bits(),
+    ]
+}
+
+/// Reads the IV and suspend registers once the coprocessor is idle, and disables it
+fn save_context(aes: &AES) -> ([u32; 4], [u32; 8]) {
+    while aes.sr().read().busy().bit_is_set() {}
+    let context = (read_iv(aes), read_susp(aes));
+    enable(aes, false);
+    context
+}
+
+fn set_chmod(aes: &AES, chmod: u8) {
+    aes.cr().modify(|_, w| unsafe {
+        w.chmod().bits(chmod & 0b11);
+        w.chmod2().bit(chmod & 0b100 != 0)
+    });
+}
+
+fn set_phase(aes: &AES, phase: u8) {
+    aes.cr().modify(|_, w| unsafe { w.gcmph().bits(phase) });
+}
+
+fn set_mode(aes: &AES, mode: u8) {
+    aes.cr().modify(|_, w| unsafe { w.mode().bits(mode) });
+}
+
+fn enable(aes: &AES, enable: bool) {
+    aes.cr().modify(|_, w| w.en().bit(enable));
+}
+
+/// Waits for the end of a computation and clears it
+fn wait_complete(aes: &AES) {
+    while aes.sr().read().ccf().bit_is_clear() {}
+    aes.cr().modify(|_, w| w.ccfc().set_bit());
+}
+
+fn write_block(aes: &AES, input: &[u8]) {
+    for word in input.chunks_exact(4) {
+        aes.dinr()
+            .write(|w| w.set(u32::from_le_bytes(word.try_into().unwrap())));
+    }
+}
+
+/// Processes one block of bytes
+fn process_block(aes: &AES, input: &[u8], output: &mut [u8]) {
+    write_block(aes, input);
+    while aes.sr().read().ccf().bit_is_clear() {}
+    for word in output[..BLOCK].chunks_exact_mut(4) {
+        word.copy_from_slice(&aes.doutr().read().bits().to_le_bytes());
+    }
+    aes.cr().modify(|_, w| w.ccfc().set_bit());
+}
+
+impl Blocks for AES {
+    fn aad_block(&self, block: &[u8; 16]) {
+        write_block(self, block);
+        wait_complete(self);
+    }
+
+    fn set_phase(&self, phase: u8) {
+        set_phase(self, phase);
+        enable(self, true);
+    }
+
+    fn process_block(&self, input: &[u8], output: &mut [u8]) {
+        process_block(self, input, output);
+    }
+}
+
+/// AES coprocessor
+pub struct Aes {
+    aes: AES,
+}
+
+impl Aes {
+    /// Enables the clock of the coprocessor and resets it
+    pub fn new(aes: AES, rcc: &mut Rcc) -> Self {
+        AES::enable(rcc);
+        AES::reset(rcc);
+        Self { aes }
+    }
+
+    /// Releases the AES peripheral
+    pub fn release(self) -> AES {
+        unsafe { self.aes.cr().write(|w| w.bits(0)) };
+        self.aes
+    }
+
+    /// Loads the key and IV of `cipher` and starts a session that encrypts or decrypts
+    /// with it
+    pub fn start<'a, C: Cipher>(&'a mut self, cipher: &'a C, dir: Direction) -> Session<'a, C> {
+        Session::new(&self.aes, cipher, dir)
+    }
+
+    /// Continues a session suspended with [`Session::suspend`], `cipher` must be the same
+    pub fn resume<'a, C: Cipher>(
+        &'a mut self,
+        cipher: &'a C,
+        suspended: Suspended<C>,
+    ) -> Session<'a, C> {
+        Session::resume(&self.aes, cipher, suspended)
+    }
+
+    /// Encrypts or decrypts `input` into `output` in a single session
+    pub fn process<C: Cipher>(
+        &mut self,
+        cipher: &C,
+        dir: Direction,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.start(cipher, dir).payload(input, output)
+    }
+}
+
+#[cfg(any(feature = "cipher", feature = "aead"))]
+impl crate::Sealed for Aes {}
+
+#[cfg(feature = "cipher")]
+impl<C: Cipher> crate::crypto::Processor<C> for Aes {
+    fn run(&mut self, cipher: &C, decrypt: bool, buf: &mut [u8]) {
+        let dir = if decrypt {
+            Direction::Decrypt
+        } else {
+            Direction::Encrypt
+        };
+        crate::crypto::run(self.start(cipher, dir), BLOCK, buf);
+    }
+}
+
+#[cfg(feature = "aead")]
+impl crate::crypto::AeadProcessor for Aes {
+    fn check_key(key: &[u8]) {
+        check_key(key);
+    }
+
+    fn gcm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16] {
+        self.run_aead(&AesGcm::new(key, nonce), decrypt, aad, buf)
+    }
+
+    fn ccm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        tag_len: usize,
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16] {
+        let cipher = AesCcm::new(key, nonce, aad.len(), buf.len(), tag_len);
+        self.run_aead(&cipher, decrypt, aad, buf)
+    }
+}
+
+#[cfg(feature = "aead")]
+impl Aes {
+    /// Runs `cipher` over `aad` and `buf` in place in a new session, returns the tag
+    fn run_aead<C: AeadCipher>(
+        &mut self,
+        cipher: &C,
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16] {
+        let dir = if decrypt {
+            Direction::Decrypt
+        } else {
+            Direction::Encrypt
+        };
+        crate::crypto::run_aead(self.start(cipher, dir), aad, buf)
+    }
+}
+
+/// Progress of a session, kept while it is suspended
+#[derive(Clone, Copy)]
+struct State {
+    dir: Direction,
+    framing: Framing,
+}
+
+/// Encryption or decryption with one cipher
+///
+/// Associated data of AEAD ciphers comes first, then the payload. Every payload but the
+/// last must be a whole number of blocks. The coprocessor is disabled when the session is
+/// dropped.
+pub struct Session<'a, C: Cipher> {
+    aes: &'a AES,
+    cipher: &'a C,
+    state: State,
+}
+
+/// A suspended [`Session`]
+pub struct Suspended<C> {
+    state: State,
+    cr: u32,
+    iv: [u32; 4],
+    susp: [u32; 8],
+    _cipher: PhantomData<C>,
+}
+
+impl<'a, C: Cipher> Session<'a, C> {
+    /// Configures the coprocessor for `cipher` and loads the key, the coprocessor is left
+    /// disabled
+    fn configure(aes: &AES, cipher: &C, dir: Direction) {
+        let key = sealed::Cipher::key(cipher);
+        unsafe { aes.cr().write(|w| w.bits(0)) };
+        aes.cr().modify(|_, w| unsafe {
+            w.datatype().bits(DATATYPE_BYTES);
+            w.keysize().bit(key.len() == 32)
+        });
+        set_chmod(aes, C::CHMOD);
+        write_key(aes, key);
+        if dir == Direction::Decrypt {
+            if C::PREPARE_KEY {
+                set_mode(aes, MODE_KEY_DERIVATION);
+                enable(aes, true);
+                wait_complete(aes);
+                enable(aes, false);
+            }
+            set_mode(aes, MODE_DECRYPT);
+        } else {
+            set_mode(aes, MODE_ENCRYPT);
+        }
+    }
+
+    fn new(aes: &'a AES, cipher: &'a C, dir: Direction) -> Self {
+        Self::configure(aes, cipher, dir);
+        write_iv(aes, words(&sealed::Cipher::iv(cipher)));
+
+        let mut session = Self {
+            aes,
+            cipher,
+            state: State {
+                dir,
+                framing: Framing::new(),
+            },
+        };
+        if C::AEAD {
+            // Computes the hash subkey or encrypts B0
+            set_phase(aes, PHASE_INIT);
+            enable(aes, true);
+            wait_complete(aes);
+            let mut prefix = [0; 6];
+            let len = cipher.aad_prefix(&mut prefix);
+            session.state.framing.start_header(aes, &prefix[..len]);
+        } else {
+            enable(aes, true);
+        }
+        session
+    }
+
+    fn resume(aes: &'a AES, cipher: &'a C, suspended: Suspended<C>) -> Self {
+        Self::configure(aes, cipher, suspended.state.dir);
+        write_iv(aes, suspended.iv);
+        write_susp(aes, suspended.susp);
+        unsafe { aes.cr().write(|w| w.bits(suspended.cr)) };
+        enable(aes, true);
+        Self {
+            aes,
+            cipher,
+            state: suspended.state,
+        }
+    }
+
+    /// Stops the session between two calls, so the coprocessor can be used by other
+    /// sessions until it is resumed with [`Aes::resume`]
+    pub fn suspend(self) -> Suspended<C> {
+        let (iv, susp) = save_context(self.aes);
+        Suspended {
+            state: self.state,
+            cr: self.aes.cr().read().bits(),
+            iv,
+            susp,
+            _cipher: PhantomData,
+        }
+    }
+
+    /// Checks the lengths of a payload and enters the payload phase, returns the length
+    /// of its whole blocks
+    fn start_payload(&mut self, input: &[u8], output: &[u8]) -> Result<usize, Error> {
+        self.state
+            .framing
+            .start_payload(self.aes, input.len(), output.len(), BLOCK, C::PARTIAL)
+            .map_err(Error::misuse)
+    }
+
+    /// Processes the partial last block of a payload
+    fn partial_block(&mut self, input: &[u8], output: &mut [u8]) {
+        let aes = self.aes;
+        let mut block = [0; 16];
+        block[..input.len()].copy_from_slice(input);
+        let mut out = [0; 16];
+        let decrypt = self.state.dir == Direction::Decrypt;
+        if self.cipher.tag_covers_output(decrypt) {
+            // The tag would cover the output of the padding, process the block in CTR mode
+            // and authenticate the masked output as associated data instead
+            let n = (self.state.framing.payload_len() - input.len() as u64) / BLOCK as u64;
+            let (iv, susp) = save_context(aes);
+            let cr = aes.cr().read().bits();
+            set_chmod(aes, CHMOD_CTR);
+            set_mode(aes, MODE_ENCRYPT);
+            write_iv(aes, words(&self.cipher.counter(n)));
+            enable(aes, true);
+            process_block(aes, &block, &mut out);
+
+            enable(aes, false);
+            unsafe { aes.cr().write(|w| w.bits(cr)) };
+            set_phase(aes, PHASE_HEADER);
+            write_iv(aes, iv);
+            write_susp(aes, susp);
+            enable(aes, true);
+            let mut masked = out;
+            masked[input.len()..].fill(0);
+            write_block(aes, &masked);
+            wait_complete(aes);
+        } else {
+            process_block(aes, &block, &mut out);
+        }
+        output[..input.len()].copy_from_slice(&out[..input.len()]);
+        self.state.framing.end_payload();
+    }
+
+    /// Encrypts or decrypts `input` into the start of `output`.
+    ///
+    /// A payload that is not a whole number of blocks ends the session, only CTR, GCM and
+    /// CCM accept it.
+    pub fn payload(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        let full = self.start_payload(input, output)?;
+        for (i, o) in input[..full]
+            .chunks_exact(BLOCK)
+            .zip(output.chunks_exact_mut(BLOCK))
+        {
+            process_block(self.aes, i, o);
+        }
+        if full != input.len() {
+            self.partial_block(&input[full..], &mut output[full..]);
+        }
+        Ok(())
+    }
+}
+
+impl<C: AeadCipher> Session<'_, C> {
+    /// Authenticates associated data, which can be split in any number of calls before
+    /// the payload.
+    ///
+    /// CCM needs exactly the length of associated data given to [`AesCcm::new`].
+    pub fn aad(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.state
+            .framing
+            .aad(self.aes, data)
+            .map_err(Error::misuse)
+    }
+
+    /// Ends the session and returns the authentication tag.
+    ///
+    /// A CCM tag is the first `tag_len` bytes. When decrypting, the tag must be compared
+    /// with the received one before the plaintext is used.
+    pub fn finish(mut self) -> [u8; 16] {
+        let cipher = self.cipher;
+        self.state.framing.finish(self.aes, |aad_len, payload_len| {
+            cipher.final_block(aad_len, payload_len)
+        })
+    }
+}
+
+#[cfg(any(feature = "cipher", feature = "aead"))]
+impl<C: Cipher> crate::crypto::Session for Session<'_, C> {
+    type Error = Error;
+
+    fn payload(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        Session::payload(self, input, output)
+    }
+}
+
+#[cfg(feature = "aead")]
+impl<C: AeadCipher> crate::crypto::AeadSession for Session<'_, C> {
+    fn aad(&mut self, data: &[u8]) -> Result<(), Error> {
+        Session::aad(self, data)
+    }
+
+    fn finish(self) -> [u8; 16] {
+        Session::finish(self)
+    }
+}
+
+impl<C: Cipher> Drop for Session<'_, C> {
+    fn drop(&mut self) {
+        self.aes.cr().modify(|_, w| {
+            w.en().clear_bit();
+            w.dmainen().clear_bit();
+            w.dmaouten().clear_bit()
+        });
+    }
+}
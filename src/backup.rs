@@ -0,0 +1,146 @@
+//! Backup domain: RTC backup registers and backup SRAM
+//!
+//! The backup domain is kept through the resets and Standby, and while only VBAT is
+//! powered. It is cleared by a backup domain reset, which [`Rtc`](crate::rtc::Rtc) does
+//! when it has to start its oscillator.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use stm32f4xx_hal::{backup::BackupRegisters, pac, prelude::*, pwr::Pwr};
+//!
+//! let dp = pac::Peripherals::take().unwrap();
+//! let mut rcc = dp.RCC.constrain();
+//! let mut pwr = Pwr::new(dp.PWR, &mut rcc);
+//!
+//! let mut bkp = BackupRegisters::new(&mut pwr);
+//! let boots = bkp.read(0) + 1;
+//! bkp.write(0, boots);
+//! ```
+
+use crate::pac::RTC;
+use crate::pwr::Pwr;
+#[cfg(feature = "bkpsram")]
+use crate::rcc::Rcc;
+#[cfg(feature = "bkpsram")]
+use core::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Enables the write access to the backup domain
+fn unlock(pwr: &mut Pwr) {
+    pwr.pwr.cr().modify(|_, w| w.dbp().set_bit());
+}
+
+/// The 20 RTC backup registers, `BKP0R` to `BKP19R`
+pub struct BackupRegisters {
+    _private: (),
+}
+
+impl BackupRegisters {
+    /// Number of backup registers
+    pub const COUNT: usize = 20;
+
+    /// Enables the write access to the backup domain
+    pub fn new(pwr: &mut Pwr) -> Self {
+        unlock(pwr);
+        Self { _private: () }
+    }
+
+    /// Reads the register `index`
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not below [`COUNT`](Self::COUNT).
+    pub fn read(&self, index: usize) -> u32 {
+        assert!(index < Self::COUNT);
+        // Only the backup registers of the RTC are accessed, not used by `Rtc`
+        let rtc = unsafe { &*RTC::ptr() };
+        rtc.bkpr(index).read().bkp().bits()
+    }
+
+    /// Writes `value` in the register `index`
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not below [`COUNT`](Self::COUNT).
+    pub fn write(&mut self, index: usize, value: u32) {
+        assert!(index < Self::COUNT);
+        let rtc = unsafe { &*RTC::ptr() };
+        rtc.bkpr(index).write(|w| w.bkp().set(value));
+    }
+}
+
+/// Size of the backup SRAM in bytes
+#[cfg(feature = "bkpsram")]
+pub const BKPSRAM_SIZE: usize = 4096;
+
+#[cfg(feature = "bkpsram")]
+const BKPSRAM_ADDR: usize = 0x4002_4000;
+
+#[cfg(feature = "bkpsram")]
+static BKPSRAM_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// The 4 KB backup SRAM
+///
+/// The content is kept through the resets and the Stop mode. The backup regulator must be
+/// enabled to also keep it in Standby and while only VBAT is powered.
+#[cfg(feature = "bkpsram")]
+pub struct BackupSram {
+    _private: (),
+}
+
+#[cfg(feature = "bkpsram")]
+impl BackupSram {
+    /// Enables the clock and the write access of the backup SRAM, `None` if it has already
+    /// been taken
+    pub fn new(rcc: &mut Rcc, pwr: &mut Pwr) -> Option<Self> {
+        if BKPSRAM_TAKEN.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        unlock(pwr);
+        rcc.ahb1enr().modify(|_, w| w.bkpsramen().set_bit());
+        // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
+        cortex_m::asm::dsb();
+        Some(Self { _private: () })
+    }
+
+    /// Enables the backup regulator, which keeps the content in Standby and VBAT mode, and
+    /// waits for it to be ready
+    pub fn enable_regulator(&mut self, pwr: &mut Pwr) {
+        pwr.pwr.csr().modify(|_, w| w.bre().set_bit());
+        while pwr.pwr.csr().read().brr().bit_is_clear() {}
+    }
+
+    /// Disables the backup regulator, the content is lost in Standby and VBAT mode
+    pub fn disable_regulator(&mut self, pwr: &mut Pwr) {
+        pwr.pwr.csr().modify(|_, w| w.bre().clear_bit());
+    }
+
+    /// Returns `true` if the backup regulator is enabled and ready
+    pub fn is_regulator_ready(&self, pwr: &Pwr) -> bool {
+        pwr.pwr.csr().read().brr().bit_is_set()
+    }
+
+    /// Returns the whole backup SRAM for the rest of the program
+    pub fn into_static(self) -> &'static mut [u8; BKPSRAM_SIZE] {
+        unsafe { &mut *(BKPSRAM_ADDR as *mut [u8; BKPSRAM_SIZE]) }
+    }
+}
+
+#[cfg(feature = "bkpsram")]
+impl Deref for BackupSram {
+    type Target = [u8; BKPSRAM_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(BKPSRAM_ADDR as *const [u8; BKPSRAM_SIZE]) }
+    }
+}
+
+#[cfg(feature = "bkpsram")]
+impl DerefMut for BackupSram {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *(BKPSRAM_ADDR as *mut [u8; BKPSRAM_SIZE]) }
+    }
+}
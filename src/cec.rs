@@ -0,0 +1,581 @@
+//! HDMI Consumer Electronics Control (CEC)
+//!
+//! Sends and receives CEC messages on the single wire bus of HDMI. The device acknowledges
+//! the messages sent to its own logical addresses, see [`Config::own_address`], and can
+//! also receive the messages sent to other devices in listen mode.
+//!
+//! The bus is slow, a byte lasts about 24 ms, so [`Cec`] moves the messages between the bus
+//! and its transmit and receive queues from the `HDMI_CEC` interrupt:
+//!
+//! ```ignore
+//! let mut cec = Cec::<4>::new(dp.HDMI_CEC, gpiob.pb6, Config::default().own_address(4), &mut rcc);
+//! cec.listen(BitFlags::ALL);
+//! // Shares `cec` with the interrupt handler, which calls `cec.poll()`
+//!
+//! // <Image View On> to the TV
+//! cec.send(Message::new(4, 0, 0x04, &[])).unwrap();
+//! if let Some(Ok(message)) = cec.receive() {
+//!     // ...
+//! }
+//! ```
+//!
+//! With the `async` feature, `CecAsync` awaits the transfers instead of queueing them.
+
+use core::fmt;
+
+use crate::gpio::alt::cec as alt;
+use crate::pac::HDMI_CEC;
+use crate::rcc::{Enable, Rcc, Reset};
+use enumflags2::BitFlags;
+use heapless::Deque;
+
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_interrupt, CecAsync};
+
+/// `TXSOM` bit of `CR`, missing from the F446 PAC
+const TXSOM: u32 = 1 << 1;
+/// `TXEOM` bit of `CR`, missing from the F446 PAC
+const TXEOM: u32 = 1 << 2;
+
+/// Receive error bits of `ISR` which stop the reception: overrun, bit rising error, short
+/// and long bit period errors
+const RX_ABORT: u32 = Event::RxOverrun as u32
+    | Event::BitRisingError as u32
+    | Event::ShortBitPeriodError as u32
+    | Event::LongBitPeriodError as u32;
+/// Receive error bits of `ISR`
+const RX_ERRORS: u32 = RX_ABORT | Event::RxNack as u32;
+/// Bits of `ISR` which end a reception
+const RX_DONE: u32 = RX_ERRORS | Event::RxEnd as u32;
+/// Transmit error bits of `ISR`, including the arbitration loss
+const TX_ERRORS: u32 = Event::ArbitrationLost as u32
+    | Event::TxUnderrun as u32
+    | Event::TxError as u32
+    | Event::TxNack as u32;
+
+/// Most bytes of a message: header, opcode and 14 operands
+pub const MAX_LEN: usize = 16;
+
+/// Clock of the CEC, about 32 kHz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockSource {
+    /// LSE, which must already run, like after [`Rtc::new`](crate::rtc::Rtc::new)
+    Lse,
+    /// HSI divided by 488
+    Hsi,
+}
+
+/// Configuration of the CEC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    clock: ClockSource,
+    addresses: u16,
+    listen: bool,
+    rx_tolerance: bool,
+    error_bits: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clock: ClockSource::Hsi,
+            addresses: 0,
+            listen: false,
+            rx_tolerance: false,
+            error_bits: true,
+        }
+    }
+}
+
+impl Config {
+    /// Clock of the CEC, the HSI by default
+    pub fn clock(mut self, clock: ClockSource) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Adds a logical address of the device, from 0 to 14. The device acknowledges the
+    /// messages sent to its addresses.
+    ///
+    /// # Panics
+    ///
+    /// If `address` is the broadcast address or above.
+    pub fn own_address(mut self, address: u8) -> Self {
+        assert!(address < Message::BROADCAST);
+        self.addresses |= 1 << address;
+        self
+    }
+
+    /// Also receives the messages sent to other devices, without acknowledging them
+    pub fn listen(mut self, listen: bool) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    /// Extends the tolerance of the bit timings of the received messages
+    pub fn rx_tolerance(mut self, rx_tolerance: bool) -> Self {
+        self.rx_tolerance = rx_tolerance;
+        self
+    }
+
+    /// Signals the bit timing errors of the received messages to the initiator with an
+    /// error bit, which is the default
+    pub fn error_bits(mut self, error_bits: bool) -> Self {
+        self.error_bits = error_bits;
+        self
+    }
+}
+
+/// Transfer error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Another initiator won the bus, the queued messages are sent again
+    ArbitrationLost,
+    /// The destination did not acknowledge a byte or rejected a broadcast message. In
+    /// listen mode, the destination of a received message did not acknowledge it.
+    Nack,
+    /// A byte to send was not written in time
+    Underrun,
+    /// The bus was driven low by another device while sending
+    Bus,
+    /// A byte was received before the previous one was read
+    Overrun,
+    /// A bit of a received message rose at the wrong time
+    BitRising,
+    /// A bit period of a received message is too short
+    ShortBitPeriod,
+    /// A bit period of a received message is too long
+    LongBitPeriod,
+}
+
+impl Error {
+    fn from_isr(bits: u32) -> Option<Self> {
+        let is_set = |event: Event| bits & event as u32 != 0;
+        Some(if is_set(Event::ArbitrationLost) {
+            Self::ArbitrationLost
+        } else if is_set(Event::TxUnderrun) {
+            Self::Underrun
+        } else if is_set(Event::TxError) {
+            Self::Bus
+        } else if is_set(Event::RxNack) || is_set(Event::TxNack) {
+            Self::Nack
+        } else if is_set(Event::RxOverrun) {
+            Self::Overrun
+        } else if is_set(Event::BitRisingError) {
+            Self::BitRising
+        } else if is_set(Event::ShortBitPeriodError) {
+            Self::ShortBitPeriod
+        } else if is_set(Event::LongBitPeriodError) {
+            Self::LongBitPeriod
+        } else {
+            return None;
+        })
+    }
+}
+
+/// Interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    /// A byte was received
+    RxByte = 1 << 0,
+    /// A message was received
+    RxEnd = 1 << 1,
+    RxOverrun = 1 << 2,
+    BitRisingError = 1 << 3,
+    ShortBitPeriodError = 1 << 4,
+    LongBitPeriodError = 1 << 5,
+    /// A received message was not acknowledged in listen mode
+    RxNack = 1 << 6,
+    ArbitrationLost = 1 << 7,
+    /// The next byte to send must be written
+    TxByteRequest = 1 << 8,
+    /// A message was sent
+    TxEnd = 1 << 9,
+    TxUnderrun = 1 << 10,
+    TxError = 1 << 11,
+    /// A sent message was not acknowledged
+    TxNack = 1 << 12,
+}
+
+/// CEC message: a header with the initiator and destination addresses, an optional
+/// opcode and up to 14 operands
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    bytes: [u8; MAX_LEN],
+    len: u8,
+}
+
+impl Message {
+    /// Destination address of the broadcast messages
+    pub const BROADCAST: u8 = 0xf;
+
+    /// Message with only a header, which polls whether `destination` is present
+    pub const fn poll(initiator: u8, destination: u8) -> Self {
+        let mut bytes = [0; MAX_LEN];
+        bytes[0] = (initiator & 0xf) << 4 | destination & 0xf;
+        Self { bytes, len: 1 }
+    }
+
+    /// # Panics
+    ///
+    /// If there are more than 14 operands.
+    pub fn new(initiator: u8, destination: u8, opcode: u8, operands: &[u8]) -> Self {
+        assert!(operands.len() <= MAX_LEN - 2);
+        let mut message = Self::poll(initiator, destination);
+        message.bytes[1] = opcode;
+        message.bytes[2..2 + operands.len()].copy_from_slice(operands);
+        message.len = 2 + operands.len() as u8;
+        message
+    }
+
+    pub const fn initiator(&self) -> u8 {
+        self.bytes[0] >> 4
+    }
+
+    pub const fn destination(&self) -> u8 {
+        self.bytes[0] & 0xf
+    }
+
+    pub const fn is_broadcast(&self) -> bool {
+        self.destination() == Self::BROADCAST
+    }
+
+    /// `None` for a polling message
+    pub const fn opcode(&self) -> Option<u8> {
+        if self.len > 1 {
+            Some(self.bytes[1])
+        } else {
+            None
+        }
+    }
+
+    pub fn operands(&self) -> &[u8] {
+        &self.bytes[2.min(self.len as usize)..self.len as usize]
+    }
+
+    /// Bytes of the message, starting with the header
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    const fn empty() -> Self {
+        Self {
+            bytes: [0; MAX_LEN],
+            len: 0,
+        }
+    }
+
+    /// Appends a received byte, the bytes after [`MAX_LEN`] are dropped
+    fn push(&mut self, byte: u8) {
+        if (self.len as usize) < MAX_LEN {
+            self.bytes[self.len as usize] = byte;
+            self.len += 1;
+        }
+    }
+}
+
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Message")
+            .field("initiator", &self.initiator())
+            .field("destination", &self.destination())
+            .field("opcode", &self.opcode())
+            .field("operands", &self.operands())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Message {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Message {{ initiator: {}, destination: {}, opcode: {}, operands: {} }}",
+            self.initiator(),
+            self.destination(),
+            self.opcode(),
+            self.operands()
+        );
+    }
+}
+
+/// Message queued by [`Cec::send`] and the result of its transmission
+#[derive(Clone, Copy)]
+struct TxSlot {
+    message: Message,
+    result: Option<Result<(), Error>>,
+}
+
+/// CEC with queues of `N` messages to send and received
+pub struct Cec<const N: usize = 4> {
+    cec: HDMI_CEC,
+    pin: alt::Cec,
+    tx: Deque<TxSlot, N>,
+    /// Slot of `tx` being sent, the previous ones have a result
+    tx_slot: usize,
+    /// Bytes of the slot written to `TXDR`, 0 when the bus is not used
+    tx_pos: usize,
+    rx: Deque<Result<Message, Error>, N>,
+    rx_message: Message,
+    rx_error: Option<Error>,
+}
+
+impl<const N: usize> Cec<N> {
+    /// Configures and enables the CEC
+    ///
+    /// # Panics
+    ///
+    /// If the LSE is selected but does not run.
+    pub fn new(cec: HDMI_CEC, pin: impl Into<alt::Cec>, config: Config, rcc: &mut Rcc) -> Self {
+        HDMI_CEC::enable(rcc);
+        HDMI_CEC::reset(rcc);
+
+        match config.clock {
+            ClockSource::Lse => {
+                assert!(rcc.bdcr().read().lserdy().bit_is_set(), "LSE does not run");
+                rcc.dckcfgr2().modify(|_, w| w.cecsel().lse());
+            }
+            ClockSource::Hsi => {
+                if rcc.cr().read().hsirdy().bit_is_clear() {
+                    rcc.cr().modify(|_, w| w.hsion().set_bit());
+                    while rcc.cr().read().hsirdy().bit_is_clear() {}
+                }
+                rcc.dckcfgr2().modify(|_, w| w.cecsel().hsi_div488());
+            }
+        }
+
+        cec.cec_cfgr().write(|w| {
+            unsafe { w.oar().bits(config.addresses) };
+            w.lstn().bit(config.listen);
+            w.rxtol().bit(config.rx_tolerance);
+            w.bregen().bit(config.error_bits);
+            w.lbpegen().bit(config.error_bits)
+        });
+        cec.cec_cr().write(|w| w.cecen().set_bit());
+
+        Self {
+            cec,
+            pin: pin.into(),
+            tx: Deque::new(),
+            tx_slot: 0,
+            tx_pos: 0,
+            rx: Deque::new(),
+            rx_message: Message::empty(),
+            rx_error: None,
+        }
+    }
+
+    /// Disables the CEC and releases it with its pin
+    pub fn release(self) -> (HDMI_CEC, alt::Cec) {
+        self.cec.cec_cr().write(|w| w.cecen().clear_bit());
+        (self.cec, self.pin)
+    }
+
+    /// Adds or removes a logical address of the device, which aborts the current transfer
+    ///
+    /// # Panics
+    ///
+    /// If `address` is the broadcast address or above.
+    pub fn set_own_address(&mut self, address: u8, own: bool) {
+        assert!(address < Message::BROADCAST);
+        self.reconfigure(|cec| {
+            cec.cec_cfgr().modify(|r, w| {
+                let addresses = r.oar().bits() & !(1 << address) | (own as u16) << address;
+                unsafe { w.oar().bits(addresses) }
+            });
+        });
+    }
+
+    /// Enables or disables listen mode, which aborts the current transfer
+    pub fn set_listen(&mut self, listen: bool) {
+        self.reconfigure(|cec| {
+            cec.cec_cfgr().modify(|_, w| w.lstn().bit(listen));
+        });
+    }
+
+    /// Queues `message`, which is returned when the queue is full
+    ///
+    /// The result of each message is read with [`tx_result`](Self::tx_result).
+    pub fn send(&mut self, message: Message) -> Result<(), Message> {
+        self.tx
+            .push_back(TxSlot {
+                message,
+                result: None,
+            })
+            .map_err(|slot| slot.message)?;
+        self.poll();
+        Ok(())
+    }
+
+    /// Result of the oldest queued message, once it is sent or failed
+    ///
+    /// A message is only removed from the queue when its result is read.
+    pub fn tx_result(&mut self) -> Option<Result<(), Error>> {
+        let result = self.tx.front()?.result?;
+        self.tx.pop_front();
+        self.tx_slot -= 1;
+        Some(result)
+    }
+
+    /// Oldest received message or receive error
+    pub fn receive(&mut self) -> Option<Result<Message, Error>> {
+        self.rx.pop_front()
+    }
+
+    /// Moves the queued messages to the bus and the received ones to the queue
+    ///
+    /// Must be called from the `HDMI_CEC` interrupt handler with all events listened, or
+    /// regularly.
+    pub fn poll(&mut self) {
+        let isr = self.cec.cec_isr().read().bits();
+
+        if isr & Event::RxByte as u32 != 0 {
+            self.rx_message
+                .push(self.cec.cec_rxdr().read().rxd().bits());
+        }
+        if isr & RX_ERRORS != 0 {
+            self.rx_error = self.rx_error.or(Error::from_isr(isr & RX_ERRORS));
+        }
+        if isr & RX_DONE != 0 {
+            let message = match self.rx_error.take() {
+                Some(error) => Err(error),
+                None => Ok(self.rx_message),
+            };
+            // The message is lost when the queue is full
+            self.rx.push_back(message).ok();
+            self.rx_message = Message::empty();
+        }
+
+        let slot = self.tx_slot;
+        if let Some(TxSlot { message, .. }) = self.tx.iter().nth(slot).copied() {
+            if let Some(result) = tx_step(&self.cec, isr, &message, &mut self.tx_pos) {
+                self.finish_tx(result);
+            }
+        }
+
+        // NOTE(unsafe) only the flags which were read are cleared
+        self.cec.cec_isr().write(|w| unsafe { w.bits(isr) });
+
+        if self.tx_pos == 0 {
+            let slot = self.tx_slot;
+            if let Some(TxSlot { message, .. }) = self.tx.iter().nth(slot).copied() {
+                start_tx(&self.cec, &message);
+                self.tx_pos = 1;
+            }
+        }
+    }
+
+    fn finish_tx(&mut self, result: Result<(), Error>) {
+        let slot = self.tx_slot;
+        if let Some(slot) = self.tx.iter_mut().nth(slot) {
+            slot.result = Some(result);
+            self.tx_slot += 1;
+        }
+    }
+
+    /// `CFGR` can only be written while the CEC is disabled
+    fn reconfigure(&mut self, f: impl FnOnce(&HDMI_CEC)) {
+        self.cec.cec_cr().write(|w| w.cecen().clear_bit());
+        f(&self.cec);
+        self.cec.cec_cr().write(|w| w.cecen().set_bit());
+        // The aborted message is sent again
+        self.tx_pos = 0;
+        self.rx_message = Message::empty();
+        self.rx_error = None;
+        self.poll();
+    }
+}
+
+/// Handles the transmit flags of `isr` while sending `message`, of which `pos` bytes are
+/// written, 0 when the bus is not used
+///
+/// Returns the result once the message is sent or failed. After an arbitration loss `pos`
+/// is 0 and the message must be started again.
+fn tx_step(
+    cec: &HDMI_CEC,
+    isr: u32,
+    message: &Message,
+    pos: &mut usize,
+) -> Option<Result<(), Error>> {
+    if let Some(error) = Error::from_isr(isr & TX_ERRORS) {
+        *pos = 0;
+        if error != Error::ArbitrationLost {
+            return Some(Err(error));
+        }
+    } else if isr & Event::TxEnd as u32 != 0 {
+        *pos = 0;
+        return Some(Ok(()));
+    } else if isr & Event::TxByteRequest as u32 != 0 && *pos != 0 {
+        write_next(cec, message, *pos);
+        *pos += 1;
+    }
+    None
+}
+
+/// Writes the header of `message` and starts sending it once the bus is free
+fn start_tx(cec: &HDMI_CEC, message: &Message) {
+    let eom = if message.len == 1 { TXEOM } else { 0 };
+    cec.cec_txdr()
+        .write(|w| unsafe { w.txd().bits(message.bytes[0]) });
+    cec.cec_cr()
+        .modify(|r, w| unsafe { w.bits(r.bits() | TXSOM | eom) });
+}
+
+/// Writes byte `pos` of `message` after a byte request
+fn write_next(cec: &HDMI_CEC, message: &Message, pos: usize) {
+    if pos + 1 == message.len as usize {
+        cec.cec_cr()
+            .modify(|r, w| unsafe { w.bits(r.bits() | TXEOM) });
+    }
+    cec.cec_txdr()
+        .write(|w| unsafe { w.txd().bits(message.bytes[pos]) });
+}
+
+impl<const N: usize> crate::Listen for Cec<N> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.cec
+            .cec_ier()
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.into().bits()) });
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.cec
+            .cec_ier()
+            .write(|w| unsafe { w.bits(event.into().bits()) });
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.cec
+            .cec_ier()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.into().bits()) });
+    }
+}
+
+impl<const N: usize> crate::ReadFlags for Cec<N> {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Event> {
+        BitFlags::from_bits_truncate(self.cec.cec_isr().read().bits())
+    }
+}
+
+impl<const N: usize> crate::ClearFlags for Cec<N> {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Event>>) {
+        self.cec
+            .cec_isr()
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
@@ -0,0 +1,133 @@
+//! Async CEC transfers driven by the `HDMI_CEC` interrupt, which must call
+//! [`on_interrupt`]:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn HDMI_CEC() {
+//!     cec::on_interrupt();
+//! }
+//! ```
+
+use core::{future::poll_fn, task::Poll};
+
+use atomic_waker::AtomicWaker;
+
+use super::{alt, start_tx, tx_step, Cec, Error, Event, Message, RX_DONE, RX_ERRORS, TX_ERRORS};
+use crate::pac::HDMI_CEC;
+
+/// Flags of a transmission: errors, byte request and end
+const TX_FLAGS: u32 = TX_ERRORS | Event::TxByteRequest as u32 | Event::TxEnd as u32;
+/// Flags of a reception: byte received, end and errors
+const RX_FLAGS: u32 = RX_DONE | Event::RxByte as u32;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Wakes the task waiting for the CEC.
+///
+/// Must be called from the `HDMI_CEC` interrupt handler when [`CecAsync`] is used. Interrupts
+/// are disabled, the woken task enables them again if it still has to wait.
+pub fn on_interrupt() {
+    let cec = unsafe { &*HDMI_CEC::ptr() };
+    cec.cec_ier().reset();
+    WAKER.wake();
+}
+
+impl<const N: usize> Cec<N> {
+    /// Converts [`Cec`] to [`CecAsync`], the queued messages are dropped
+    pub fn into_async(self) -> CecAsync {
+        self.cec.cec_ier().reset();
+        CecAsync {
+            cec: self.cec,
+            pin: self.pin,
+        }
+    }
+}
+
+/// CEC awaiting the transfers, one at a time
+pub struct CecAsync {
+    cec: HDMI_CEC,
+    pin: alt::Cec,
+}
+
+impl CecAsync {
+    /// Disables the CEC and releases it with its pin
+    pub fn release(self) -> (HDMI_CEC, alt::Cec) {
+        self.cec.cec_ier().reset();
+        self.cec.cec_cr().write(|w| w.cecen().clear_bit());
+        (self.cec, self.pin)
+    }
+
+    /// Sends `message`, again after an arbitration loss
+    ///
+    /// The messages received in the meantime are dropped. When the future is dropped
+    /// before the end, the transmission is aborted.
+    pub async fn send(&mut self, message: &Message) -> Result<(), Error> {
+        let cec = &self.cec;
+        cec.cec_isr().write(|w| unsafe { w.bits(TX_FLAGS) });
+        let mut abort = TxAbort { cec, pending: true };
+        let mut pos = 0;
+        let result = poll_fn(|cx| {
+            let isr = cec.cec_isr().read().bits() & TX_FLAGS;
+            cec.cec_isr().write(|w| unsafe { w.bits(isr) });
+            if let Some(result) = tx_step(cec, isr, message, &mut pos) {
+                return Poll::Ready(result);
+            }
+            if pos == 0 {
+                start_tx(cec, message);
+                pos = 1;
+            }
+
+            WAKER.register(cx.waker());
+            cec.cec_ier()
+                .modify(|r, w| unsafe { w.bits(r.bits() | TX_FLAGS) });
+            Poll::Pending
+        })
+        .await;
+        abort.pending = false;
+        result
+    }
+
+    /// Receives the next message sent to the device, or to any device in listen mode
+    pub async fn receive(&mut self) -> Result<Message, Error> {
+        let cec = &self.cec;
+        let mut message = Message::empty();
+        let mut error = None;
+        poll_fn(|cx| {
+            let isr = cec.cec_isr().read().bits() & RX_FLAGS;
+            if isr & Event::RxByte as u32 != 0 {
+                message.push(cec.cec_rxdr().read().rxd().bits());
+            }
+            cec.cec_isr().write(|w| unsafe { w.bits(isr) });
+            error = error.or(Error::from_isr(isr & RX_ERRORS));
+            if isr & RX_DONE != 0 {
+                return Poll::Ready(match error {
+                    Some(error) => Err(error),
+                    None => Ok(message),
+                });
+            }
+
+            WAKER.register(cx.waker());
+            cec.cec_ier()
+                .modify(|r, w| unsafe { w.bits(r.bits() | RX_FLAGS) });
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Aborts the transmission of [`CecAsync::send`] when it is dropped before the end
+struct TxAbort<'a> {
+    cec: &'a HDMI_CEC,
+    pending: bool,
+}
+
+impl Drop for TxAbort<'_> {
+    fn drop(&mut self) {
+        if self.pending {
+            // Disabling the CEC also clears `TXSOM` and `TXEOM`
+            self.cec.cec_ier().reset();
+            self.cec.cec_cr().write(|w| w.cecen().clear_bit());
+            self.cec.cec_cr().write(|w| w.cecen().set_bit());
+        }
+    }
+}
@@ -0,0 +1,838 @@
+//! Cryptographic processor (CRYP)
+//!
+//! Encrypts and decrypts with DES, TDES and AES with 128, 192 or 256-bit keys in ECB, CBC
+//! and CTR modes. AES-GCM and AES-CCM are only available on STM32F43x and STM32F479.
+//!
+//! A [`Session`] loads the key and IV of a cipher, data is then fed in one or more calls:
+//!
+//! ```ignore
+//! let mut cryp = Cryp::new(dp.CRYP, &mut rcc);
+//!
+//! let cipher = AesCbc::new(&key, &iv);
+//! let mut session = cryp.start(&cipher, Direction::Encrypt);
+//! session.payload(&plaintext, &mut ciphertext)?;
+//! drop(session);
+//!
+//! let cipher = AesGcm::new(&key, &nonce);
+//! let mut session = cryp.start(&cipher, Direction::Encrypt);
+//! session.aad(&header)?;
+//! session.payload(&plaintext, &mut ciphertext)?;
+//! let tag = session.finish();
+//! ```
+//!
+//! With the `async` feature, [`Cryp::into_async`] moves the payload with DMA2 stream 6
+//! (input) and stream 5 (output).
+//!
+//! With the `cipher` and `aead` features, the adapters of the [`crypto`](crate::crypto)
+//! module implement the RustCrypto traits.
+
+use crate::crypto::{Blocks, Framing, Misuse};
+#[cfg(not(feature = "gpio-f417"))]
+use crate::crypto::{PHASE_FINAL, PHASE_HEADER};
+use crate::pac::CRYP;
+use crate::rcc::{Enable, Rcc, Reset};
+
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{CrypAsync, SessionAsync};
+
+/// CRYP errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The output is shorter than the input, or the input is not a whole number of
+    /// blocks for ECB and CBC modes
+    Length,
+    /// Associated data after the payload, or payload after a partial last block
+    Phase,
+    /// DMA transfer error
+    Dma,
+}
+
+impl Error {
+    fn misuse(misuse: Misuse) -> Self {
+        match misuse {
+            Misuse::Length => Self::Length,
+            Misuse::Phase => Self::Phase,
+        }
+    }
+}
+
+/// Direction of a [`Session`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+const AES_BLOCK: usize = 16;
+const DES_BLOCK: usize = 8;
+
+/// `DATATYPE` of byte strings, the processor swaps the bytes of every word
+const DATATYPE_BYTES: u8 = 0b10;
+
+/// `GCM_CCMPH` of the init phase, the other phases are shared with the AES
+#[cfg(not(feature = "gpio-f417"))]
+const PHASE_INIT: u8 = 0b00;
+
+/// `ALGOMODE` values, bit 3 is `ALGOMODE3`
+mod algo {
+    pub const TDES_ECB: u8 = 0b0000;
+    pub const TDES_CBC: u8 = 0b0001;
+    pub const DES_ECB: u8 = 0b0010;
+    pub const DES_CBC: u8 = 0b0011;
+    pub const AES_ECB: u8 = 0b0100;
+    pub const AES_CBC: u8 = 0b0101;
+    pub const AES_CTR: u8 = 0b0110;
+    pub const AES_KEY: u8 = 0b0111;
+    #[cfg(not(feature = "gpio-f417"))]
+    pub const AES_GCM: u8 = 0b1000;
+    #[cfg(not(feature = "gpio-f417"))]
+    pub const AES_CCM: u8 = 0b1001;
+}
+
+mod sealed {
+    use super::{Direction, CRYP};
+
+    pub trait Cipher {
+        /// Block size in bytes
+        const BLOCK_SIZE: usize;
+        /// `ALGOMODE` of the cipher
+        const ALGOMODE: u8;
+        /// The last block of the payload may be partial
+        const PARTIAL: bool;
+        /// The decryption key must be derived from the key first
+        const PREPARE_KEY: bool = false;
+
+        fn key(&self) -> &[u8];
+
+        fn iv(&self) -> [u8; 16] {
+            [0; 16]
+        }
+
+        /// Runs the init phase of AEAD modes, with the processor disabled
+        fn init(&self, _cryp: &CRYP) {}
+
+        /// Formatted associated data length written before the associated data
+        fn aad_prefix(&self, _prefix: &mut [u8; 6]) -> usize {
+            0
+        }
+
+        /// Prepares the processing of a partial last block, returns the state needed
+        /// by [`Cipher::after_partial`]
+        fn before_partial(&self, _cryp: &CRYP, _dir: Direction) -> [u32; 4] {
+            [0; 4]
+        }
+
+        /// Completes the processing of a partial last block of `len` bytes, given the
+        /// processor output for the zero padded block
+        fn after_partial(
+            &self,
+            _cryp: &CRYP,
+            _dir: Direction,
+            _block: &mut [u8; 16],
+            _state: [u32; 4],
+            _len: usize,
+        ) {
+        }
+
+        /// Block written in the final phase of AEAD modes
+        fn final_block(&self, _aad_len: u64, _payload_len: u64) -> [u8; 16] {
+            [0; 16]
+        }
+    }
+}
+
+/// A cipher and its key, that a [`Session`] is started with
+pub trait Cipher: sealed::Cipher {}
+
+/// A cipher that authenticates associated data and the payload with a tag
+pub trait AeadCipher: Cipher {}
+
+fn check_aes_key(key: &[u8]) {
+    assert!(
+        matches!(key.len(), 16 | 24 | 32),
+        "AES keys are 16, 24 or 32 bytes"
+    );
+}
+
+macro_rules! cipher {
+    ($(#[$attr:meta])* $Cipher:ident, $mode:expr, $block:expr, $partial:expr, $prepare:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy)]
+        pub struct $Cipher<'k> {
+            key: &'k [u8],
+            iv: [u8; 16],
+        }
+
+        impl sealed::Cipher for $Cipher<'_> {
+            const BLOCK_SIZE: usize = $block;
+            const ALGOMODE: u8 = $mode;
+            const PARTIAL: bool = $partial;
+            const PREPARE_KEY: bool = $prepare;
+
+            fn key(&self) -> &[u8] {
+                self.key
+            }
+
+            fn iv(&self) -> [u8; 16] {
+                self.iv
+            }
+        }
+
+        impl Cipher for $Cipher<'_> {}
+    };
+}
+
+cipher!(
+    /// AES in electronic codebook mode
+    AesEcb, algo::AES_ECB, AES_BLOCK, false, true
+);
+cipher!(
+    /// AES in cipher block chaining mode
+    AesCbc, algo::AES_CBC, AES_BLOCK, false, true
+);
+cipher!(
+    /// AES in counter mode
+    AesCtr, algo::AES_CTR, AES_BLOCK, true, false
+);
+cipher!(
+    /// DES in electronic codebook mode
+    DesEcb, algo::DES_ECB, DES_BLOCK, false, false
+);
+cipher!(
+    /// DES in cipher block chaining mode
+    DesCbc, algo::DES_CBC, DES_BLOCK, false, false
+);
+cipher!(
+    /// Triple DES in electronic codebook mode
+    TdesEcb, algo::TDES_ECB, DES_BLOCK, false, false
+);
+cipher!(
+    /// Triple DES in cipher block chaining mode
+    TdesCbc, algo::TDES_CBC, DES_BLOCK, false, false
+);
+
+#[cfg(feature = "cipher")]
+crate::crypto::modes! {
+    AesEcb: EcbMode, U16;
+    AesCbc: CbcMode, U16;
+    AesCtr: CtrMode, U16;
+    DesEcb: EcbMode, U8;
+    DesCbc: CbcMode, U8;
+    TdesEcb: EcbMode, U8;
+    TdesCbc: CbcMode, U8;
+}
+
+fn des_iv(iv: &[u8; 8]) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(iv);
+    block
+}
+
+impl<'k> AesEcb<'k> {
+    /// # Panics
+    ///
+    /// If the key is not 16, 24 or 32 bytes long.
+    pub fn new(key: &'k [u8]) -> Self {
+        check_aes_key(key);
+        Self { key, iv: [0; 16] }
+    }
+}
+
+impl<'k> AesCbc<'k> {
+    /// # Panics
+    ///
+    /// If the key is not 16, 24 or 32 bytes long.
+    pub fn new(key: &'k [u8], iv: &[u8; 16]) -> Self {
+        check_aes_key(key);
+        Self { key, iv: *iv }
+    }
+}
+
+impl<'k> AesCtr<'k> {
+    /// Creates the cipher with the initial counter block `iv`.
+    ///
+    /// # Panics
+    ///
+    /// If the key is not 16, 24 or 32 bytes long.
+    pub fn new(key: &'k [u8], iv: &[u8; 16]) -> Self {
+        check_aes_key(key);
+        Self { key, iv: *iv }
+    }
+}
+
+impl<'k> DesEcb<'k> {
+    pub fn new(key: &'k [u8; 8]) -> Self {
+        Self { key, iv: [0; 16] }
+    }
+}
+
+impl<'k> DesCbc<'k> {
+    pub fn new(key: &'k [u8; 8], iv: &[u8; 8]) -> Self {
+        Self {
+            key,
+            iv: des_iv(iv),
+        }
+    }
+}
+
+impl<'k> TdesEcb<'k> {
+    /// Creates the cipher with the three DES keys one after the other
+    pub fn new(key: &'k [u8; 24]) -> Self {
+        Self { key, iv: [0; 16] }
+    }
+}
+
+impl<'k> TdesCbc<'k> {
+    /// Creates the cipher with the three DES keys one after the other
+    pub fn new(key: &'k [u8; 24], iv: &[u8; 8]) -> Self {
+        Self {
+            key,
+            iv: des_iv(iv),
+        }
+    }
+}
+
+/// AES in Galois/counter mode
+#[cfg(not(feature = "gpio-f417"))]
+#[derive(Clone, Copy)]
+pub struct AesGcm<'k> {
+    key: &'k [u8],
+    iv: [u8; 16],
+}
+
+#[cfg(not(feature = "gpio-f417"))]
+impl<'k> AesGcm<'k> {
+    /// Creates the cipher with a 96-bit `nonce`.
+    ///
+    /// # Panics
+    ///
+    /// If the key is not 16, 24 or 32 bytes long.
+    pub fn new(key: &'k [u8], nonce: &[u8; 12]) -> Self {
+        check_aes_key(key);
+        let mut iv = [0; 16];
+        iv[..12].copy_from_slice(nonce);
+        // The payload starts with counter 2, counter 1 encrypts the tag
+        iv[15] = 2;
+        Self { key, iv }
+    }
+}
+
+#[cfg(not(feature = "gpio-f417"))]
+impl sealed::Cipher for AesGcm<'_> {
+    const BLOCK_SIZE: usize = AES_BLOCK;
+    const ALGOMODE: u8 = algo::AES_GCM;
+    const PARTIAL: bool = true;
+
+    fn key(&self) -> &[u8] {
+        self.key
+    }
+
+    fn iv(&self) -> [u8; 16] {
+        self.iv
+    }
+
+    fn init(&self, cryp: &CRYP) {
+        // Computes the hash subkey
+        cryp.cr()
+            .modify(|_, w| unsafe { w.gcm_ccmph().bits(PHASE_INIT) });
+        cryp.cr().modify(|_, w| w.crypen().set_bit());
+        while cryp.cr().read().crypen().bit_is_set() {}
+    }
+
+    fn before_partial(&self, cryp: &CRYP, dir: Direction) -> [u32; 4] {
+        // The processor would hash the encrypted padding, encrypt the last block in CTR mode
+        // and hash the masked result in the final phase instead
+        if dir == Direction::Encrypt {
+            cryp.cr().modify(|_, w| w.crypen().clear_bit());
+            set_algomode(cryp, algo::AES_CTR);
+            let counter = cryp.csgcmccmr(7).read().bits().wrapping_sub(1);
+            cryp.init(1).ivrr().write(|w| unsafe { w.bits(counter) });
+            cryp.cr().modify(|_, w| w.crypen().set_bit());
+        }
+        [0; 4]
+    }
+
+    fn after_partial(
+        &self,
+        cryp: &CRYP,
+        dir: Direction,
+        block: &mut [u8; 16],
+        _state: [u32; 4],
+        len: usize,
+    ) {
+        if dir == Direction::Encrypt {
+            cryp.cr().modify(|_, w| w.crypen().clear_bit());
+            set_algomode(cryp, algo::AES_GCM);
+            block[len..].fill(0);
+            cryp.cr().modify(|_, w| w.crypen().set_bit());
+            cryp.cr()
+                .modify(|_, w| unsafe { w.gcm_ccmph().bits(PHASE_FINAL) });
+            let mut discard = [0; 16];
+            process_block(cryp, &block[..], &mut discard);
+        }
+    }
+
+    fn final_block(&self, aad_len: u64, payload_len: u64) -> [u8; 16] {
+        crate::crypto::gcm_final_block(aad_len, payload_len)
+    }
+}
+
+#[cfg(not(feature = "gpio-f417"))]
+impl Cipher for AesGcm<'_> {}
+#[cfg(not(feature = "gpio-f417"))]
+impl AeadCipher for AesGcm<'_> {}
+
+/// AES in counter with CBC-MAC mode
+///
+/// The lengths of the associated data and of the payload are part of the first
+/// authenticated block, so they are given upfront.
+#[cfg(not(feature = "gpio-f417"))]
+#[derive(Clone, Copy)]
+pub struct AesCcm<'k> {
+    key: &'k [u8],
+    b0: [u8; 16],
+    aad_len: usize,
+}
+
+#[cfg(not(feature = "gpio-f417"))]
+impl<'k> AesCcm<'k> {
+    /// Creates the cipher for `aad_len` bytes of associated data and `payload_len` bytes
+    /// of payload, with a `tag_len` bytes long tag.
+    ///
+    /// # Panics
+    ///
+    /// If the key is not 16, 24 or 32 bytes long, the nonce is not 7 to 13 bytes long, the
+    /// tag length is not even and between 4 and 16 or the payload length does not fit in
+    /// the bytes left by the nonce.
+    pub fn new(
+        key: &'k [u8],
+        nonce: &[u8],
+        aad_len: usize,
+        payload_len: usize,
+        tag_len: usize,
+    ) -> Self {
+        check_aes_key(key);
+        let b0 = crate::crypto::ccm_b0(nonce, aad_len, payload_len, tag_len);
+        Self { key, b0, aad_len }
+    }
+
+    /// Counter block `n`
+    fn counter(&self, n: u8) -> [u8; 16] {
+        let q = (self.b0[0] & 0x07) as usize + 1;
+        let mut block = self.b0;
+        block[0] &= 0x07;
+        block[16 - q..].fill(0);
+        block[15] = n;
+        block
+    }
+}
+
+#[cfg(not(feature = "gpio-f417"))]
+impl sealed::Cipher for AesCcm<'_> {
+    const BLOCK_SIZE: usize = AES_BLOCK;
+    const ALGOMODE: u8 = algo::AES_CCM;
+    const PARTIAL: bool = true;
+
+    fn key(&self) -> &[u8] {
+        self.key
+    }
+
+    fn iv(&self) -> [u8; 16] {
+        self.counter(1)
+    }
+
+    fn init(&self, cryp: &CRYP) {
+        cryp.cr()
+            .modify(|_, w| unsafe { w.gcm_ccmph().bits(PHASE_INIT) });
+        write_block(cryp, &self.b0);
+        cryp.cr().modify(|_, w| w.crypen().set_bit());
+        while cryp.cr().read().crypen().bit_is_set() {}
+    }
+
+    fn aad_prefix(&self, prefix: &mut [u8; 6]) -> usize {
+        crate::crypto::ccm_aad_prefix(self.aad_len, prefix)
+    }
+
+    fn before_partial(&self, cryp: &CRYP, dir: Direction) -> [u32; 4] {
+        // The processor would authenticate the decrypted padding, decrypt the last block in
+        // CTR mode and correct the MAC state with the masked result instead
+        let mut state = [0; 4];
+        if dir == Direction::Decrypt {
+            cryp.cr().modify(|_, w| w.crypen().clear_bit());
+            let counter = cryp.init(1).ivrr().read().bits();
+            for (i, s) in state.iter_mut().enumerate() {
+                *s = cryp.csgcmccmr(i).read().bits().swap_bytes();
+            }
+            cryp.init(1).ivrr().write(|w| unsafe { w.bits(counter) });
+            set_algomode(cryp, algo::AES_CTR);
+            cryp.cr().modify(|_, w| w.crypen().set_bit());
+        }
+        state
+    }
+
+    fn after_partial(
+        &self,
+        cryp: &CRYP,
+        dir: Direction,
+        block: &mut [u8; 16],
+        state: [u32; 4],
+        len: usize,
+    ) {
+        if dir == Direction::Decrypt {
+            let mut masked = *block;
+            masked[len..].fill(0);
+            let mut words = [0; 4];
+            for (i, w) in words.iter_mut().enumerate() {
+                let plain = u32::from_le_bytes(masked[4 * i..4 * i + 4].try_into().unwrap());
+                *w = plain ^ state[i] ^ cryp.csgcmccmr(i).read().bits().swap_bytes();
+            }
+            set_algomode(cryp, algo::AES_CCM);
+            cryp.cr()
+                .modify(|_, w| unsafe { w.gcm_ccmph().bits(PHASE_FINAL) });
+            cryp.cr()
+                .modify(|_, w| unsafe { w.gcm_ccmph().bits(PHASE_HEADER) });
+            for w in words {
+                write_word(cryp, w);
+            }
+        }
+    }
+
+    fn final_block(&self, _aad_len: u64, _payload_len: u64) -> [u8; 16] {
+        self.counter(0)
+    }
+}
+
+#[cfg(not(feature = "gpio-f417"))]
+impl Cipher for AesCcm<'_> {}
+#[cfg(not(feature = "gpio-f417"))]
+impl AeadCipher for AesCcm<'_> {}
+
+fn set_algomode(cryp: &CRYP, mode: u8) {
+    cryp.cr().modify(|_, w| unsafe {
+        w.algomode0().bits(mode & 0b111);
+        w.algomode3().bit(mode & 0b1000 != 0)
+    });
+}
+
+fn write_word(cryp: &CRYP, word: u32) {
+    while cryp.sr().read().ifnf().bit_is_clear() {}
+    cryp.din().write(|w| unsafe { w.bits(word) });
+}
+
+/// Writes one block of bytes to the input FIFO
+fn write_block(cryp: &CRYP, input: &[u8]) {
+    for word in input.chunks_exact(4) {
+        write_word(cryp, u32::from_le_bytes(word.try_into().unwrap()));
+    }
+}
+
+/// Processes one block of bytes
+fn process_block(cryp: &CRYP, input: &[u8], output: &mut [u8]) {
+    // The FIFOs hold 8 words, more than one block
+    write_block(cryp, input);
+    for word in output[..input.len()].chunks_exact_mut(4) {
+        while cryp.sr().read().ofne().bit_is_clear() {}
+        word.copy_from_slice(&cryp.dout().read().bits().to_le_bytes());
+    }
+}
+
+fn wait_idle(cryp: &CRYP) {
+    while cryp.sr().read().busy().bit_is_set() {}
+}
+
+impl Blocks for CRYP {
+    fn aad_block(&self, block: &[u8; 16]) {
+        write_block(self, block);
+    }
+
+    fn set_phase(&self, phase: u8) {
+        // Restarts the processor in `phase`
+        wait_idle(self);
+        self.cr().modify(|_, w| w.crypen().clear_bit());
+        self.cr()
+            .modify(|_, w| unsafe { w.gcm_ccmph().bits(phase) });
+        self.cr().modify(|_, w| w.crypen().set_bit());
+    }
+
+    fn process_block(&self, input: &[u8], output: &mut [u8]) {
+        process_block(self, input, output);
+    }
+}
+
+/// Cryptographic processor
+pub struct Cryp {
+    cryp: CRYP,
+}
+
+impl Cryp {
+    /// Enables the clock of the processor and resets it
+    pub fn new(cryp: CRYP, rcc: &mut Rcc) -> Self {
+        CRYP::enable(rcc);
+        CRYP::reset(rcc);
+        Self { cryp }
+    }
+
+    /// Releases the CRYP peripheral
+    pub fn release(self) -> CRYP {
+        self.cryp.cr().reset();
+        self.cryp
+    }
+
+    /// Loads the key and IV of `cipher` and starts a session that encrypts or decrypts
+    /// with it
+    pub fn start<'a, C: Cipher>(&'a mut self, cipher: &'a C, dir: Direction) -> Session<'a, C> {
+        Session::new(&self.cryp, cipher, dir)
+    }
+
+    /// Encrypts or decrypts `input` into `output` in a single session
+    pub fn process<C: Cipher>(
+        &mut self,
+        cipher: &C,
+        dir: Direction,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.start(cipher, dir).payload(input, output)
+    }
+}
+
+#[cfg(any(feature = "cipher", feature = "aead"))]
+impl crate::Sealed for Cryp {}
+
+#[cfg(feature = "cipher")]
+impl<C: Cipher> crate::crypto::Processor<C> for Cryp {
+    fn run(&mut self, cipher: &C, decrypt: bool, buf: &mut [u8]) {
+        let dir = if decrypt {
+            Direction::Decrypt
+        } else {
+            Direction::Encrypt
+        };
+        crate::crypto::run(self.start(cipher, dir), C::BLOCK_SIZE, buf);
+    }
+}
+
+#[cfg(all(feature = "aead", not(feature = "gpio-f417")))]
+impl crate::crypto::AeadProcessor for Cryp {
+    fn check_key(key: &[u8]) {
+        check_aes_key(key);
+    }
+
+    fn gcm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16] {
+        self.run_aead(&AesGcm::new(key, nonce), decrypt, aad, buf)
+    }
+
+    fn ccm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        tag_len: usize,
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16] {
+        let cipher = AesCcm::new(key, nonce, aad.len(), buf.len(), tag_len);
+        self.run_aead(&cipher, decrypt, aad, buf)
+    }
+}
+
+#[cfg(all(feature = "aead", not(feature = "gpio-f417")))]
+impl Cryp {
+    /// Runs `cipher` over `aad` and `buf` in place in a new session, returns the tag
+    fn run_aead<C: AeadCipher>(
+        &mut self,
+        cipher: &C,
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16] {
+        let dir = if decrypt {
+            Direction::Decrypt
+        } else {
+            Direction::Encrypt
+        };
+        crate::crypto::run_aead(self.start(cipher, dir), aad, buf)
+    }
+}
+
+/// Encryption or decryption with one cipher
+///
+/// Associated data of AEAD ciphers comes first, then the payload. Every payload but the
+/// last must be a whole number of blocks. The processor is disabled when the session is
+/// dropped.
+pub struct Session<'a, C: Cipher> {
+    cryp: &'a CRYP,
+    cipher: &'a C,
+    dir: Direction,
+    framing: Framing,
+}
+
+impl<'a, C: Cipher> Session<'a, C> {
+    fn new(cryp: &'a CRYP, cipher: &'a C, dir: Direction) -> Self {
+        cryp.cr().modify(|_, w| w.crypen().clear_bit());
+        cryp.dmacr().reset();
+        let key = sealed::Cipher::key(cipher);
+        cryp.cr().write(|w| unsafe {
+            w.algodir().bit(dir == Direction::Decrypt);
+            w.datatype().bits(DATATYPE_BYTES);
+            w.keysize().bits(match key.len() {
+                24 => 0b01,
+                32 => 0b10,
+                _ => 0b00,
+            })
+        });
+        set_algomode(cryp, C::ALGOMODE);
+
+        // AES keys end in K3RR, DES keys start in K1LR
+        let first = if C::BLOCK_SIZE == DES_BLOCK {
+            2
+        } else {
+            8 - key.len() / 4
+        };
+        for (i, word) in key.chunks_exact(4).enumerate() {
+            let word = u32::from_be_bytes(word.try_into().unwrap());
+            let n = first + i;
+            if n % 2 == 0 {
+                cryp.key(n / 2).klr().write(|w| unsafe { w.bits(word) });
+            } else {
+                cryp.key(n / 2).krr().write(|w| unsafe { w.bits(word) });
+            }
+        }
+        if C::PREPARE_KEY && dir == Direction::Decrypt {
+            set_algomode(cryp, algo::AES_KEY);
+            cryp.cr().modify(|_, w| w.crypen().set_bit());
+            wait_idle(cryp);
+            cryp.cr().modify(|_, w| w.crypen().clear_bit());
+            set_algomode(cryp, C::ALGOMODE);
+        }
+
+        let iv = sealed::Cipher::iv(cipher);
+        for (n, half) in iv.chunks_exact(8).enumerate() {
+            let left = u32::from_be_bytes(half[..4].try_into().unwrap());
+            let right = u32::from_be_bytes(half[4..].try_into().unwrap());
+            cryp.init(n).ivlr().write(|w| unsafe { w.bits(left) });
+            cryp.init(n).ivrr().write(|w| unsafe { w.bits(right) });
+        }
+        cryp.cr().modify(|_, w| w.fflush().set_bit());
+
+        cipher.init(cryp);
+        let mut framing = Framing::new();
+        if C::ALGOMODE & 0b1000 != 0 {
+            let mut prefix = [0; 6];
+            let len = cipher.aad_prefix(&mut prefix);
+            framing.start_header(cryp, &prefix[..len]);
+        } else {
+            cryp.cr().modify(|_, w| w.crypen().set_bit());
+        }
+        Self {
+            cryp,
+            cipher,
+            dir,
+            framing,
+        }
+    }
+
+    /// Checks the lengths of a payload and enters the payload phase, returns the length
+    /// of its whole blocks
+    fn start_payload(&mut self, input: &[u8], output: &[u8]) -> Result<usize, Error> {
+        self.framing
+            .start_payload(
+                self.cryp,
+                input.len(),
+                output.len(),
+                C::BLOCK_SIZE,
+                C::PARTIAL,
+            )
+            .map_err(Error::misuse)
+    }
+
+    /// Processes the partial last block of a payload
+    fn partial_block(&mut self, input: &[u8], output: &mut [u8]) {
+        let mut block = [0; 16];
+        block[..input.len()].copy_from_slice(input);
+        let state = self.cipher.before_partial(self.cryp, self.dir);
+        let mut out = [0; 16];
+        process_block(self.cryp, &block[..C::BLOCK_SIZE], &mut out);
+        self.cipher
+            .after_partial(self.cryp, self.dir, &mut out, state, input.len());
+        output[..input.len()].copy_from_slice(&out[..input.len()]);
+        self.framing.end_payload();
+    }
+
+    /// Encrypts or decrypts `input` into the start of `output`.
+    ///
+    /// A payload that is not a whole number of blocks ends the session, only stream modes
+    /// (CTR, GCM and CCM) accept it.
+    pub fn payload(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        let full = self.start_payload(input, output)?;
+        for (i, o) in input[..full]
+            .chunks_exact(C::BLOCK_SIZE)
+            .zip(output.chunks_exact_mut(C::BLOCK_SIZE))
+        {
+            process_block(self.cryp, i, o);
+        }
+        if full != input.len() {
+            self.partial_block(&input[full..], &mut output[full..]);
+        }
+        Ok(())
+    }
+}
+
+impl<C: AeadCipher> Session<'_, C> {
+    /// Authenticates associated data, which can be split in any number of calls before
+    /// the payload.
+    ///
+    /// CCM needs exactly the length of associated data given to [`AesCcm::new`].
+    pub fn aad(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.framing.aad(self.cryp, data).map_err(Error::misuse)
+    }
+
+    /// Ends the session and returns the authentication tag.
+    ///
+    /// A CCM tag is the first `tag_len` bytes. When decrypting, the tag must be compared
+    /// with the received one before the plaintext is used.
+    pub fn finish(mut self) -> [u8; 16] {
+        let cipher = self.cipher;
+        self.framing.finish(self.cryp, |aad_len, payload_len| {
+            cipher.final_block(aad_len, payload_len)
+        })
+    }
+}
+
+#[cfg(any(feature = "cipher", all(feature = "aead", not(feature = "gpio-f417"))))]
+impl<C: Cipher> crate::crypto::Session for Session<'_, C> {
+    type Error = Error;
+
+    fn payload(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        Session::payload(self, input, output)
+    }
+}
+
+#[cfg(all(feature = "aead", not(feature = "gpio-f417")))]
+impl<C: AeadCipher> crate::crypto::AeadSession for Session<'_, C> {
+    fn aad(&mut self, data: &[u8]) -> Result<(), Error> {
+        Session::aad(self, data)
+    }
+
+    fn finish(self) -> [u8; 16] {
+        Session::finish(self)
+    }
+}
+
+impl<C: Cipher> Drop for Session<'_, C> {
+    fn drop(&mut self) {
+        self.cryp.cr().modify(|_, w| w.crypen().clear_bit());
+        self.cryp.dmacr().reset();
+    }
+}
@@ -0,0 +1,176 @@
+//! Async payload processing with DMA.
+//!
+//! Transfers are awaited with the interrupts of DMA2 stream 6 (input) and stream 5
+//! (output), which must call [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt):
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn DMA2_STREAM5() {
+//!     dma::Stream5::<pac::DMA2>::on_interrupt();
+//! }
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM6() {
+//!     dma::Stream6::<pac::DMA2>::on_interrupt();
+//! }
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+use super::{AeadCipher, Cipher, Cryp, Direction, Error, Session};
+use crate::crypto::DMA_MAX_WORDS;
+use crate::dma::{
+    self,
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use crate::pac::CRYP;
+
+impl Cryp {
+    /// Converts [`Cryp`] to [`CrypAsync`] that moves the payload with the `input` and
+    /// `output` streams
+    pub fn into_async<IN, OUT, const CH: u8>(self, input: IN, output: OUT) -> CrypAsync<IN, OUT, CH>
+    where
+        IN: Stream,
+        OUT: Stream,
+        ChannelX<CH>: Channel,
+        CRYP: DMASet<IN, CH, MemoryToPeripheral> + DMASet<OUT, CH, PeripheralToMemory>,
+    {
+        CrypAsync {
+            cryp: self,
+            input,
+            output,
+        }
+    }
+}
+
+/// Cryptographic processor that moves the payload with DMA
+///
+/// Derefs to [`Cryp`], so the blocking API stays available. A client must enable the
+/// interrupts of both DMA streams and call
+/// [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in them.
+pub struct CrypAsync<IN, OUT, const CH: u8> {
+    cryp: Cryp,
+    input: IN,
+    output: OUT,
+}
+
+impl<IN, OUT, const CH: u8> CrypAsync<IN, OUT, CH>
+where
+    IN: Stream,
+    OUT: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Returns the [`Cryp`] and the DMA streams
+    pub fn release(self) -> (Cryp, IN, OUT) {
+        (self.cryp, self.input, self.output)
+    }
+
+    /// Loads the key and IV of `cipher` and starts a session that encrypts or decrypts
+    /// with it
+    pub fn start<'a, C: Cipher>(
+        &'a mut self,
+        cipher: &'a C,
+        dir: Direction,
+    ) -> SessionAsync<'a, C, IN, OUT, CH> {
+        SessionAsync {
+            session: Session::new(&self.cryp.cryp, cipher, dir),
+            input: &mut self.input,
+            output: &mut self.output,
+        }
+    }
+}
+
+/// Encryption or decryption with one cipher, the payload is moved with DMA
+///
+/// Follows the rules of [`Session`]. Dropping a pending payload stops the transfers.
+pub struct SessionAsync<'a, C: Cipher, IN, OUT, const CH: u8> {
+    session: Session<'a, C>,
+    input: &'a mut IN,
+    output: &'a mut OUT,
+}
+
+impl<C, IN, OUT, const CH: u8> SessionAsync<'_, C, IN, OUT, CH>
+where
+    C: Cipher,
+    IN: Stream,
+    OUT: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Encrypts or decrypts `input` into the start of `output`.
+    ///
+    /// Whole blocks are moved with DMA, a partial last block ends the session like with
+    /// [`Session::payload`].
+    pub async fn payload(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        let full = self.session.start_payload(input, output)?;
+        let cryp = self.session.cryp;
+        for (i, o) in input[..full]
+            .chunks(DMA_MAX_WORDS * 4)
+            .zip(output.chunks_mut(DMA_MAX_WORDS * 4))
+        {
+            let words = (i.len() / 4) as u16;
+            // NOTE(unsafe) the buffers outlive the transfers which are stopped on drop
+            let mut output = unsafe {
+                OneShot::start_packed(
+                    &mut *self.output,
+                    ChannelX::<CH>::VALUE,
+                    DmaDirection::PeripheralToMemory,
+                    cryp.dout().as_ptr() as u32,
+                    o.as_mut_ptr() as *const u8,
+                    words,
+                )
+            };
+            let mut input = unsafe {
+                OneShot::start_packed(
+                    &mut *self.input,
+                    ChannelX::<CH>::VALUE,
+                    DmaDirection::MemoryToPeripheral,
+                    cryp.din().as_ptr() as u32,
+                    i.as_ptr(),
+                    words,
+                )
+            };
+            cryp.dmacr().write(|w| {
+                w.dien().set_bit();
+                w.doen().set_bit()
+            });
+            let result = dma::wait_both(&mut input, &mut output).await;
+            cryp.dmacr().reset();
+            result.map_err(|_| Error::Dma)?;
+        }
+        if full != input.len() {
+            self.session
+                .partial_block(&input[full..], &mut output[full..]);
+        }
+        Ok(())
+    }
+}
+
+impl<C, IN, OUT, const CH: u8> SessionAsync<'_, C, IN, OUT, CH>
+where
+    C: AeadCipher,
+{
+    /// See [`Session::aad`]
+    pub fn aad(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.session.aad(data)
+    }
+
+    /// See [`Session::finish`]
+    pub fn finish(self) -> [u8; 16] {
+        self.session.finish()
+    }
+}
+
+impl<IN, OUT, const CH: u8> Deref for CrypAsync<IN, OUT, CH> {
+    type Target = Cryp;
+
+    fn deref(&self) -> &Cryp {
+        &self.cryp
+    }
+}
+
+impl<IN, OUT, const CH: u8> DerefMut for CrypAsync<IN, OUT, CH> {
+    fn deref_mut(&mut self) -> &mut Cryp {
+        &mut self.cryp
+    }
+}
@@ -0,0 +1,795 @@
+//! RustCrypto `cipher` and `aead` traits for the CRYP and AES processors
+//!
+//! An adapter owns a processor, `cryp::Cryp` or `aes::Aes`, and runs one session of it per
+//! call. The chaining state of CBC and CTR is kept by the adapter between the calls.
+//!
+//! - With the `cipher` feature, [`Ecb`] implements `BlockEncrypt` and `BlockDecrypt`, [`Cbc`]
+//!   implements `BlockEncryptMut` and `BlockDecryptMut` and [`Ctr`] implements
+//!   `StreamCipher`.
+//! - With the `aead` feature, [`Gcm`] and [`Ccm`] implement `AeadInPlace`.
+//!
+//! ```ignore
+//! use aead::AeadInPlace;
+//! use cipher::BlockEncryptMut;
+//!
+//! let mut cbc = Cbc::new(Cryp::new(dp.CRYP, &mut rcc), AesCbc::new(&key, &iv));
+//! cbc.encrypt_blocks_mut(&mut blocks);
+//!
+//! let (cryp, _) = cbc.release();
+//! let gcm = Gcm::new(cryp, &key);
+//! let tag = gcm.encrypt_in_place_detached(&nonce.into(), &header, &mut buffer)?;
+//! ```
+//!
+//! The framing of associated data and payload shared by the `cryp` and `aes` sessions lives
+//! here too.
+
+#[cfg(feature = "aead")]
+use aead::{
+    consts::{U0, U12, U16},
+    generic_array::{ArrayLength, GenericArray},
+    AeadCore, AeadInPlace, Nonce, Tag,
+};
+#[cfg(feature = "cipher")]
+use cipher::{
+    consts::U8,
+    inout::{InOut, InOutBuf},
+    typenum::Unsigned,
+    Block, BlockBackend, BlockClosure, BlockDecrypt, BlockDecryptMut, BlockEncrypt,
+    BlockEncryptMut, BlockSizeUser, ParBlocks, ParBlocksSizeUser, StreamCipher, StreamCipherError,
+};
+#[cfg(any(feature = "cipher", feature = "aead"))]
+use core::cell::RefCell;
+#[cfg(feature = "aead")]
+use core::marker::PhantomData;
+
+/// `GCM_CCMPH` of the CRYP and `GCMPH` of the AES, the phases of AEAD modes
+pub(crate) const PHASE_HEADER: u8 = 0b01;
+pub(crate) const PHASE_PAYLOAD: u8 = 0b10;
+pub(crate) const PHASE_FINAL: u8 = 0b11;
+
+/// Block transfers of a processor, used by [`Framing`]
+pub(crate) trait Blocks {
+    /// Writes one block of associated data and waits until it is processed
+    fn aad_block(&self, block: &[u8; 16]);
+
+    /// Switches the enabled processor to an AEAD `phase`
+    fn set_phase(&self, phase: u8);
+
+    /// Processes one block of bytes
+    fn process_block(&self, input: &[u8], output: &mut [u8]);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Header,
+    Payload,
+    /// A partial last block was processed
+    End,
+}
+
+/// Misuse of a session, mapped to the `Length` and `Phase` errors of the drivers
+pub(crate) enum Misuse {
+    Length,
+    Phase,
+}
+
+/// Progress of a session: associated data first, then the payload
+#[derive(Clone, Copy)]
+pub(crate) struct Framing {
+    phase: Phase,
+    /// Associated data that does not fill a block yet
+    aad_buf: [u8; 16],
+    aad_buf_len: usize,
+    aad_len: u64,
+    payload_len: u64,
+}
+
+impl Framing {
+    /// A session without associated data
+    pub(crate) const fn new() -> Self {
+        Self {
+            phase: Phase::Payload,
+            aad_buf: [0; 16],
+            aad_buf_len: 0,
+            aad_len: 0,
+            payload_len: 0,
+        }
+    }
+
+    /// Enters the header phase of an AEAD mode, starting with the formatted length of the
+    /// associated data
+    pub(crate) fn start_header(&mut self, blocks: &impl Blocks, prefix: &[u8]) {
+        self.phase = Phase::Header;
+        blocks.set_phase(PHASE_HEADER);
+        self.buffer_aad(blocks, prefix);
+    }
+
+    /// Writes whole blocks of associated data and keeps the rest
+    fn buffer_aad(&mut self, blocks: &impl Blocks, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = data.len().min(16 - self.aad_buf_len);
+            self.aad_buf[self.aad_buf_len..self.aad_buf_len + n].copy_from_slice(&data[..n]);
+            self.aad_buf_len += n;
+            data = &data[n..];
+            if self.aad_buf_len == 16 {
+                blocks.aad_block(&self.aad_buf);
+                self.aad_buf_len = 0;
+            }
+        }
+    }
+
+    /// Writes the zero padded rest of the associated data and leaves the header phase
+    fn end_header(&mut self, blocks: &impl Blocks, next: u8) {
+        if self.aad_buf_len != 0 {
+            self.aad_buf[self.aad_buf_len..].fill(0);
+            blocks.aad_block(&self.aad_buf);
+            self.aad_buf_len = 0;
+        }
+        blocks.set_phase(next);
+    }
+
+    /// Authenticates associated data before the payload
+    pub(crate) fn aad(&mut self, blocks: &impl Blocks, data: &[u8]) -> Result<(), Misuse> {
+        if self.phase != Phase::Header {
+            return Err(Misuse::Phase);
+        }
+        self.buffer_aad(blocks, data);
+        self.aad_len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Checks the lengths of a payload and enters the payload phase, returns the length
+    /// of its whole blocks of `block` bytes
+    pub(crate) fn start_payload(
+        &mut self,
+        blocks: &impl Blocks,
+        input: usize,
+        output: usize,
+        block: usize,
+        partial: bool,
+    ) -> Result<usize, Misuse> {
+        let full = input - input % block;
+        if output < input || (full != input && !partial) {
+            return Err(Misuse::Length);
+        }
+        match self.phase {
+            Phase::Header => {
+                self.end_header(blocks, PHASE_PAYLOAD);
+                self.phase = Phase::Payload;
+            }
+            Phase::Payload => {}
+            Phase::End => return Err(Misuse::Phase),
+        }
+        self.payload_len += input as u64;
+        Ok(full)
+    }
+
+    /// Ends the payload after a partial last block
+    pub(crate) fn end_payload(&mut self) {
+        self.phase = Phase::End;
+    }
+
+    /// Bytes of payload given so far
+    #[cfg(feature = "aes")]
+    pub(crate) fn payload_len(&self) -> u64 {
+        self.payload_len
+    }
+
+    /// Runs the final phase with the block returned by `final_block` for the lengths of
+    /// the associated data and of the payload, returns the tag
+    pub(crate) fn finish(
+        &mut self,
+        blocks: &impl Blocks,
+        final_block: impl FnOnce(u64, u64) -> [u8; 16],
+    ) -> [u8; 16] {
+        if self.phase == Phase::Header {
+            self.end_header(blocks, PHASE_FINAL);
+        } else {
+            blocks.set_phase(PHASE_FINAL);
+        }
+        let block = final_block(self.aad_len, self.payload_len);
+        let mut tag = [0; 16];
+        blocks.process_block(&block, &mut tag);
+        tag
+    }
+}
+
+/// Final GCM block, the lengths in bits of the associated data and of the payload
+#[cfg(any(feature = "aes", not(feature = "gpio-f417")))]
+pub(crate) fn gcm_final_block(aad_len: u64, payload_len: u64) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&(aad_len * 8).to_be_bytes());
+    block[8..].copy_from_slice(&(payload_len * 8).to_be_bytes());
+    block
+}
+
+/// First CCM block `B0`, with the flags, the nonce and the payload length.
+///
+/// Panics if the nonce is not 7 to 13 bytes long, the tag length is not even and between
+/// 4 and 16 or the payload length does not fit in the bytes left by the nonce.
+#[cfg(any(feature = "aes", not(feature = "gpio-f417")))]
+pub(crate) fn ccm_b0(nonce: &[u8], aad_len: usize, payload_len: usize, tag_len: usize) -> [u8; 16] {
+    assert!((7..=13).contains(&nonce.len()));
+    assert!((4..=16).contains(&tag_len) && tag_len % 2 == 0);
+    // Bytes of the payload length field
+    let q = 15 - nonce.len();
+    let length = (payload_len as u64).to_be_bytes();
+    assert!(length[..8 - q].iter().all(|&b| b == 0));
+
+    let mut b0 = [0; 16];
+    b0[0] = ((aad_len > 0) as u8) << 6 | (((tag_len - 2) / 2) as u8) << 3 | (q - 1) as u8;
+    b0[1..1 + nonce.len()].copy_from_slice(nonce);
+    b0[16 - q..].copy_from_slice(&length[8 - q..]);
+    b0
+}
+
+/// Encodes the CCM associated data length into `prefix`, returns its size
+#[cfg(any(feature = "aes", not(feature = "gpio-f417")))]
+pub(crate) fn ccm_aad_prefix(aad_len: usize, prefix: &mut [u8; 6]) -> usize {
+    let len = aad_len as u32;
+    if len == 0 {
+        0
+    } else if len < 0xFF00 {
+        prefix[..2].copy_from_slice(&(len as u16).to_be_bytes());
+        2
+    } else {
+        prefix[..2].copy_from_slice(&[0xFF, 0xFE]);
+        prefix[2..].copy_from_slice(&len.to_be_bytes());
+        6
+    }
+}
+
+/// Most words of one DMA transfer of a payload, a whole number of AES and DES blocks
+#[cfg(feature = "async")]
+pub(crate) const DMA_MAX_WORDS: usize = 0xFFFC;
+
+/// A session of the `cryp` or `aes` module
+#[cfg(any(
+    feature = "cipher",
+    all(feature = "aead", any(feature = "aes", not(feature = "gpio-f417")))
+))]
+pub(crate) trait Session {
+    type Error: core::fmt::Debug;
+
+    fn payload(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// A session of an AEAD cipher
+#[cfg(all(feature = "aead", any(feature = "aes", not(feature = "gpio-f417"))))]
+pub(crate) trait AeadSession: Session {
+    fn aad(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    fn finish(self) -> [u8; 16];
+}
+
+/// Encrypts or decrypts `buf` in place with a new `session`, in blocks of `block` bytes
+#[cfg(feature = "cipher")]
+pub(crate) fn run(mut session: impl Session, block: usize, buf: &mut [u8]) {
+    // The adapters only give whole blocks, or a partial last block in CTR mode
+    in_place(buf, block, |i, o| session.payload(i, o)).expect("payload of a new session");
+}
+
+/// Runs a new AEAD `session` over `aad` and `buf` in place, returns the tag
+#[cfg(all(feature = "aead", any(feature = "aes", not(feature = "gpio-f417"))))]
+pub(crate) fn run_aead(mut session: impl AeadSession, aad: &[u8], buf: &mut [u8]) -> [u8; 16] {
+    session.aad(aad).expect("associated data of a new session");
+    in_place(buf, 16, |i, o| session.payload(i, o)).expect("payload after the associated data");
+    session.finish()
+}
+
+/// Feeds `buf` block by block to `payload`, which writes its output in place
+#[cfg(any(
+    feature = "cipher",
+    all(feature = "aead", any(feature = "aes", not(feature = "gpio-f417")))
+))]
+fn in_place<E>(
+    buf: &mut [u8],
+    block: usize,
+    mut payload: impl FnMut(&[u8], &mut [u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut input = [0; 16];
+    for chunk in buf.chunks_mut(block) {
+        let input = &mut input[..chunk.len()];
+        input.copy_from_slice(chunk);
+        payload(input, chunk)?;
+    }
+    Ok(())
+}
+
+/// A processor that runs the cipher `C`
+#[cfg(feature = "cipher")]
+pub trait Processor<C>: crate::Sealed {
+    /// Encrypts or decrypts `buf` in place in a new session
+    #[doc(hidden)]
+    fn run(&mut self, cipher: &C, decrypt: bool, buf: &mut [u8]);
+}
+
+/// A cipher of the `cryp` or `aes` module
+#[cfg(feature = "cipher")]
+pub trait Mode: Copy + crate::Sealed {
+    /// Block size in bytes
+    type BlockSize: cipher::ArrayLength<u8>;
+
+    #[doc(hidden)]
+    fn iv_mut(&mut self) -> &mut [u8; 16];
+}
+
+/// A cipher in electronic codebook mode, used by [`Ecb`]
+#[cfg(feature = "cipher")]
+pub trait EcbMode: Mode {}
+
+/// A cipher in cipher block chaining mode, used by [`Cbc`]
+#[cfg(feature = "cipher")]
+pub trait CbcMode: Mode {}
+
+/// A cipher in counter mode, used by [`Ctr`]
+#[cfg(feature = "cipher")]
+pub trait CtrMode: Mode {}
+
+/// Implements [`Mode`] for the cipher types, which keep their IV in an `iv` field
+#[cfg(feature = "cipher")]
+macro_rules! modes {
+    ($($Cipher:ident: $Mode:ident, $BlockSize:ident;)*) => {
+        $(
+            impl crate::Sealed for $Cipher<'_> {}
+
+            impl crate::crypto::Mode for $Cipher<'_> {
+                type BlockSize = cipher::consts::$BlockSize;
+
+                fn iv_mut(&mut self) -> &mut [u8; 16] {
+                    &mut self.iv
+                }
+            }
+
+            impl crate::crypto::$Mode for $Cipher<'_> {}
+        )*
+    };
+}
+#[cfg(feature = "cipher")]
+pub(crate) use modes;
+
+/// Number of blocks processed in one session
+#[cfg(feature = "cipher")]
+const PAR_BLOCKS: usize = 8;
+
+/// Runs the blocks given by the `cipher` traits through the processor
+#[cfg(feature = "cipher")]
+struct Backend<'a, P, C> {
+    processor: &'a mut P,
+    cipher: C,
+    decrypt: bool,
+    /// The last ciphertext block is the IV of the next call
+    chain: bool,
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: Mode> Backend<'_, P, C> {
+    /// Processes whole blocks in one session
+    fn process(&mut self, buf: &mut [u8]) {
+        let n = C::BlockSize::USIZE;
+        let mut iv = [0; 16];
+        if self.decrypt {
+            iv[..n].copy_from_slice(&buf[buf.len() - n..]);
+        }
+        self.processor.run(&self.cipher, self.decrypt, buf);
+        if !self.decrypt {
+            iv[..n].copy_from_slice(&buf[buf.len() - n..]);
+        }
+        if self.chain {
+            self.cipher.iv_mut()[..n].copy_from_slice(&iv[..n]);
+        }
+    }
+
+    fn proc_blocks(&mut self, mut blocks: InOutBuf<'_, '_, Block<Self>>) {
+        if blocks.is_empty() {
+            return;
+        }
+        let n = C::BlockSize::USIZE;
+        let mut buf = [0; PAR_BLOCKS * 16];
+        let buf = &mut buf[..blocks.len() * n];
+        for (chunk, block) in buf.chunks_exact_mut(n).zip(blocks.get_in()) {
+            chunk.copy_from_slice(block);
+        }
+        self.process(buf);
+        for (block, chunk) in blocks.get_out().iter_mut().zip(buf.chunks_exact(n)) {
+            block.copy_from_slice(chunk);
+        }
+    }
+}
+
+#[cfg(feature = "cipher")]
+impl<P, C: Mode> BlockSizeUser for Backend<'_, P, C> {
+    type BlockSize = C::BlockSize;
+}
+
+#[cfg(feature = "cipher")]
+impl<P, C: Mode> ParBlocksSizeUser for Backend<'_, P, C> {
+    type ParBlocksSize = U8;
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: Mode> BlockBackend for Backend<'_, P, C> {
+    fn proc_block(&mut self, mut block: InOut<'_, '_, Block<Self>>) {
+        let mut buf = block.clone_in();
+        self.process(&mut buf);
+        *block.get_out() = buf;
+    }
+
+    fn proc_par_blocks(&mut self, blocks: InOut<'_, '_, ParBlocks<Self>>) {
+        self.proc_blocks(blocks.into_buf());
+    }
+
+    fn proc_tail_blocks(&mut self, blocks: InOutBuf<'_, '_, Block<Self>>) {
+        self.proc_blocks(blocks);
+    }
+}
+
+/// Electronic codebook mode, implements `BlockEncrypt` and `BlockDecrypt`
+#[cfg(feature = "cipher")]
+pub struct Ecb<P, C> {
+    processor: RefCell<P>,
+    cipher: C,
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: EcbMode> Ecb<P, C> {
+    pub fn new(processor: P, cipher: C) -> Self {
+        Self {
+            processor: RefCell::new(processor),
+            cipher,
+        }
+    }
+
+    /// Returns the processor and the cipher
+    pub fn release(self) -> (P, C) {
+        (self.processor.into_inner(), self.cipher)
+    }
+
+    fn with_backend(&self, decrypt: bool, f: impl BlockClosure<BlockSize = C::BlockSize>) {
+        f.call(&mut Backend {
+            processor: &mut *self.processor.borrow_mut(),
+            cipher: self.cipher,
+            decrypt,
+            chain: false,
+        });
+    }
+}
+
+#[cfg(feature = "cipher")]
+impl<P, C: Mode> BlockSizeUser for Ecb<P, C> {
+    type BlockSize = C::BlockSize;
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: EcbMode> BlockEncrypt for Ecb<P, C> {
+    fn encrypt_with_backend(&self, f: impl BlockClosure<BlockSize = C::BlockSize>) {
+        self.with_backend(false, f);
+    }
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: EcbMode> BlockDecrypt for Ecb<P, C> {
+    fn decrypt_with_backend(&self, f: impl BlockClosure<BlockSize = C::BlockSize>) {
+        self.with_backend(true, f);
+    }
+}
+
+/// Cipher block chaining mode, implements `BlockEncryptMut` and `BlockDecryptMut`
+///
+/// The IV of the cipher is the one of the first call, an adapter encrypts or decrypts
+/// one message.
+#[cfg(feature = "cipher")]
+pub struct Cbc<P, C> {
+    processor: P,
+    cipher: C,
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: CbcMode> Cbc<P, C> {
+    pub fn new(processor: P, cipher: C) -> Self {
+        Self { processor, cipher }
+    }
+
+    /// Returns the processor and the cipher, with the IV to continue the message
+    pub fn release(self) -> (P, C) {
+        (self.processor, self.cipher)
+    }
+
+    fn with_backend(&mut self, decrypt: bool, f: impl BlockClosure<BlockSize = C::BlockSize>) {
+        let mut backend = Backend {
+            processor: &mut self.processor,
+            cipher: self.cipher,
+            decrypt,
+            chain: true,
+        };
+        f.call(&mut backend);
+        self.cipher = backend.cipher;
+    }
+}
+
+#[cfg(feature = "cipher")]
+impl<P, C: Mode> BlockSizeUser for Cbc<P, C> {
+    type BlockSize = C::BlockSize;
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: CbcMode> BlockEncryptMut for Cbc<P, C> {
+    fn encrypt_with_backend_mut(&mut self, f: impl BlockClosure<BlockSize = C::BlockSize>) {
+        self.with_backend(false, f);
+    }
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: CbcMode> BlockDecryptMut for Cbc<P, C> {
+    fn decrypt_with_backend_mut(&mut self, f: impl BlockClosure<BlockSize = C::BlockSize>) {
+        self.with_backend(true, f);
+    }
+}
+
+/// Counter mode, implements `StreamCipher`
+///
+/// The keystream is generated by the processor up to 8 blocks ahead, the counter is the
+/// last 32 bits of the IV like in the processor.
+#[cfg(feature = "cipher")]
+pub struct Ctr<P, C> {
+    processor: P,
+    cipher: C,
+    keystream: [u8; PAR_BLOCKS * 16],
+    pos: usize,
+    len: usize,
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: CtrMode> Ctr<P, C> {
+    pub fn new(processor: P, cipher: C) -> Self {
+        Self {
+            processor,
+            cipher,
+            keystream: [0; PAR_BLOCKS * 16],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the processor and the cipher, with the counter of the next keystream block
+    /// that has not been generated
+    pub fn release(self) -> (P, C) {
+        (self.processor, self.cipher)
+    }
+
+    /// Generates the keystream for at least `needed` bytes, up to 8 blocks
+    fn refill(&mut self, needed: usize) {
+        let blocks = ((needed + 15) / 16).min(PAR_BLOCKS);
+        self.pos = 0;
+        self.len = blocks * 16;
+        // The encrypted zeros are the keystream
+        let keystream = &mut self.keystream[..self.len];
+        keystream.fill(0);
+        self.processor.run(&self.cipher, false, keystream);
+        let iv = self.cipher.iv_mut();
+        let counter = u32::from_be_bytes(iv[12..].try_into().unwrap());
+        iv[12..].copy_from_slice(&counter.wrapping_add(blocks as u32).to_be_bytes());
+    }
+}
+
+#[cfg(feature = "cipher")]
+impl<P: Processor<C>, C: CtrMode> StreamCipher for Ctr<P, C> {
+    fn try_apply_keystream_inout(
+        &mut self,
+        mut buf: InOutBuf<'_, '_, u8>,
+    ) -> Result<(), StreamCipherError> {
+        while !buf.is_empty() {
+            if self.pos == self.len {
+                self.refill(buf.len());
+            }
+            let n = buf.len().min(self.len - self.pos);
+            let (mut head, tail) = buf.split_at(n);
+            head.xor_in2out(&self.keystream[self.pos..self.pos + n]);
+            self.pos += n;
+            buf = tail;
+        }
+        Ok(())
+    }
+}
+
+/// A processor that runs AES-GCM and AES-CCM
+#[cfg(feature = "aead")]
+pub trait AeadProcessor: crate::Sealed {
+    /// Panics if the processor does not support the key length
+    #[doc(hidden)]
+    fn check_key(key: &[u8]);
+
+    /// Runs AES-GCM over `aad` and `buf` in place in a new session, returns the tag
+    #[doc(hidden)]
+    fn gcm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16];
+
+    /// Runs AES-CCM over `aad` and `buf` in place in a new session, returns the tag
+    #[doc(hidden)]
+    fn ccm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        tag_len: usize,
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> [u8; 16];
+}
+
+/// Compares the tags in constant time, and clears the plaintext if they differ
+#[cfg(feature = "aead")]
+fn check_tag(expected: &[u8], tag: &[u8], buffer: &mut [u8]) -> aead::Result<()> {
+    let diff = expected
+        .iter()
+        .zip(tag)
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    if diff == 0 {
+        Ok(())
+    } else {
+        buffer.fill(0);
+        Err(aead::Error)
+    }
+}
+
+/// AES in Galois/counter mode with a 96-bit nonce and a 128-bit tag, implements
+/// `AeadInPlace`
+#[cfg(feature = "aead")]
+pub struct Gcm<'k, P> {
+    processor: RefCell<P>,
+    key: &'k [u8],
+}
+
+#[cfg(feature = "aead")]
+impl<'k, P: AeadProcessor> Gcm<'k, P> {
+    /// # Panics
+    ///
+    /// If the processor does not support the key length.
+    pub fn new(processor: P, key: &'k [u8]) -> Self {
+        P::check_key(key);
+        Self {
+            processor: RefCell::new(processor),
+            key,
+        }
+    }
+
+    /// Returns the processor
+    pub fn release(self) -> P {
+        self.processor.into_inner()
+    }
+
+    fn run(&self, nonce: &Nonce<Self>, decrypt: bool, aad: &[u8], buf: &mut [u8]) -> [u8; 16] {
+        let nonce = nonce.as_slice().try_into().unwrap();
+        self.processor
+            .borrow_mut()
+            .gcm(self.key, nonce, decrypt, aad, buf)
+    }
+}
+
+#[cfg(feature = "aead")]
+impl<P> AeadCore for Gcm<'_, P> {
+    type NonceSize = U12;
+    type TagSize = U16;
+    type CiphertextOverhead = U0;
+}
+
+#[cfg(feature = "aead")]
+impl<P: AeadProcessor> AeadInPlace for Gcm<'_, P> {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<Tag<Self>> {
+        let tag = self.run(nonce, false, associated_data, buffer);
+        Ok(tag.into())
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag<Self>,
+    ) -> aead::Result<()> {
+        let expected = self.run(nonce, true, associated_data, buffer);
+        check_tag(&expected, tag, buffer)
+    }
+}
+
+/// AES in counter with CBC-MAC mode with an `M` bytes tag and an `N` bytes nonce,
+/// implements `AeadInPlace`
+#[cfg(feature = "aead")]
+pub struct Ccm<'k, P, M, N> {
+    processor: RefCell<P>,
+    key: &'k [u8],
+    _sizes: PhantomData<(M, N)>,
+}
+
+#[cfg(feature = "aead")]
+impl<'k, P, M, N> Ccm<'k, P, M, N>
+where
+    P: AeadProcessor,
+    M: ArrayLength<u8>,
+    N: ArrayLength<u8>,
+{
+    /// # Panics
+    ///
+    /// If the processor does not support the key length, `M` is not even and between 4 and
+    /// 16 or `N` is not between 7 and 13.
+    pub fn new(processor: P, key: &'k [u8]) -> Self {
+        P::check_key(key);
+        assert!((4..=16).contains(&M::USIZE) && M::USIZE % 2 == 0);
+        assert!((7..=13).contains(&N::USIZE));
+        Self {
+            processor: RefCell::new(processor),
+            key,
+            _sizes: PhantomData,
+        }
+    }
+
+    /// Returns the processor
+    pub fn release(self) -> P {
+        self.processor.into_inner()
+    }
+
+    fn run(
+        &self,
+        nonce: &Nonce<Self>,
+        decrypt: bool,
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> aead::Result<[u8; 16]> {
+        // The payload length must fit in the bytes left by the nonce
+        let q = 15 - N::USIZE;
+        if q < 8 && (buf.len() as u64) >> (8 * q) != 0 {
+            return Err(aead::Error);
+        }
+        Ok(self
+            .processor
+            .borrow_mut()
+            .ccm(self.key, nonce, M::USIZE, decrypt, aad, buf))
+    }
+}
+
+#[cfg(feature = "aead")]
+impl<P, M: ArrayLength<u8>, N: ArrayLength<u8>> AeadCore for Ccm<'_, P, M, N> {
+    type NonceSize = N;
+    type TagSize = M;
+    type CiphertextOverhead = U0;
+}
+
+#[cfg(feature = "aead")]
+impl<P, M, N> AeadInPlace for Ccm<'_, P, M, N>
+where
+    P: AeadProcessor,
+    M: ArrayLength<u8>,
+    N: ArrayLength<u8>,
+{
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<Tag<Self>> {
+        let tag = self.run(nonce, false, associated_data, buffer)?;
+        Ok(GenericArray::clone_from_slice(&tag[..M::USIZE]))
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag<Self>,
+    ) -> aead::Result<()> {
+        let expected = self.run(nonce, true, associated_data, buffer)?;
+        check_tag(&expected[..M::USIZE], tag, buffer)
+    }
+}
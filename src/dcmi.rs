@@ -0,0 +1,486 @@
+//! Digital camera interface (DCMI)
+//!
+//! Captures 8, 10, 12 or 14-bit parallel data from a camera sensor, synchronised by the
+//! HSYNC and VSYNC signals or by codes embedded in the data stream. Frames are captured
+//! once ([`CaptureMode::Snapshot`]) or continuously, optionally cropped to a window, and
+//! JPEG data can be received with variable line lengths.
+//!
+//! Captured data is read from a 32-bit register with DMA2 stream 1 or 7, channel 1. [`Dcmi`]
+//! is the peripheral of a [`Transfer`](crate::dma::Transfer), frames bigger than one DMA
+//! transfer of 65535 words go into two buffers which are swapped with
+//! [`next_transfer`](crate::dma::Transfer::next_transfer) at every transfer complete
+//! interrupt:
+//!
+//! ```ignore
+//! let dcmi = Dcmi::new(
+//!     dp.DCMI,
+//!     (gpioa.pa6, gpioa.pa4, gpiob.pb7),
+//!     (gpioc.pc6, gpioc.pc7, gpioc.pc8, gpioc.pc9, gpioe.pe4, gpiob.pb6, gpioe.pe5, gpioe.pe6),
+//!     Config::default().mode(CaptureMode::Snapshot),
+//!     &mut rcc,
+//! );
+//!
+//! let streams = StreamsTuple::new(dp.DMA2, &mut rcc);
+//! let config = DmaConfig::default()
+//!     .memory_increment(true)
+//!     .double_buffer(true)
+//!     .transfer_complete_interrupt(true)
+//!     .fifo_enable(true)
+//!     .fifo_threshold(FifoThreshold::Full)
+//!     .memory_burst(BurstMode::Burst4);
+//! let mut transfer =
+//!     Transfer::init_peripheral_to_memory(streams.1, dcmi, first_half, Some(second_half), config);
+//! transfer.start(|dcmi| dcmi.capture());
+//! ```
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::PeripheralToMemory;
+use crate::gpio::alt::dcmi as alt;
+use crate::pac::DCMI;
+use crate::rcc::{Enable, Rcc, Reset};
+use enumflags2::BitFlags;
+
+/// `CAPTURE` bit of `CR`, missing from the F446 PAC
+const CAPTURE: u32 = 1 << 0;
+
+/// Width of the parallel data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataWidth {
+    Bits8 = 0,
+    Bits10 = 1,
+    Bits12 = 2,
+    Bits14 = 3,
+}
+
+/// Data pins `D0`, `D1`, ... of the parallel interface
+pub trait DataPins {
+    const WIDTH: DataWidth;
+
+    type Pins;
+    fn convert(self) -> Self::Pins;
+}
+
+macro_rules! data_pins {
+    ($($width:ident, $Data:ident: ($($D:ident: $i:tt),+);)+) => {
+        $(
+            #[doc = concat!("Converted data pins of a [`DataWidth::", stringify!($width), "`] interface")]
+            pub type $Data = ($(alt::$D),+);
+
+            impl<$($D),+> DataPins for ($($D),+)
+            where
+                $($D: Into<alt::$D>),+
+            {
+                const WIDTH: DataWidth = DataWidth::$width;
+
+                type Pins = $Data;
+                fn convert(self) -> Self::Pins {
+                    ($(self.$i.into()),+)
+                }
+            }
+        )+
+    };
+}
+
+data_pins! {
+    Bits8, Data8: (D0: 0, D1: 1, D2: 2, D3: 3, D4: 4, D5: 5, D6: 6, D7: 7);
+    Bits10, Data10: (D0: 0, D1: 1, D2: 2, D3: 3, D4: 4, D5: 5, D6: 6, D7: 7, D8: 8, D9: 9);
+    Bits12, Data12: (D0: 0, D1: 1, D2: 2, D3: 3, D4: 4, D5: 5, D6: 6, D7: 7, D8: 8, D9: 9, D10: 10, D11: 11);
+    Bits14, Data14: (
+        D0: 0, D1: 1, D2: 2, D3: 3, D4: 4, D5: 5, D6: 6, D7: 7, D8: 8, D9: 9, D10: 10, D11: 11,
+        D12: 12, D13: 13
+    );
+}
+
+/// Edge of the pixel clock on which data is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PixelClockPolarity {
+    Falling,
+    Rising,
+}
+
+/// Level of HSYNC or VSYNC while the data is not valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SyncPolarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Frames which are captured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureRate {
+    All = 0,
+    /// Every other frame
+    Alternate = 1,
+    /// One frame out of four
+    OneOfFour = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureMode {
+    /// Frames are captured until [`Dcmi::stop`]
+    Continuous,
+    /// A single frame is captured, then capture stops
+    Snapshot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pixel_clock_polarity: PixelClockPolarity,
+    hsync_polarity: SyncPolarity,
+    vsync_polarity: SyncPolarity,
+    capture_rate: CaptureRate,
+    mode: CaptureMode,
+    jpeg: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pixel_clock_polarity: PixelClockPolarity::Rising,
+            hsync_polarity: SyncPolarity::ActiveLow,
+            vsync_polarity: SyncPolarity::ActiveLow,
+            capture_rate: CaptureRate::All,
+            mode: CaptureMode::Continuous,
+            jpeg: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn pixel_clock_polarity(mut self, polarity: PixelClockPolarity) -> Self {
+        self.pixel_clock_polarity = polarity;
+        self
+    }
+
+    /// Polarity of HSYNC, not used with embedded synchronisation
+    pub fn hsync_polarity(mut self, polarity: SyncPolarity) -> Self {
+        self.hsync_polarity = polarity;
+        self
+    }
+
+    /// Polarity of VSYNC, not used with embedded synchronisation
+    pub fn vsync_polarity(mut self, polarity: SyncPolarity) -> Self {
+        self.vsync_polarity = polarity;
+        self
+    }
+
+    pub fn capture_rate(mut self, capture_rate: CaptureRate) -> Self {
+        self.capture_rate = capture_rate;
+        self
+    }
+
+    pub fn mode(mut self, mode: CaptureMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Receives compressed data, HSYNC is used as data enable and lines may have
+    /// different lengths
+    pub fn jpeg(mut self, jpeg: bool) -> Self {
+        self.jpeg = jpeg;
+        self
+    }
+}
+
+/// Synchronisation codes embedded in the data
+///
+/// Each code is compared with the received data only on the bits which are set in the
+/// matching byte of the mask, so that one code can match several events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EmbeddedSync {
+    codes: [u8; 4],
+    masks: [u8; 4],
+}
+
+impl EmbeddedSync {
+    /// Codes of the frame start, line start, line end and frame end, all bits compared.
+    ///
+    /// With a frame end code of 0xFF, the frame end is the last line end before the next
+    /// frame start.
+    pub fn new(frame_start: u8, line_start: u8, line_end: u8, frame_end: u8) -> Self {
+        Self {
+            codes: [frame_start, line_start, line_end, frame_end],
+            masks: [0xFF; 4],
+        }
+    }
+
+    /// Sets the bits of the frame start, line start, line end and frame end codes which
+    /// are compared
+    pub fn masks(mut self, frame_start: u8, line_start: u8, line_end: u8, frame_end: u8) -> Self {
+        self.masks = [frame_start, line_start, line_end, frame_end];
+        self
+    }
+}
+
+/// Window of the frame which is captured
+///
+/// Horizontal values count pixel clocks, 2 per pixel for 8-bit RGB565 or YUV422 data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Crop {
+    /// Pixel clocks skipped at the start of each line
+    pub x: u16,
+    /// Lines skipped at the start of the frame
+    pub y: u16,
+    /// Pixel clocks captured in each line, 1 to 16384
+    pub width: u16,
+    /// Lines captured, 1 to 16384
+    pub height: u16,
+}
+
+/// DCMI interrupt events and status flags
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    /// A frame or crop window was captured
+    FrameComplete = 1 << 0,
+    /// Data was received while the FIFO was full, the DMA stream must be restarted
+    Overrun = 1 << 1,
+    /// Embedded synchronisation codes were received in an unexpected order
+    SyncError = 1 << 2,
+    /// VSYNC changed from active to inactive, a frame starts
+    Vsync = 1 << 3,
+    /// A line was received
+    Line = 1 << 4,
+}
+
+/// Pixel clock, HSYNC and VSYNC pins of an interface with hardware synchronisation
+pub type HardwareSync = (alt::Pixclk, alt::Hsync, alt::Vsync);
+
+/// Digital camera interface
+///
+/// `DATA` are the converted data pins, one of [`Data8`], [`Data10`], [`Data12`] or
+/// [`Data14`]. `SYNC` are the [`HardwareSync`] pins, or the pixel clock alone with
+/// synchronisation codes.
+pub struct Dcmi<DATA, SYNC = HardwareSync> {
+    dcmi: DCMI,
+    pins: (SYNC, DATA),
+}
+
+impl<DATA> Dcmi<DATA> {
+    /// Configures the interface for hardware synchronisation with the
+    /// `(pixclk, hsync, vsync)` pins
+    pub fn new<D: DataPins<Pins = DATA>>(
+        dcmi: DCMI,
+        pins: (
+            impl Into<alt::Pixclk>,
+            impl Into<alt::Hsync>,
+            impl Into<alt::Vsync>,
+        ),
+        data: D,
+        config: Config,
+        rcc: &mut Rcc,
+    ) -> Self {
+        let pins = (pins.0.into(), pins.1.into(), pins.2.into());
+        Self::init(&dcmi, D::WIDTH, None, config, rcc);
+        Self {
+            dcmi,
+            pins: (pins, data.convert()),
+        }
+    }
+}
+
+impl<DATA> Dcmi<DATA, alt::Pixclk> {
+    /// Configures the interface for synchronisation codes embedded in the data
+    ///
+    /// # Panics
+    ///
+    /// If `data` is not 8 bits wide, codes are only supported with 8-bit data.
+    pub fn new_embedded_sync<D: DataPins<Pins = DATA>>(
+        dcmi: DCMI,
+        pixclk: impl Into<alt::Pixclk>,
+        data: D,
+        sync: EmbeddedSync,
+        config: Config,
+        rcc: &mut Rcc,
+    ) -> Self {
+        assert_eq!(D::WIDTH, DataWidth::Bits8);
+        let pixclk = pixclk.into();
+        Self::init(&dcmi, D::WIDTH, Some(sync), config, rcc);
+        Self {
+            dcmi,
+            pins: (pixclk, data.convert()),
+        }
+    }
+}
+
+impl<DATA, SYNC> Dcmi<DATA, SYNC> {
+    fn init(
+        dcmi: &DCMI,
+        width: DataWidth,
+        sync: Option<EmbeddedSync>,
+        config: Config,
+        rcc: &mut Rcc,
+    ) {
+        DCMI::enable(rcc);
+        DCMI::reset(rcc);
+
+        if let Some(sync) = sync {
+            let [fs, ls, le, fe] = sync.codes;
+            dcmi.escr().write(|w| unsafe {
+                w.fsc().bits(fs);
+                w.lsc().bits(ls);
+                w.lec().bits(le);
+                w.fec().bits(fe)
+            });
+            let [fs, ls, le, fe] = sync.masks;
+            dcmi.esur().write(|w| unsafe {
+                w.fsu().bits(fs);
+                w.lsu().bits(ls);
+                w.leu().bits(le);
+                w.feu().bits(fe)
+            });
+        }
+        dcmi.cr().write(|w| {
+            w.edm().set(width as u8);
+            unsafe { w.fcrc().bits(config.capture_rate as u8) };
+            w.vspol()
+                .bit(config.vsync_polarity == SyncPolarity::ActiveHigh);
+            w.hspol()
+                .bit(config.hsync_polarity == SyncPolarity::ActiveHigh);
+            w.pckpol()
+                .bit(config.pixel_clock_polarity == PixelClockPolarity::Rising);
+            w.ess().bit(sync.is_some());
+            w.jpeg().bit(config.jpeg);
+            w.cm().bit(config.mode == CaptureMode::Snapshot)
+        });
+        dcmi.cr().modify(|_, w| w.enable().set_bit());
+    }
+
+    /// Disables the interface and releases the DCMI peripheral and its
+    /// `(sync, data)` pins
+    pub fn release(self) -> (DCMI, (SYNC, DATA)) {
+        self.dcmi.cr().modify(|_, w| w.enable().clear_bit());
+        (self.dcmi, self.pins)
+    }
+
+    /// Starts capturing at the next frame start. The DMA stream must be running.
+    pub fn capture(&mut self) {
+        self.dcmi
+            .cr()
+            .modify(|r, w| unsafe { w.bits(r.bits() | CAPTURE) });
+    }
+
+    /// Stops capturing at the end of the current frame
+    pub fn stop(&mut self) {
+        self.dcmi
+            .cr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !CAPTURE) });
+    }
+
+    /// A capture is requested or in progress. Snapshots end on their own.
+    pub fn is_capturing(&self) -> bool {
+        self.dcmi.cr().read().bits() & CAPTURE != 0
+    }
+
+    /// Selects snapshot or continuous capture, takes effect at the next [`capture`](Self::capture)
+    pub fn set_mode(&mut self, mode: CaptureMode) {
+        self.dcmi
+            .cr()
+            .modify(|_, w| w.cm().bit(mode == CaptureMode::Snapshot));
+    }
+
+    /// Captures only `crop` of the frames, or whole frames with `None`.
+    ///
+    /// Must be called while capture is stopped.
+    ///
+    /// # Panics
+    ///
+    /// If the crop window is empty or larger than 16384 pixel clocks or lines, or if it
+    /// starts after 16383 pixel clocks or 8191 lines.
+    pub fn set_crop(&mut self, crop: Option<Crop>) {
+        if let Some(crop) = crop {
+            assert!((1..=0x4000).contains(&crop.width) && (1..=0x4000).contains(&crop.height));
+            assert!(crop.x < 0x4000 && crop.y < 0x2000);
+            self.dcmi.cwstrt().write(|w| {
+                w.hoffcnt().set(crop.x);
+                w.vst().set(crop.y)
+            });
+            self.dcmi.cwsize().write(|w| {
+                w.capcnt().set(crop.width - 1);
+                w.vline().set(crop.height - 1)
+            });
+        }
+        self.dcmi.cr().modify(|_, w| w.crop().bit(crop.is_some()));
+    }
+
+    /// A frame is being received, VSYNC is inactive
+    pub fn is_frame_active(&self) -> bool {
+        self.dcmi.sr().read().vsync().bit_is_clear()
+    }
+
+    /// Data is waiting in the FIFO
+    pub fn fifo_not_empty(&self) -> bool {
+        self.dcmi.sr().read().fne().bit_is_set()
+    }
+
+    /// Reads a word of captured data from the FIFO, without DMA
+    pub fn read(&mut self) -> Option<u32> {
+        self.fifo_not_empty().then(|| self.dcmi.dr().read().bits())
+    }
+}
+
+impl<DATA, SYNC> crate::Listen for Dcmi<DATA, SYNC> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.dcmi
+            .ier()
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.into().bits()) });
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.dcmi
+            .ier()
+            .write(|w| unsafe { w.bits(event.into().bits()) });
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.dcmi
+            .ier()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.into().bits()) });
+    }
+}
+
+impl<DATA, SYNC> crate::ReadFlags for Dcmi<DATA, SYNC> {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Event> {
+        BitFlags::from_bits_truncate(self.dcmi.ris().read().bits())
+    }
+}
+
+impl<DATA, SYNC> crate::ClearFlags for Dcmi<DATA, SYNC> {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Event>>) {
+        self.dcmi
+            .icr()
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
+
+unsafe impl<DATA, SYNC> PeriAddress for Dcmi<DATA, SYNC> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        self.dcmi.dr().as_ptr() as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<DATA, SYNC, STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, PeripheralToMemory>
+    for Dcmi<DATA, SYNC>
+where
+    DCMI: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+{
+}
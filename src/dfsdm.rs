@@ -0,0 +1,697 @@
+//! Digital filter for sigma-delta modulators (DFSDM)
+//!
+//! Channels receive the serial bit stream of external sigma-delta modulators or PDM
+//! microphones, in SPI format with a clock or Manchester coded. Filters decimate the bit
+//! stream of one channel with a sinc filter and an integrator into 24-bit samples.
+//!
+//! DFSDM1 has 4 channels and 2 filters, DFSDM2 on STM32F413/F423 has 8 channels and
+//! 4 filters. The clock output drives the microphones:
+//!
+//! ```ignore
+//! let (mut dfsdm, (ch0, mut ch1, ..), (mut flt0, ..)) = dp.DFSDM1.split(&mut rcc);
+//! // 48 MHz / 24 = 2 MHz PDM clock
+//! dfsdm.set_clock_output(gpioc.pc2, ClockSource::System, 24);
+//! dfsdm.enable();
+//!
+//! ch1.datin(gpioa.pa4);
+//! ch1.configure(ChannelConfig::default().spi_clock(SpiClock::Ckout));
+//! ch1.enable();
+//!
+//! // 2 MHz / 64 = 31.25 kHz
+//! flt0.configure(FilterConfig::default().order(SincOrder::Sinc4).oversampling(64));
+//! flt0.set_regular_channel(&ch1, true);
+//! flt0.enable();
+//! flt0.start_regular();
+//! let sample = nb::block!(flt0.read_regular())?;
+//! ```
+//!
+//! [`FLT`] is the peripheral of a DMA [`Transfer`](crate::dma::Transfer) of regular
+//! conversions after [`FLT::set_regular_dma`], each word holds a sample in its upper 24
+//! bits, see [`sample`].
+
+use core::{marker::PhantomData, ops::Deref};
+
+use enumflags2::BitFlags;
+
+#[cfg(feature = "dfsdm2")]
+use crate::gpio::alt::DfsdmAdvanced;
+use crate::gpio::alt::{DfsdmBasic, DfsdmGeneral};
+#[cfg(feature = "dfsdm2")]
+use crate::pac::DFSDM2;
+#[cfg(feature = "gpio-f412")]
+use crate::pac::{dfsdm, DFSDM as DFSDM1};
+#[cfg(feature = "gpio-f413")]
+use crate::pac::{dfsdm2 as dfsdm, DFSDM1};
+use crate::rcc::{Enable, Rcc, Reset};
+
+/// DFSDM errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A conversion ended before the previous one was read
+    Overrun,
+}
+
+pub trait Instance:
+    crate::Sealed
+    + crate::Ptr<RB = dfsdm::RegisterBlock>
+    + Deref<Target = Self::RB>
+    + Enable
+    + Reset
+    + DfsdmBasic
+{
+    /// Channel handles, from channel 0
+    type Channels;
+    /// Filter handles, from filter 0
+    type Filters;
+    /// Clock and data input pins of the channels, from channel 0
+    type Pins;
+
+    #[doc(hidden)]
+    fn parts() -> (Self::Channels, Self::Filters);
+    #[doc(hidden)]
+    fn release_pins(channels: Self::Channels) -> Self::Pins;
+}
+
+/// Pins of channel `C`
+pub trait ChannelPins<const C: u8> {
+    /// Clock input
+    type Ckin;
+    /// Data input
+    type Datin;
+}
+
+macro_rules! channel_pins {
+    ($Trait:ident: $($C:literal => $Ckin:ident, $Datin:ident;)+) => {
+        $(
+            impl<DFSDM: $Trait> ChannelPins<$C> for DFSDM {
+                type Ckin = DFSDM::$Ckin;
+                type Datin = DFSDM::$Datin;
+            }
+        )+
+    };
+}
+
+channel_pins! { DfsdmBasic:
+    0 => Ckin0, Datin0;
+    1 => Ckin1, Datin1;
+}
+channel_pins! { DfsdmGeneral:
+    2 => Ckin2, Datin2;
+    3 => Ckin3, Datin3;
+}
+#[cfg(feature = "dfsdm2")]
+channel_pins! { DfsdmAdvanced:
+    4 => Ckin4, Datin4;
+    5 => Ckin5, Datin5;
+    6 => Ckin6, Datin6;
+    7 => Ckin7, Datin7;
+}
+
+macro_rules! dfsdm {
+    ($DFSDM:ty: channels [$($C:tt),+], filters [$($F:literal),+]) => {
+        impl Instance for $DFSDM {
+            type Channels = ($(CH<$DFSDM, $C>),+);
+            type Filters = ($(FLT<$DFSDM, $F>),+);
+            type Pins = ($((
+                Option<<$DFSDM as ChannelPins<$C>>::Ckin>,
+                Option<<$DFSDM as ChannelPins<$C>>::Datin>,
+            )),+);
+
+            fn parts() -> (Self::Channels, Self::Filters) {
+                (($(CH::<$DFSDM, $C>::new()),+), ($(FLT::<$DFSDM, $F>::new()),+))
+            }
+
+            fn release_pins(channels: Self::Channels) -> Self::Pins {
+                ($((channels.$C.ckin, channels.$C.datin)),+)
+            }
+        }
+    };
+}
+
+dfsdm!(DFSDM1: channels [0, 1, 2, 3], filters [0, 1]);
+#[cfg(feature = "dfsdm2")]
+dfsdm!(DFSDM2: channels [0, 1, 2, 3, 4, 5, 6, 7], filters [0, 1, 2, 3]);
+
+pub trait DfsdmExt: Instance + Sized {
+    /// Enables the clock of the DFSDM, resets it and splits it into its channels and
+    /// filters
+    fn split(self, rcc: &mut Rcc) -> (Dfsdm<Self>, Self::Channels, Self::Filters);
+}
+
+impl<DFSDM: Instance> DfsdmExt for DFSDM {
+    fn split(self, rcc: &mut Rcc) -> (Dfsdm<Self>, Self::Channels, Self::Filters) {
+        DFSDM::enable(rcc);
+        DFSDM::reset(rcc);
+        let (channels, filters) = DFSDM::parts();
+        (
+            Dfsdm {
+                dfsdm: self,
+                ckout: None,
+            },
+            channels,
+            filters,
+        )
+    }
+}
+
+/// Source of the clock output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockSource {
+    /// APB2 clock
+    System,
+    /// Audio clock of the SAI and I2S
+    Audio,
+}
+
+/// Settings shared by all channels and filters
+pub struct Dfsdm<DFSDM: Instance> {
+    dfsdm: DFSDM,
+    ckout: Option<DFSDM::Ckout>,
+}
+
+impl<DFSDM: Instance> Dfsdm<DFSDM> {
+    /// Outputs `source` divided by `divider` on `ckout`, to clock the modulators. Returns
+    /// the clock output pin set before.
+    ///
+    /// Must be called while the DFSDM is disabled.
+    ///
+    /// # Panics
+    ///
+    /// If `divider` is not between 2 and 256.
+    pub fn set_clock_output(
+        &mut self,
+        ckout: impl Into<DFSDM::Ckout>,
+        source: ClockSource,
+        divider: u16,
+    ) -> Option<DFSDM::Ckout> {
+        assert!((2..=256).contains(&divider));
+        let ckout = self.ckout.replace(ckout.into());
+        self.dfsdm.ch(0).cfgr1().modify(|_, w| {
+            w.ckoutsrc().bit(source == ClockSource::Audio);
+            w.ckoutdiv().set((divider - 1) as u8)
+        });
+        ckout
+    }
+
+    /// Enables the channels and filters which are enabled, and the clock output
+    pub fn enable(&mut self) {
+        self.dfsdm
+            .ch(0)
+            .cfgr1()
+            .modify(|_, w| w.dfsdmen().set_bit());
+    }
+
+    pub fn disable(&mut self) {
+        self.dfsdm
+            .ch(0)
+            .cfgr1()
+            .modify(|_, w| w.dfsdmen().clear_bit());
+    }
+
+    /// Disables the DFSDM and releases it with the clock output pin and the pins of the
+    /// channels, given the channel and filter handles
+    pub fn release(
+        mut self,
+        channels: DFSDM::Channels,
+        _filters: DFSDM::Filters,
+    ) -> (DFSDM, (Option<DFSDM::Ckout>, DFSDM::Pins)) {
+        self.disable();
+        (self.dfsdm, (self.ckout, DFSDM::release_pins(channels)))
+    }
+}
+
+/// Format of the serial input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SerialInterface {
+    /// Data sampled on rising clock edges
+    SpiRising = 0,
+    /// Data sampled on falling clock edges
+    SpiFalling = 1,
+    /// Rising edge is 0, falling edge is 1
+    Manchester = 2,
+    /// Rising edge is 1, falling edge is 0
+    ManchesterInverted = 3,
+}
+
+/// Clock of SPI inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiClock {
+    /// External clock from the `CKIN` pin of the channel
+    Ckin = 0,
+    /// Internal clock output
+    Ckout = 1,
+    /// Internal clock output divided by 2, sampling on its falling edges
+    CkoutHalfFalling = 2,
+    /// Internal clock output divided by 2, sampling on its rising edges
+    CkoutHalfRising = 3,
+}
+
+/// Pin from which a channel receives data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InputPin {
+    /// `DATIN` and `CKIN` of the channel
+    Own,
+    /// `DATIN` and `CKIN` of the following channel, to receive two microphones on one pin
+    Following,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelConfig {
+    interface: SerialInterface,
+    spi_clock: SpiClock,
+    input: InputPin,
+    clock_absence_detection: bool,
+    offset: i32,
+    right_shift: u8,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            interface: SerialInterface::SpiRising,
+            spi_clock: SpiClock::Ckin,
+            input: InputPin::Own,
+            clock_absence_detection: false,
+            offset: 0,
+            right_shift: 0,
+        }
+    }
+}
+
+impl ChannelConfig {
+    pub fn interface(mut self, interface: SerialInterface) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    pub fn spi_clock(mut self, spi_clock: SpiClock) -> Self {
+        self.spi_clock = spi_clock;
+        self
+    }
+
+    pub fn input(mut self, input: InputPin) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Detects a missing SPI clock, reported by filter 0
+    pub fn clock_absence_detection(mut self, enable: bool) -> Self {
+        self.clock_absence_detection = enable;
+        self
+    }
+
+    /// 24-bit offset subtracted from the filter output
+    pub fn offset(mut self, offset: i32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Bits the filter output is shifted right, 0 to 31
+    pub fn right_shift(mut self, right_shift: u8) -> Self {
+        self.right_shift = right_shift;
+        self
+    }
+}
+
+/// Channel `C` of a DFSDM
+pub struct CH<DFSDM: ChannelPins<C>, const C: u8> {
+    ckin: Option<DFSDM::Ckin>,
+    datin: Option<DFSDM::Datin>,
+}
+
+impl<DFSDM: Instance + ChannelPins<C>, const C: u8> CH<DFSDM, C> {
+    fn new() -> Self {
+        Self {
+            ckin: None,
+            datin: None,
+        }
+    }
+
+    fn ch(&self) -> &dfsdm::CH {
+        // NOTE(unsafe) only this handle accesses the registers of channel `C`
+        unsafe { (*DFSDM::ptr()).ch(C as usize) }
+    }
+
+    /// Connects the clock input pin of the channel, returns the pin connected before
+    pub fn ckin(&mut self, pin: impl Into<DFSDM::Ckin>) -> Option<DFSDM::Ckin> {
+        self.ckin.replace(pin.into())
+    }
+
+    /// Connects the data input pin of the channel, returns the pin connected before
+    pub fn datin(&mut self, pin: impl Into<DFSDM::Datin>) -> Option<DFSDM::Datin> {
+        self.datin.replace(pin.into())
+    }
+
+    /// Must be called while the channel is disabled
+    ///
+    /// # Panics
+    ///
+    /// If the offset does not fit in 24 bits or the right shift is more than 31.
+    pub fn configure(&mut self, config: ChannelConfig) {
+        assert!((-0x80_0000..0x80_0000).contains(&config.offset));
+        assert!(config.right_shift < 32);
+        self.ch().cfgr1().modify(|_, w| {
+            w.sitp().set(config.interface as u8);
+            w.spicksel().set(config.spi_clock as u8);
+            w.chinsel().bit(config.input == InputPin::Following);
+            w.ckaben().bit(config.clock_absence_detection)
+        });
+        self.ch().cfgr2().write(|w| {
+            w.offset().set(config.offset as u32 & 0xFF_FFFF);
+            w.dtrbs().set(config.right_shift)
+        });
+    }
+
+    pub fn enable(&mut self) {
+        self.ch().cfgr1().modify(|_, w| w.chen().set_bit());
+    }
+
+    pub fn disable(&mut self) {
+        self.ch().cfgr1().modify(|_, w| w.chen().clear_bit());
+    }
+}
+
+/// Order of the sinc filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SincOrder {
+    FastSinc = 0,
+    Sinc1 = 1,
+    Sinc2 = 2,
+    Sinc3 = 3,
+    Sinc4 = 4,
+    Sinc5 = 5,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterConfig {
+    order: SincOrder,
+    oversampling: u16,
+    integrator: u16,
+    fast: bool,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            order: SincOrder::Sinc3,
+            oversampling: 64,
+            integrator: 1,
+            fast: false,
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn order(mut self, order: SincOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Decimation of the sinc filter, 1 to 1024
+    pub fn oversampling(mut self, oversampling: u16) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    /// Samples of the sinc filter summed by the integrator, 1 to 256
+    pub fn integrator(mut self, integrator: u16) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Faster continuous conversions of a single channel, without the filter settling
+    /// time of each conversion
+    pub fn fast(mut self, fast: bool) -> Self {
+        self.fast = fast;
+        self
+    }
+}
+
+/// Edge of the external trigger of injected conversions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    Rising = 1,
+    Falling = 2,
+    Both = 3,
+}
+
+/// Start of injected conversions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InjectedTrigger {
+    /// [`FLT::start_injected`]
+    Software,
+    /// Started with filter 0, all filters synchronised this way convert together
+    Synchronous,
+    /// External trigger `source` (`JEXTSEL`, 0 to 7)
+    External { source: u8, edge: TriggerEdge },
+}
+
+/// Filter interrupt events
+///
+/// Clock absence and short circuit interrupts are only enabled by filter 0.
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    InjectedEnd = 1 << 0,
+    RegularEnd = 1 << 1,
+    InjectedOverrun = 1 << 2,
+    RegularOverrun = 1 << 3,
+    AnalogWatchdog = 1 << 4,
+    ShortCircuit = 1 << 5,
+    ClockAbsence = 1 << 6,
+}
+
+/// Filter status flags
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Flag {
+    /// An injected conversion ended, cleared by reading its data
+    InjectedEnd = 1 << 0,
+    /// A regular conversion ended, cleared by reading its data
+    RegularEnd = 1 << 1,
+    InjectedOverrun = 1 << 2,
+    RegularOverrun = 1 << 3,
+    AnalogWatchdog = 1 << 4,
+    InjectedInProgress = 1 << 13,
+    RegularInProgress = 1 << 14,
+}
+
+/// Manually clearable filter flags
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum CFlag {
+    InjectedOverrun = 1 << 2,
+    RegularOverrun = 1 << 3,
+}
+
+/// Extracts the sample of a regular or injected data word
+pub const fn sample(word: u32) -> i32 {
+    word as i32 >> 8
+}
+
+/// Filter `F` of a DFSDM
+pub struct FLT<DFSDM, const F: u8> {
+    _dfsdm: PhantomData<DFSDM>,
+}
+
+impl<DFSDM, const F: u8> crate::Sealed for FLT<DFSDM, F> {}
+
+impl<DFSDM: Instance, const F: u8> FLT<DFSDM, F> {
+    fn new() -> Self {
+        Self {
+            _dfsdm: PhantomData,
+        }
+    }
+
+    fn flt(&self) -> &dfsdm::FLT {
+        // NOTE(unsafe) only this handle accesses the registers of filter `F`
+        unsafe { (*DFSDM::ptr()).flt(F as usize) }
+    }
+
+    /// Must be called while the filter is disabled
+    ///
+    /// # Panics
+    ///
+    /// If the oversampling ratio is not between 1 and 1024 or the integrator length is
+    /// not between 1 and 256.
+    pub fn configure(&mut self, config: FilterConfig) {
+        assert!((1..=1024).contains(&config.oversampling));
+        assert!((1..=256).contains(&config.integrator));
+        self.flt().fcr().write(|w| {
+            unsafe { w.ford().bits(config.order as u8) };
+            w.fosr().set(config.oversampling - 1);
+            w.iosr().set((config.integrator - 1) as u8)
+        });
+        self.flt().cr1().modify(|_, w| w.fast().bit(config.fast));
+    }
+
+    pub fn enable(&mut self) {
+        self.flt().cr1().modify(|_, w| w.dfen().set_bit());
+    }
+
+    /// Stops conversions and disables the filter
+    pub fn disable(&mut self) {
+        self.flt().cr1().modify(|_, w| w.dfen().clear_bit());
+    }
+
+    /// Selects the channel of regular conversions, which run once per
+    /// [`start_regular`](Self::start_regular) or continuously
+    pub fn set_regular_channel<const C: u8>(&mut self, _channel: &CH<DFSDM, C>, continuous: bool)
+    where
+        DFSDM: ChannelPins<C>,
+    {
+        self.flt().cr1().modify(|_, w| {
+            w.rch().set(C);
+            w.rcont().bit(continuous)
+        });
+    }
+
+    /// Starts regular conversions together with filter 0.
+    ///
+    /// Must be called while the filter is disabled.
+    pub fn set_regular_sync(&mut self, sync: bool) {
+        self.flt().cr1().modify(|_, w| w.rsync().bit(sync));
+    }
+
+    /// Moves regular data with DMA, must be called while the filter is disabled
+    pub fn set_regular_dma(&mut self, dma: bool) {
+        self.flt().cr1().modify(|_, w| w.rdmaen().bit(dma));
+    }
+
+    pub fn start_regular(&mut self) {
+        self.flt().cr1().modify(|_, w| w.rswstart().set_bit());
+    }
+
+    /// Stops continuous regular conversions
+    pub fn stop_regular(&mut self) {
+        self.flt().cr1().modify(|_, w| w.rcont().clear_bit());
+    }
+
+    /// Reads the sample of the last regular conversion
+    pub fn read_regular(&mut self) -> nb::Result<i32, Error> {
+        let isr = self.flt().isr().read();
+        if isr.rovrf().bit_is_set() {
+            self.flt().icr().write(|w| w.clrrovrf().set_bit());
+            Err(nb::Error::Other(Error::Overrun))
+        } else if isr.reocf().bit_is_set() {
+            Ok(sample(self.flt().rdatar().read().bits()))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Selects the channels of injected conversions, one bit per channel, converted one
+    /// per trigger or all in a scan.
+    ///
+    /// Must be called while the filter is disabled.
+    ///
+    /// # Panics
+    ///
+    /// If no channel is selected or an external trigger source is more than 7.
+    pub fn set_injected(&mut self, channels: u8, scan: bool, trigger: InjectedTrigger) {
+        assert_ne!(channels, 0);
+        self.flt().jchgr().write(|w| w.jchg().set(channels));
+        self.flt().cr1().modify(|_, w| {
+            w.jscan().bit(scan);
+            w.jsync().bit(trigger == InjectedTrigger::Synchronous);
+            match trigger {
+                InjectedTrigger::External { source, edge } => {
+                    assert!(source < 8);
+                    unsafe { w.jextsel().bits(source) };
+                    w.jexten().set(edge as u8)
+                }
+                _ => w.jexten().set(0),
+            }
+        });
+    }
+
+    /// Moves injected data with DMA, must be called while the filter is disabled
+    pub fn set_injected_dma(&mut self, dma: bool) {
+        self.flt().cr1().modify(|_, w| w.jdmaen().bit(dma));
+    }
+
+    pub fn start_injected(&mut self) {
+        self.flt().cr1().modify(|_, w| w.jswstart().set_bit());
+    }
+
+    /// Reads the channel and the sample of the last injected conversion
+    pub fn read_injected(&mut self) -> nb::Result<(u8, i32), Error> {
+        let isr = self.flt().isr().read();
+        if isr.jovrf().bit_is_set() {
+            self.flt().icr().write(|w| w.clrjovrf().set_bit());
+            Err(nb::Error::Other(Error::Overrun))
+        } else if isr.jeocf().bit_is_set() {
+            let data = self.flt().jdatar().read();
+            Ok((data.jdatach().bits(), sample(data.bits())))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<DFSDM: Instance, const F: u8> crate::Listen for FLT<DFSDM, F> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.flt()
+            .cr2()
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.into().bits()) });
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Event>>) {
+        let all = BitFlags::<Event>::ALL.bits();
+        self.flt()
+            .cr2()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !all | event.into().bits()) });
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.flt()
+            .cr2()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.into().bits()) });
+    }
+}
+
+impl<DFSDM: Instance, const F: u8> crate::ReadFlags for FLT<DFSDM, F> {
+    type Flag = Flag;
+
+    fn flags(&self) -> BitFlags<Flag> {
+        BitFlags::from_bits_truncate(self.flt().isr().read().bits())
+    }
+}
+
+impl<DFSDM: Instance, const F: u8> crate::ClearFlags for FLT<DFSDM, F> {
+    type Flag = CFlag;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<CFlag>>) {
+        self.flt()
+            .icr()
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
+
+unsafe impl<DFSDM: Instance, const F: u8> crate::dma::traits::PeriAddress for FLT<DFSDM, F> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        self.flt().rdatar().as_ptr() as u32
+    }
+
+    type MemSize = u32;
+}
@@ -0,0 +1,351 @@
+//! Interrupt driven completion of DMA transfers for async drivers.
+//!
+//! Each of the 16 streams of DMA1 and DMA2 has its own waker. Async drivers only work if
+//! [`StreamX::on_interrupt`] is called from the interrupt handler of every stream they use:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn DMA2_STREAM0() {
+//!     dma::Stream0::<pac::DMA2>::on_interrupt();
+//! }
+//! ```
+//!
+//! Don't forget to unmask the stream interrupts in the NVIC.
+//!
+//! [`Transfer::wait_complete`] and [`Transfer::wait_half`] await events of a running transfer.
+
+use core::{
+    future::poll_fn,
+    mem, ptr,
+    sync::atomic::{compiler_fence, Ordering},
+    task::{Context, Poll},
+};
+
+use atomic_waker::AtomicWaker;
+use enumflags2::BitFlags;
+
+use super::{
+    config::Priority,
+    stream_disable,
+    traits::{Channel, DMASet, Direction, Instance, PeriAddress, Stream, StreamISR},
+    ChannelX, DMAError, DmaChannel, DmaDataSize, DmaDirection, DmaEvent, DmaFlag,
+    DmaFlowController, StreamX, Transfer,
+};
+use crate::{pac, Listen, ReadFlags};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_WAKER: AtomicWaker = AtomicWaker::new();
+static WAKERS: [AtomicWaker; 16] = [NEW_WAKER; 16];
+
+impl<I: Instance, const S: u8> StreamX<I, S>
+where
+    Self: crate::Sealed + StreamISR,
+{
+    #[inline(always)]
+    pub(super) fn stream_waker() -> &'static AtomicWaker {
+        let dma = if ptr::eq(I::ptr(), pac::DMA1::ptr()) {
+            0
+        } else {
+            8
+        };
+        &WAKERS[dma + S as usize]
+    }
+
+    /// Wakes the task waiting for this stream.
+    ///
+    /// Must be called from the `DMAx_STREAMy` interrupt handler when the stream is used by an
+    /// async driver. Events that fired are disabled, the woken task enables them again if it
+    /// still has to wait.
+    pub fn on_interrupt() {
+        let mut stream = Self::new();
+        if !stream.flags().is_empty() {
+            stream.unlisten(BitFlags::ALL);
+            stream.unlisten_fifo_error();
+            Self::stream_waker().wake();
+        }
+    }
+}
+
+impl<STREAM, const CHANNEL: u8, PERIPHERAL, DIR, BUF>
+    Transfer<STREAM, CHANNEL, PERIPHERAL, DIR, BUF>
+where
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    DIR: Direction,
+    PERIPHERAL: PeriAddress + DMASet<STREAM, CHANNEL, DIR>,
+{
+    /// Waits until the transfer completes and clears the transfer complete flag.
+    ///
+    /// In circular and double buffer modes each call waits for the next completion.
+    /// [`StreamX::on_interrupt`] must be called from the interrupt handler of the stream.
+    pub async fn wait_complete(&mut self) -> Result<(), DMAError<()>> {
+        self.wait_event(DmaFlag::TransferComplete, DmaEvent::TransferComplete)
+            .await
+    }
+
+    /// Waits until half of the transfer is done and clears the half transfer flag.
+    ///
+    /// [`StreamX::on_interrupt`] must be called from the interrupt handler of the stream.
+    pub async fn wait_half(&mut self) -> Result<(), DMAError<()>> {
+        self.wait_event(DmaFlag::HalfTransfer, DmaEvent::HalfTransfer)
+            .await
+    }
+
+    async fn wait_event(&mut self, flag: DmaFlag, event: DmaEvent) -> Result<(), DMAError<()>> {
+        let stream = &mut self.stream;
+        poll_fn(|cx| {
+            STREAM::waker().register(cx.waker());
+            match poll_flags(stream, flag) {
+                Poll::Pending => {
+                    stream.listen(event | DmaEvent::TransferError | DmaEvent::DirectModeError);
+                    stream.listen_fifo_error();
+                    Poll::Pending
+                }
+                ready => ready,
+            }
+        })
+        .await
+    }
+}
+
+/// Clears and reports the first error or `flag` when it is set
+fn poll_flags<STREAM: Stream>(
+    stream: &mut STREAM,
+    flag: DmaFlag,
+) -> Poll<Result<(), DMAError<()>>> {
+    let flags = stream.flags();
+    let result = if flags.contains(DmaFlag::TransferError) {
+        stream.clear_flags(DmaFlag::TransferError);
+        Err(DMAError::TransferError(()))
+    } else if flags.contains(DmaFlag::DirectModeError) {
+        stream.clear_flags(DmaFlag::DirectModeError);
+        Err(DMAError::DirectModeError(()))
+    } else if flags.contains(DmaFlag::FifoError) {
+        stream.clear_flags(DmaFlag::FifoError);
+        Err(DMAError::FifoError(()))
+    } else if flags.contains(flag) {
+        // "Subsequent reads and writes cannot be moved ahead of preceding reads"
+        compiler_fence(Ordering::Acquire);
+        stream.clear_flags(flag);
+        Ok(())
+    } else {
+        return Poll::Pending;
+    };
+    Poll::Ready(result)
+}
+
+fn data_size<W>() -> DmaDataSize {
+    match mem::size_of::<W>() {
+        1 => DmaDataSize::Byte,
+        2 => DmaDataSize::HalfWord,
+        _ => DmaDataSize::Word,
+    }
+}
+
+/// Stops `stream` and sets everything shared by all [`OneShot`] transfers
+fn configure<STREAM: Stream>(
+    stream: &mut STREAM,
+    channel: DmaChannel,
+    direction: DmaDirection,
+    peri: u32,
+    mem: u32,
+    memory_increment: bool,
+) {
+    stream_disable(stream);
+    stream.clear_all_flags();
+    stream.set_channel(channel);
+    stream.set_direction(direction);
+    stream.set_peripheral_address(peri);
+    stream.set_memory_address(mem);
+    stream.set_priority(Priority::Medium);
+    stream.set_memory_increment(memory_increment);
+    stream.set_peripheral_increment(false);
+    stream.set_circular_mode(false);
+    stream.set_double_buffer(false);
+}
+
+/// Single transfer on a borrowed stream. The stream is stopped when it is dropped.
+pub(crate) struct OneShot<'a, STREAM: Stream> {
+    stream: &'a mut STREAM,
+}
+
+impl<'a, STREAM: Stream> OneShot<'a, STREAM> {
+    /// Configures `stream` to move `len` words of type `W` between the peripheral register at
+    /// `peri` and `mem`, and starts it.
+    ///
+    /// # Safety
+    ///
+    /// `mem` must stay valid for `len` words until the returned value is dropped, `peri` must
+    /// accept `W` sized accesses and the stream and channel must be mapped to that peripheral.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) unsafe fn start<W>(
+        stream: &'a mut STREAM,
+        channel: DmaChannel,
+        direction: DmaDirection,
+        peri: u32,
+        mem: *const W,
+        len: u16,
+        memory_increment: bool,
+    ) -> Self {
+        let size = data_size::<W>();
+        configure(
+            stream,
+            channel,
+            direction,
+            peri,
+            mem as u32,
+            memory_increment,
+        );
+        stream.set_number_of_transfers(len);
+        stream.set_memory_size(size);
+        stream.set_peripheral_size(size);
+        stream.set_flow_controller(DmaFlowController::Dma);
+        stream.set_fifo_enable(false);
+        Self::enable(stream)
+    }
+
+    /// Configures `stream` to move words of type `W` between `mem` and the 32-bit FIFO at
+    /// `peri` until the peripheral ends the transfer, and starts it.
+    ///
+    /// Data goes through the stream FIFO in bursts of 4 words, memory bursts are only used
+    /// for word sized `W`.
+    ///
+    /// # Safety
+    ///
+    /// `mem` must stay valid for every word the peripheral requests until the returned value
+    /// is dropped and the stream and channel must be mapped to that peripheral.
+    #[cfg(all(feature = "sdio-host", feature = "sdio"))]
+    pub(crate) unsafe fn start_peripheral_flow<W>(
+        stream: &'a mut STREAM,
+        channel: DmaChannel,
+        direction: DmaDirection,
+        peri: u32,
+        mem: *const W,
+    ) -> Self {
+        use super::config::{BurstMode, FifoThreshold};
+
+        let size = data_size::<W>();
+        configure(stream, channel, direction, peri, mem as u32, true);
+        stream.set_memory_size(size);
+        stream.set_peripheral_size(DmaDataSize::Word);
+        stream.set_flow_controller(DmaFlowController::Peripheral);
+        stream.set_fifo_enable(true);
+        stream.set_fifo_threshold(FifoThreshold::Full);
+        stream.set_memory_burst(if size == DmaDataSize::Word {
+            BurstMode::Burst4
+        } else {
+            BurstMode::NoBurst
+        });
+        stream.set_peripheral_burst(BurstMode::Burst4);
+        Self::enable(stream)
+    }
+
+    /// Configures `stream` to move `len` words between the 32-bit register at `peri` and
+    /// `mem`, and starts it. The stream FIFO packs the `W` sized memory accesses into words.
+    ///
+    /// # Safety
+    ///
+    /// `mem` must stay valid for `len` words until the returned value is dropped and the
+    /// stream and channel must be mapped to that peripheral.
+    #[cfg(any(feature = "aes", feature = "cryp", feature = "hash"))]
+    pub(crate) unsafe fn start_packed<W>(
+        stream: &'a mut STREAM,
+        channel: DmaChannel,
+        direction: DmaDirection,
+        peri: u32,
+        mem: *const W,
+        len: u16,
+    ) -> Self {
+        use super::config::{BurstMode, FifoThreshold};
+
+        configure(stream, channel, direction, peri, mem as u32, true);
+        stream.set_number_of_transfers(len);
+        stream.set_memory_size(data_size::<W>());
+        stream.set_peripheral_size(DmaDataSize::Word);
+        stream.set_flow_controller(DmaFlowController::Dma);
+        stream.set_fifo_enable(true);
+        stream.set_fifo_threshold(FifoThreshold::HalfFull);
+        stream.set_memory_burst(BurstMode::NoBurst);
+        stream.set_peripheral_burst(BurstMode::NoBurst);
+        Self::enable(stream)
+    }
+
+    unsafe fn enable(stream: &'a mut STREAM) -> Self {
+        stream.unlisten_fifo_error();
+        stream.listen_only(DmaEvent::TransferComplete | DmaEvent::TransferError);
+
+        // "Preceding reads and writes cannot be moved past subsequent writes"
+        compiler_fence(Ordering::Release);
+        stream.enable();
+
+        Self { stream }
+    }
+
+    /// Polls for the end of the transfer, registering the task to be woken by the stream
+    /// interrupt while it is running.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), DMAError<()>>> {
+        STREAM::waker().register(cx.waker());
+        let flags = self.stream.flags();
+        if flags.contains(DmaFlag::TransferError) {
+            Poll::Ready(Err(DMAError::TransferError(())))
+        } else if flags.contains(DmaFlag::TransferComplete) {
+            // "Subsequent reads and writes cannot be moved ahead of preceding reads"
+            compiler_fence(Ordering::Acquire);
+            self.stream.clear_all_flags();
+            Poll::Ready(Ok(()))
+        } else {
+            self.stream
+                .listen(DmaEvent::TransferComplete | DmaEvent::TransferError);
+            Poll::Pending
+        }
+    }
+
+    /// Number of words which are not transferred yet
+    pub(crate) fn remaining(&self) -> u16 {
+        self.stream.number_of_transfers()
+    }
+
+    /// Waits until all words are transferred.
+    pub(crate) async fn wait(&mut self) -> Result<(), DMAError<()>> {
+        poll_fn(|cx| self.poll(cx)).await
+    }
+}
+
+impl<STREAM: Stream> Drop for OneShot<'_, STREAM> {
+    fn drop(&mut self) {
+        stream_disable(self.stream);
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Waits until both transfers moved all their words, or one of them fails.
+///
+/// Used by peripherals that read and write at the same time, where a failed transfer stalls
+/// the other one.
+#[cfg(any(feature = "aes", feature = "cryp"))]
+pub(crate) async fn wait_both<A: Stream, B: Stream>(
+    a: &mut OneShot<'_, A>,
+    b: &mut OneShot<'_, B>,
+) -> Result<(), DMAError<()>> {
+    let (mut a_done, mut b_done) = (false, false);
+    poll_fn(|cx| {
+        if !a_done {
+            if let Poll::Ready(result) = a.poll(cx) {
+                result?;
+                a_done = true;
+            }
+        }
+        if !b_done {
+            if let Poll::Ready(result) = b.poll(cx) {
+                result?;
+                b_done = true;
+            }
+        }
+        if a_done && b_done {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
@@ -21,8 +21,14 @@ use enumflags2::BitFlags;
 use crate::pac::RCC;
 use crate::{pac, rcc};
 
+#[cfg(feature = "async")]
+mod asynch;
 pub mod traits;
 use crate::serial::RxISR;
+#[cfg(all(feature = "async", any(feature = "aes", feature = "cryp")))]
+pub(crate) use asynch::wait_both;
+#[cfg(feature = "async")]
+pub(crate) use asynch::OneShot;
 use traits::{
     sealed::Bits, Channel, DMASet, Direction, DmaEventExt, DmaFlagExt, Instance, PeriAddress,
     SafePeripheralRead, Stream, StreamISR,
@@ -30,6 +36,7 @@ use traits::{
 
 /// Errors.
 #[derive(PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DMAError<T> {
     /// DMA not ready to change buffers.
     NotReady(T),
@@ -37,6 +44,12 @@ pub enum DMAError<T> {
     SmallBuffer(T),
     /// Overrun during a double buffering or circular transfer.
     Overrun(T),
+    /// Bus error while accessing the memory or the peripheral.
+    TransferError(T),
+    /// FIFO overrun or underrun.
+    FifoError(T),
+    /// Direct mode request while the previous data was not transferred.
+    DirectModeError(T),
 }
 
 // Manually implement `Debug`, so we can have debug information even with a buffer `T` that doesn't
@@ -48,6 +61,9 @@ impl<T> Debug for DMAError<T> {
             DMAError::NotReady(_) => f.debug_tuple("NotReady").finish(),
             DMAError::SmallBuffer(_) => f.debug_tuple("SmallBuffer").finish(),
             DMAError::Overrun(_) => f.debug_tuple("Overrun").finish(),
+            DMAError::TransferError(_) => f.debug_tuple("TransferError").finish(),
+            DMAError::FifoError(_) => f.debug_tuple("FifoError").finish(),
+            DMAError::DirectModeError(_) => f.debug_tuple("DirectModeError").finish(),
         }
     }
 }
@@ -666,6 +682,12 @@ where
             CurrentBuffer::FirstBuffer
         }
     }
+
+    #[cfg(feature = "async")]
+    #[inline(always)]
+    fn waker() -> &'static atomic_waker::AtomicWaker {
+        Self::stream_waker()
+    }
 }
 
 impl<I: Instance, const S: u8> StreamX<I, S>
@@ -256,6 +256,11 @@ pub trait Stream: StreamISR + crate::Listen<Event = DmaEvent> + crate::Sealed {
 
     /// Get which buffer is currently in use by the DMA.
     fn current_buffer(&self) -> CurrentBuffer;
+
+    /// Waker of the task waiting for this stream, woken by [`StreamX::on_interrupt`].
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn waker() -> &'static atomic_waker::AtomicWaker;
 }
 
 /// DMA direction.
@@ -336,12 +341,7 @@ use dma_map;
 mod f4;
 
 #[cfg(feature = "dfsdm")]
-pub struct FLT<T, const F: u8> {
-    _per: PhantomData<T>,
-}
-
-#[cfg(feature = "dfsdm")]
-impl<T, const F: u8> crate::Sealed for FLT<T, F> {}
+pub use crate::dfsdm::FLT;
 
 #[cfg(feature = "sai")]
 pub use crate::sai::SAICH;
@@ -386,17 +386,7 @@ mod dfsdm1 {
         (Stream4<DMA2>:3, FLT<DFSDM1, 1>, [PeripheralToMemory]), //DFSDM1_FLT1
         (Stream6<DMA2>:3, FLT<DFSDM1, 0>, [PeripheralToMemory]), //DFSDM1_FLT0:DMA_CHANNEL_3
     );
-    /* TODO: flt clusters
-    unsafe impl<const F: u8> PeriAddress for FLT<DFSDM1, F> {
-        #[inline(always)]
-        fn address(&self) -> u32 {
-            unsafe { (*DFSDM1::ptr()).flt(F as usize).rdatar().as_ptr() as u32 }
-        }
-
-        type MemSize = u32;
-    } */
 }
-/*
 #[cfg(feature = "dfsdm2")]
 dma_map!(
     (Stream0<DMA2>:8, FLT<pac::DFSDM2, 0>, [PeripheralToMemory]), //DFSDM2_FLT0
@@ -408,16 +398,6 @@ dma_map!(
     (Stream6<DMA2>:8, FLT<pac::DFSDM2, 2>, [PeripheralToMemory]), //DFSDM2_FLT2
     (Stream7<DMA2>:8, FLT<pac::DFSDM2, 3>, [PeripheralToMemory]), //DFSDM2_FLT3
 );
-#[cfg(feature = "dfsdm2")]
-unsafe impl<const F: u8> PeriAddress for FLT<pac::DFSDM2, F> {
-    #[inline(always)]
-    fn address(&self) -> u32 {
-        unsafe { (*DFSDM2::ptr()).flt(F as usize).rdatar().as_ptr() as u32 }
-    }
-
-    type MemSize = u32;
-}
-*/
 #[cfg(feature = "quadspi")]
 dma_map!(
     (Stream7<DMA2>:3, pac::QUADSPI, [MemoryToPeripheral | PeripheralToMemory]), //QUADSPI
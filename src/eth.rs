@@ -0,0 +1,231 @@
+//! Ethernet MAC (ETH)
+//!
+//! 10/100 Mbit/s MAC with its own DMA, connected to an external PHY through the MII or
+//! the RMII. [`new`] configures the MAC, the DMA descriptor rings and the PTP clock, and
+//! splits them into:
+//!
+//! - [`EthernetMac`]: speed, duplex and the MAC address filters. The PHY is managed over
+//!   [`Mdio`], see [`EthernetMac::mdio`].
+//! - [`EthernetDma`]: sends and receives frames through the descriptor rings. With the
+//!   `smoltcp` feature it implements `smoltcp::phy::Device`.
+//! - [`EthernetPtp`]: the IEEE 1588 system time, which timestamps received frames and the
+//!   frames sent with [`EthernetDma::send_timestamped`].
+//!
+//! Every ring entry holds a buffer for one frame. The rings must be in memory the DMA can
+//! reach, which excludes the CCM RAM:
+//!
+//! ```ignore
+//! static mut RX_RING: [RxRingEntry; 4] = [RxRingEntry::INIT; 4];
+//! static mut TX_RING: [TxRingEntry; 2] = [TxRingEntry::INIT; 2];
+//!
+//! let mut syscfg = dp.SYSCFG.constrain(&mut rcc);
+//! let eth::Parts { mac, mut dma, ptp } = eth::new(
+//!     (dp.ETHERNET_MAC, dp.ETHERNET_MMC, dp.ETHERNET_DMA, dp.ETHERNET_PTP),
+//!     (gpioa.pa1, gpioa.pa7, gpiog.pg11, gpiog.pg13, gpiob.pb13, gpioc.pc4, gpioc.pc5),
+//!     unsafe { &mut *addr_of_mut!(RX_RING) },
+//!     unsafe { &mut *addr_of_mut!(TX_RING) },
+//!     Config::default().mac_address([0x02, 0x00, 0x11, 0x22, 0x33, 0x44]),
+//!     &mut syscfg,
+//!     &mut rcc,
+//! );
+//!
+//! let mut mdio = mac.mdio(gpioa.pa2, gpioc.pc1);
+//! let bsr = mdio.read(0, 1);
+//!
+//! if let Ok(frame) = dma.recv_next() {
+//!     defmt::info!("{} bytes", frame.len());
+//! }
+//! ```
+//!
+//! In RMII mode the DMA reset only completes with the 50 MHz reference clock on `REF_CLK`,
+//! so the PHY must be clocked before [`new`] is called.
+
+use crate::gpio::alt::eth as alt;
+use crate::pac::{ETHERNET_DMA, ETHERNET_MAC, ETHERNET_MMC, ETHERNET_PTP};
+use crate::rcc::{Enable, Rcc, Reset};
+use crate::syscfg::SysCfg;
+
+mod dma;
+mod mac;
+mod mdio;
+mod ptp;
+#[cfg(feature = "smoltcp")]
+mod smoltcp;
+
+pub use dma::{EthernetDma, Event, RxPacket, RxRingEntry, TxId, TxRingEntry};
+pub use mac::{AddressFilter, AddressSlot, EthernetMac};
+pub use mdio::Mdio;
+pub use ptp::{EthernetPtp, Timestamp};
+
+/// `MII_RMII_SEL` bit of `SYSCFG_PMC`, missing from the F469 PAC
+const MII_RMII_SEL: u32 = 1 << 23;
+
+/// Largest frame without the CRC that fits in a ring entry, a VLAN tagged frame
+pub const MTU: usize = 1518;
+
+/// Errors of [`EthernetDma`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The received frame did not fit in the buffer of one ring entry and was dropped
+    Truncated,
+    /// The MAC reported an error for the received frame, like a CRC error or an overflow,
+    /// and it was dropped
+    Receive,
+    /// The frame was sent without timestamp, or the ring entry was reused since
+    NoTimestamp,
+}
+
+/// Pins of the media independent interface
+///
+/// Implemented for the RMII pins `(ref_clk, crs_dv, tx_en, txd0, txd1, rxd0, rxd1)` and
+/// for the MII pins `(tx_clk, tx_en, txd0, txd1, txd2, txd3, rx_clk, rx_dv, rxd0, rxd1,
+/// rxd2, rxd3, crs, col)`.
+pub trait Pins {
+    /// Reduced media independent interface
+    const RMII: bool;
+
+    type Pins;
+    fn convert(self) -> Self::Pins;
+}
+
+macro_rules! pins {
+    ($($rmii:literal: ($($P:ident: $i:tt),+);)+) => {
+        $(
+            impl<$($P),+> Pins for ($($P),+)
+            where
+                $($P: Into<alt::$P>),+
+            {
+                const RMII: bool = $rmii;
+
+                type Pins = ($(alt::$P),+);
+                fn convert(self) -> Self::Pins {
+                    ($(self.$i.into()),+)
+                }
+            }
+        )+
+    };
+}
+
+pins! {
+    true: (RefClk: 0, CrsDv: 1, TxEn: 2, Txd0: 3, Txd1: 4, Rxd0: 5, Rxd1: 6);
+    false: (
+        TxClk: 0, TxEn: 1, Txd0: 2, Txd1: 3, Txd2: 4, Txd3: 5, RxClk: 6, RxDv: 7, Rxd0: 8,
+        Rxd1: 9, Rxd2: 10, Rxd3: 11, Crs: 12, Col: 13
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Speed {
+    Mbps10,
+    Mbps100,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    mac_address: [u8; 6],
+    speed: Speed,
+    duplex: Duplex,
+    checksum_offload: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mac_address: [0x02, 0x00, 0x00, 0x00, 0x00, 0x00],
+            speed: Speed::Mbps100,
+            duplex: Duplex::Full,
+            checksum_offload: true,
+        }
+    }
+}
+
+impl Config {
+    /// Address of the station, filtered by [`AddressSlot::A0`]
+    pub fn mac_address(mut self, mac_address: [u8; 6]) -> Self {
+        self.mac_address = mac_address;
+        self
+    }
+
+    /// Initial speed, it must be updated with [`EthernetMac::set_speed`] once the PHY
+    /// negotiated the link
+    pub fn speed(mut self, speed: Speed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Initial duplex mode, see [`speed`](Self::speed)
+    pub fn duplex(mut self, duplex: Duplex) -> Self {
+        self.duplex = duplex;
+        self
+    }
+
+    /// Inserts the IPv4, TCP, UDP and ICMP checksums of sent frames and drops received
+    /// frames with a wrong checksum
+    pub fn checksum_offload(mut self, checksum_offload: bool) -> Self {
+        self.checksum_offload = checksum_offload;
+        self
+    }
+}
+
+/// Parts of the Ethernet peripheral returned by [`new`]
+pub struct Parts<'rx, 'tx, PINS> {
+    pub mac: EthernetMac<PINS>,
+    pub dma: EthernetDma<'rx, 'tx>,
+    pub ptp: EthernetPtp,
+}
+
+/// Enables the clocks of the Ethernet peripheral, resets it, selects the MII or the RMII
+/// and starts sending and receiving with the descriptor rings.
+///
+/// # Panics
+///
+/// If a ring is empty.
+pub fn new<'rx, 'tx, PINS: Pins>(
+    eth: (ETHERNET_MAC, ETHERNET_MMC, ETHERNET_DMA, ETHERNET_PTP),
+    pins: PINS,
+    rx_ring: &'rx mut [RxRingEntry],
+    tx_ring: &'tx mut [TxRingEntry],
+    config: Config,
+    syscfg: &mut SysCfg,
+    rcc: &mut Rcc,
+) -> Parts<'rx, 'tx, PINS::Pins> {
+    let (mac, mmc, eth_dma, eth_ptp) = eth;
+    let pins = pins.convert();
+
+    // The interface can only be changed while the MAC is in reset, before its clocks
+    // are enabled
+    ETHERNET_MAC::disable(rcc);
+    syscfg.pmc().modify(|r, w| unsafe {
+        w.bits(if PINS::RMII {
+            r.bits() | MII_RMII_SEL
+        } else {
+            r.bits() & !MII_RMII_SEL
+        })
+    });
+    rcc.ahb1enr().modify(|_, w| {
+        w.ethmactxen().set_bit();
+        w.ethmacrxen().set_bit();
+        w.ethmacptpen().set_bit()
+    });
+    ETHERNET_MAC::enable(rcc);
+    ETHERNET_MAC::reset(rcc);
+
+    let hclk = rcc.clocks.hclk().raw();
+    let mut dma = EthernetDma::new(eth_dma, rx_ring, tx_ring, config.checksum_offload);
+    let mac = EthernetMac::new(mac, mmc, pins, &config, hclk);
+    let ptp = EthernetPtp::new(eth_ptp, hclk);
+    dma.start();
+
+    Parts { mac, dma, ptp }
+}
@@ -0,0 +1,515 @@
+use core::ops::{Deref, DerefMut};
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{fence, Ordering};
+
+use super::{Error, Timestamp, MTU};
+use crate::pac::ETHERNET_DMA;
+use enumflags2::BitFlags;
+
+/// Bytes of the buffer of a ring entry, a multiple of 4 bigger than a frame with CRC
+const BUFFER_SIZE: usize = 1524;
+
+/// Descriptor owned by the DMA
+const OWN: u32 = 1 << 31;
+
+// TDES0
+const TDES0_LS: u32 = 1 << 29;
+const TDES0_FS: u32 = 1 << 28;
+const TDES0_TTSE: u32 = 1 << 25;
+/// IP header and payload checksums with pseudo-header
+const TDES0_CIC: u32 = 0b11 << 22;
+const TDES0_TCH: u32 = 1 << 20;
+const TDES0_TTSS: u32 = 1 << 17;
+
+// RDES0
+const RDES0_FL_SHIFT: u32 = 16;
+const RDES0_FL_MASK: u32 = 0x3fff;
+const RDES0_ES: u32 = 1 << 15;
+const RDES0_FS: u32 = 1 << 9;
+const RDES0_LS: u32 = 1 << 8;
+const RDES0_TSV: u32 = 1 << 7;
+
+// RDES1
+const RDES1_RCH: u32 = 1 << 14;
+
+/// Enhanced descriptor with the words of the timestamp
+#[repr(C, align(4))]
+struct Descriptor {
+    words: [u32; 8],
+}
+
+impl Descriptor {
+    const INIT: Self = Self { words: [0; 8] };
+
+    fn read(&self, i: usize) -> u32 {
+        unsafe { addr_of!(self.words[i]).read_volatile() }
+    }
+
+    fn write(&mut self, i: usize, value: u32) {
+        unsafe { addr_of_mut!(self.words[i]).write_volatile(value) }
+    }
+
+    fn is_owned(&self) -> bool {
+        self.read(0) & OWN != 0
+    }
+
+    /// Gives the descriptor to the DMA once all other words are written
+    fn give(&mut self, word0: u32) {
+        fence(Ordering::Release);
+        self.write(0, word0 | OWN);
+        cortex_m::asm::dsb();
+    }
+
+    fn timestamp(&self) -> Timestamp {
+        Timestamp::from_parts(self.read(7), self.read(6))
+    }
+}
+
+/// Receive descriptor with the buffer of one frame
+#[repr(C, align(4))]
+pub struct RxRingEntry {
+    desc: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl RxRingEntry {
+    pub const INIT: Self = Self {
+        desc: Descriptor::INIT,
+        buffer: [0; BUFFER_SIZE],
+    };
+}
+
+impl Default for RxRingEntry {
+    fn default() -> Self {
+        Self::INIT
+    }
+}
+
+/// Transmit descriptor with the buffer of one frame
+#[repr(C, align(4))]
+pub struct TxRingEntry {
+    desc: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+    /// Frame sent from the entry, see [`TxId`]
+    sequence: u32,
+}
+
+impl TxRingEntry {
+    pub const INIT: Self = Self {
+        desc: Descriptor::INIT,
+        buffer: [0; BUFFER_SIZE],
+        sequence: 0,
+    };
+}
+
+impl Default for TxRingEntry {
+    fn default() -> Self {
+        Self::INIT
+    }
+}
+
+/// Frame sent with [`EthernetDma::send_timestamped`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxId {
+    index: usize,
+    sequence: u32,
+}
+
+/// DMA interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    /// A frame was sent
+    Transmit = 1 << 0,
+    TransmitStopped = 1 << 1,
+    /// The next transmit descriptor is owned by the CPU
+    TransmitBufferUnavailable = 1 << 2,
+    TransmitJabberTimeout = 1 << 3,
+    ReceiveOverflow = 1 << 4,
+    TransmitUnderflow = 1 << 5,
+    /// A frame was received
+    Receive = 1 << 6,
+    /// The next receive descriptor is owned by the CPU, the DMA is suspended
+    ReceiveBufferUnavailable = 1 << 7,
+    ReceiveStopped = 1 << 8,
+    ReceiveWatchdogTimeout = 1 << 9,
+    EarlyTransmit = 1 << 10,
+    FatalBusError = 1 << 13,
+    EarlyReceive = 1 << 14,
+}
+
+/// Events summarized by `NIS`, the others are summarized by `AIS`
+const NORMAL_EVENTS: u32 = (1 << 0) | (1 << 2) | (1 << 6) | (1 << 14);
+const NIS: u32 = 1 << 16;
+const AIS: u32 = 1 << 15;
+
+fn summary(events: u32) -> u32 {
+    let mut bits = events;
+    if events & NORMAL_EVENTS != 0 {
+        bits |= NIS;
+    }
+    if events & !NORMAL_EVENTS != 0 {
+        bits |= AIS;
+    }
+    bits
+}
+
+pub(super) struct RxRing<'a> {
+    entries: &'a mut [RxRingEntry],
+    next: usize,
+}
+
+impl<'a> RxRing<'a> {
+    fn new(entries: &'a mut [RxRingEntry]) -> Self {
+        assert!(!entries.is_empty());
+        let len = entries.len();
+        let first = addr_of!(entries[0].desc) as u32;
+        for i in 0..len {
+            let next = if i + 1 == len {
+                first
+            } else {
+                addr_of!(entries[i + 1].desc) as u32
+            };
+            let entry = &mut entries[i];
+            let buffer = entry.buffer.as_ptr() as u32;
+            entry.desc.write(1, RDES1_RCH | BUFFER_SIZE as u32);
+            entry.desc.write(2, buffer);
+            entry.desc.write(3, next);
+            entry.desc.give(0);
+        }
+        Self { entries, next: 0 }
+    }
+
+    fn address(&self) -> u32 {
+        addr_of!(self.entries[0].desc) as u32
+    }
+
+    /// Waits for a complete frame in the next entry, erroneous frames are dropped
+    pub(super) fn poll(&mut self, dma: &ETHERNET_DMA) -> nb::Result<(), Error> {
+        let entry = &mut self.entries[self.next];
+        let rdes0 = entry.desc.read(0);
+        if rdes0 & OWN != 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        fence(Ordering::Acquire);
+
+        let error = if rdes0 & (RDES0_FS | RDES0_LS) != RDES0_FS | RDES0_LS {
+            Some(Error::Truncated)
+        } else if rdes0 & RDES0_ES != 0 {
+            Some(Error::Receive)
+        } else {
+            None
+        };
+        match error {
+            Some(error) => {
+                entry.desc.give(0);
+                resume_rx(dma);
+                self.next = (self.next + 1) % self.entries.len();
+                Err(nb::Error::Other(error))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the frame found by [`poll`](Self::poll)
+    pub(super) fn take<'r>(&'r mut self, dma: &'r ETHERNET_DMA) -> RxPacket<'r> {
+        let index = self.next;
+        self.next = (self.next + 1) % self.entries.len();
+        let entry = &mut self.entries[index];
+        let rdes0 = entry.desc.read(0);
+        // The frame length includes the CRC
+        let length = ((rdes0 >> RDES0_FL_SHIFT) & RDES0_FL_MASK) as usize;
+        RxPacket {
+            entry,
+            length: length.saturating_sub(4).min(MTU),
+            dma,
+        }
+    }
+}
+
+/// Resumes the DMA if it was suspended because it found no free descriptor
+fn resume_rx(dma: &ETHERNET_DMA) {
+    dma.dmarpdr().write(|w| unsafe { w.bits(1) });
+}
+
+/// Received frame without CRC, the ring entry is given back to the DMA on drop
+pub struct RxPacket<'a> {
+    entry: &'a mut RxRingEntry,
+    length: usize,
+    dma: &'a ETHERNET_DMA,
+}
+
+impl RxPacket<'_> {
+    /// Time of the start frame delimiter
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        (self.entry.desc.read(0) & RDES0_TSV != 0).then(|| self.entry.desc.timestamp())
+    }
+}
+
+impl Deref for RxPacket<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.entry.buffer[..self.length]
+    }
+}
+
+impl DerefMut for RxPacket<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.entry.buffer[..self.length]
+    }
+}
+
+impl Drop for RxPacket<'_> {
+    fn drop(&mut self) {
+        self.entry.desc.give(0);
+        resume_rx(self.dma);
+    }
+}
+
+pub(super) struct TxRing<'a> {
+    entries: &'a mut [TxRingEntry],
+    next: usize,
+    sequence: u32,
+    checksum_offload: bool,
+}
+
+impl<'a> TxRing<'a> {
+    fn new(entries: &'a mut [TxRingEntry], checksum_offload: bool) -> Self {
+        assert!(!entries.is_empty());
+        let len = entries.len();
+        let first = addr_of!(entries[0].desc) as u32;
+        for i in 0..len {
+            let next = if i + 1 == len {
+                first
+            } else {
+                addr_of!(entries[i + 1].desc) as u32
+            };
+            let entry = &mut entries[i];
+            let buffer = entry.buffer.as_ptr() as u32;
+            entry.desc.write(0, TDES0_TCH);
+            entry.desc.write(2, buffer);
+            entry.desc.write(3, next);
+        }
+        Self {
+            entries,
+            next: 0,
+            sequence: 0,
+            checksum_offload,
+        }
+    }
+
+    fn address(&self) -> u32 {
+        addr_of!(self.entries[0].desc) as u32
+    }
+
+    pub(super) fn is_available(&self) -> bool {
+        !self.entries[self.next].desc.is_owned()
+    }
+
+    #[cfg(feature = "smoltcp")]
+    pub(super) fn checksum_offload(&self) -> bool {
+        self.checksum_offload
+    }
+
+    /// Sends a frame from the next entry, which must be available
+    pub(super) fn send<F, R>(
+        &mut self,
+        dma: &ETHERNET_DMA,
+        length: usize,
+        timestamp: bool,
+        f: F,
+    ) -> (R, TxId)
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        assert!(length <= MTU);
+        let index = self.next;
+        self.next = (self.next + 1) % self.entries.len();
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let entry = &mut self.entries[index];
+        fence(Ordering::Acquire);
+        let result = f(&mut entry.buffer[..length]);
+        entry.sequence = self.sequence;
+
+        let mut tdes0 = TDES0_TCH | TDES0_FS | TDES0_LS;
+        if self.checksum_offload {
+            tdes0 |= TDES0_CIC;
+        }
+        if timestamp {
+            tdes0 |= TDES0_TTSE;
+        }
+        entry.desc.write(1, length as u32);
+        entry.desc.give(tdes0);
+
+        // Resumes the DMA if it is suspended
+        dma.dmatpdr().write(|w| unsafe { w.bits(1) });
+
+        (
+            result,
+            TxId {
+                index,
+                sequence: self.sequence,
+            },
+        )
+    }
+}
+
+/// Ethernet DMA with the descriptor rings
+pub struct EthernetDma<'rx, 'tx> {
+    pub(super) dma: ETHERNET_DMA,
+    pub(super) rx: RxRing<'rx>,
+    pub(super) tx: TxRing<'tx>,
+}
+
+impl<'rx, 'tx> EthernetDma<'rx, 'tx> {
+    pub(super) fn new(
+        dma: ETHERNET_DMA,
+        rx_ring: &'rx mut [RxRingEntry],
+        tx_ring: &'tx mut [TxRingEntry],
+        checksum_offload: bool,
+    ) -> Self {
+        // Resets the MAC as well
+        dma.dmabmr().modify(|_, w| w.sr().set_bit());
+        while dma.dmabmr().read().sr().bit_is_set() {}
+
+        dma.dmabmr().write(|w| {
+            w.edfe().set_bit();
+            w.aab().set_bit();
+            w.usp().set_bit();
+            w.fb().set_bit();
+            unsafe {
+                w.rdp().bits(32);
+                w.pbl().bits(32)
+            }
+        });
+        dma.dmaomr().write(|w| {
+            w.rsf().set_bit();
+            w.tsf().set_bit();
+            w.osf().set_bit()
+        });
+
+        let rx = RxRing::new(rx_ring);
+        let tx = TxRing::new(tx_ring, checksum_offload);
+        dma.dmardlar().write(|w| unsafe { w.bits(rx.address()) });
+        dma.dmatdlar().write(|w| unsafe { w.bits(tx.address()) });
+        Self { dma, rx, tx }
+    }
+
+    pub(super) fn start(&mut self) {
+        self.dma.dmaomr().modify(|_, w| w.ftf().set_bit());
+        while self.dma.dmaomr().read().ftf().bit_is_set() {}
+        self.dma.dmaomr().modify(|_, w| {
+            w.st().set_bit();
+            w.sr().set_bit()
+        });
+    }
+
+    /// Stops the DMA and releases it
+    pub fn release(self) -> ETHERNET_DMA {
+        self.dma.dmaomr().modify(|_, w| {
+            w.st().clear_bit();
+            w.sr().clear_bit()
+        });
+        self.dma
+    }
+
+    /// Takes the next received frame, frames with errors are dropped and reported
+    pub fn recv_next(&mut self) -> nb::Result<RxPacket<'_>, Error> {
+        self.rx.poll(&self.dma)?;
+        Ok(self.rx.take(&self.dma))
+    }
+
+    /// A frame can be sent
+    pub fn tx_is_available(&self) -> bool {
+        self.tx.is_available()
+    }
+
+    /// Sends a frame of `length` bytes which `f` writes
+    ///
+    /// # Panics
+    ///
+    /// If `length` is bigger than [`MTU`].
+    pub fn send<F, R>(&mut self, length: usize, f: F) -> nb::Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        if !self.tx.is_available() {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(self.tx.send(&self.dma, length, false, f).0)
+    }
+
+    /// Sends a frame like [`send`](Self::send), its timestamp is read with
+    /// [`tx_timestamp`](Self::tx_timestamp)
+    pub fn send_timestamped<F, R>(&mut self, length: usize, f: F) -> nb::Result<(R, TxId), Error>
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        if !self.tx.is_available() {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(self.tx.send(&self.dma, length, true, f))
+    }
+
+    /// Time at which the frame `id` was sent, until its ring entry is reused
+    pub fn tx_timestamp(&self, id: TxId) -> nb::Result<Timestamp, Error> {
+        let entry = &self.tx.entries[id.index];
+        if entry.sequence != id.sequence {
+            return Err(nb::Error::Other(Error::NoTimestamp));
+        }
+        let tdes0 = entry.desc.read(0);
+        if tdes0 & OWN != 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        fence(Ordering::Acquire);
+        if tdes0 & TDES0_TTSS == 0 {
+            return Err(nb::Error::Other(Error::NoTimestamp));
+        }
+        Ok(entry.desc.timestamp())
+    }
+}
+
+impl crate::Listen for EthernetDma<'_, '_> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.dma
+            .dmaier()
+            .modify(|r, w| unsafe { w.bits(summary(r.bits() | event.into().bits())) });
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.dma
+            .dmaier()
+            .write(|w| unsafe { w.bits(summary(event.into().bits())) });
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.dma.dmaier().modify(|r, w| unsafe {
+            w.bits(summary(r.bits() & !(event.into().bits() | NIS | AIS)))
+        });
+    }
+}
+
+impl crate::ReadFlags for EthernetDma<'_, '_> {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Event> {
+        BitFlags::from_bits_truncate(self.dma.dmasr().read().bits())
+    }
+}
+
+impl crate::ClearFlags for EthernetDma<'_, '_> {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Event>>) {
+        self.dma
+            .dmasr()
+            .write(|w| unsafe { w.bits(summary(flags.into().bits())) });
+    }
+}
@@ -0,0 +1,241 @@
+use super::{Config, Duplex, Mdio, Speed};
+use crate::gpio::alt::eth as alt;
+use crate::pac::{ETHERNET_MAC, ETHERNET_MMC};
+
+/// Perfect address filter of the MAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressSlot {
+    /// Address of the station, always compared with the destination address
+    A0,
+    A1,
+    A2,
+    A3,
+}
+
+/// Address compared by [`AddressSlot::A1`] to [`AddressSlot::A3`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AddressFilter {
+    pub address: [u8; 6],
+    /// Compares the source address instead of the destination address. Frames from
+    /// other sources are dropped once a source filter is set.
+    pub source: bool,
+    /// Bytes of the address which are not compared, bit 0 for the first byte
+    pub mask: u8,
+}
+
+impl AddressFilter {
+    /// Passes frames sent to `address`
+    pub const fn destination(address: [u8; 6]) -> Self {
+        Self {
+            address,
+            source: false,
+            mask: 0,
+        }
+    }
+
+    /// Passes frames sent from `address`
+    pub const fn source(address: [u8; 6]) -> Self {
+        Self {
+            address,
+            source: true,
+            mask: 0,
+        }
+    }
+}
+
+/// Ethernet MAC
+///
+/// The frame filter passes broadcast frames and frames sent to the station address, see
+/// [`Config::mac_address`]. More addresses are passed by the perfect filters of
+/// [`set_address_filter`](Self::set_address_filter) or by the multicast hash filter of
+/// [`add_multicast`](Self::add_multicast).
+pub struct EthernetMac<PINS> {
+    mac: ETHERNET_MAC,
+    mmc: ETHERNET_MMC,
+    pins: PINS,
+    /// Clock range of MDC
+    cr: u8,
+}
+
+impl<PINS> EthernetMac<PINS> {
+    pub(super) fn new(
+        mac: ETHERNET_MAC,
+        mmc: ETHERNET_MMC,
+        pins: PINS,
+        config: &Config,
+        hclk: u32,
+    ) -> Self {
+        // MDC must stay below 2.5 MHz
+        let cr = match hclk {
+            0..=34_999_999 => 0b010,
+            35_000_000..=59_999_999 => 0b011,
+            60_000_000..=99_999_999 => 0b000,
+            100_000_000..=149_999_999 => 0b001,
+            _ => 0b100,
+        };
+
+        // The counter interrupts can't be cleared without reading the counters
+        mmc.mmcrimr().write(|w| {
+            w.rfcem().set_bit();
+            w.rfaem().set_bit();
+            w.rgufm().set_bit()
+        });
+        mmc.mmctimr().write(|w| {
+            w.tgfscm().set_bit();
+            w.tgfmscm().set_bit();
+            w.tgfm().set_bit()
+        });
+        mac.macimr().write(|w| {
+            w.tstim().set_bit();
+            w.pmtim().set_bit()
+        });
+
+        let mut mac = Self { mac, mmc, pins, cr };
+        mac.set_address(config.mac_address);
+        mac.mac.maccr().write(|w| {
+            w.ipco().bit(config.checksum_offload);
+            w.re().set_bit();
+            w.te().set_bit()
+        });
+        mac.set_speed(config.speed, config.duplex);
+        mac
+    }
+
+    /// Releases the MAC and its pins
+    pub fn release(self) -> (ETHERNET_MAC, ETHERNET_MMC, PINS) {
+        (self.mac, self.mmc, self.pins)
+    }
+
+    /// Manages the PHY through the `mdio` and `mdc` pins
+    pub fn mdio(&self, mdio: impl Into<alt::Mdio>, mdc: impl Into<alt::Mdc>) -> Mdio {
+        Mdio::new(mdio.into(), mdc.into(), self.cr)
+    }
+
+    /// Sets the speed and the duplex mode negotiated by the PHY
+    pub fn set_speed(&mut self, speed: Speed, duplex: Duplex) {
+        self.mac.maccr().modify(|_, w| {
+            w.fes().bit(speed == Speed::Mbps100);
+            w.dm().bit(duplex == Duplex::Full)
+        });
+    }
+
+    /// Changes the address of the station
+    pub fn set_address(&mut self, address: [u8; 6]) {
+        self.mac
+            .maca0hr()
+            .write(|w| w.maca0h().set(u16::from_le_bytes([address[4], address[5]])));
+        self.mac.maca0lr().write(|w| {
+            w.maca0l()
+                .set(u32::from_le_bytes(address[..4].try_into().unwrap()))
+        });
+    }
+
+    /// Sets or removes the perfect filter of `slot`, [`AddressSlot::A0`] is set with
+    /// [`set_address`](Self::set_address) and can't be removed.
+    pub fn set_address_filter(&mut self, slot: AddressSlot, filter: Option<AddressFilter>) {
+        let (high, low) = match filter {
+            Some(filter) => (
+                1 << 31
+                    | (filter.source as u32) << 30
+                    | ((filter.mask & 0x3f) as u32) << 24
+                    | u16::from_le_bytes([filter.address[4], filter.address[5]]) as u32,
+                u32::from_le_bytes(filter.address[..4].try_into().unwrap()),
+            ),
+            None => (0, 0),
+        };
+        let mac = &self.mac;
+        // NOTE(unsafe) the reserved bits stay 0
+        unsafe {
+            match slot {
+                AddressSlot::A0 => {
+                    if let Some(filter) = filter {
+                        return self.set_address(filter.address);
+                    }
+                }
+                AddressSlot::A1 => {
+                    mac.maca1hr().write(|w| w.bits(high));
+                    mac.maca1lr().write(|w| w.bits(low));
+                }
+                AddressSlot::A2 => {
+                    mac.maca2hr().write(|w| w.bits(high));
+                    mac.maca2lr().write(|w| w.bits(low));
+                }
+                AddressSlot::A3 => {
+                    mac.maca3hr().write(|w| w.bits(high));
+                    mac.maca3lr().write(|w| w.bits(low));
+                }
+            }
+        }
+
+        let source = [
+            mac.maca1hr().read().bits(),
+            mac.maca2hr().read().bits(),
+            mac.maca3hr().read().bits(),
+        ]
+        .iter()
+        .any(|high| high & (0b11 << 30) == 0b11 << 30);
+        mac.macffr().modify(|_, w| w.saf().bit(source));
+    }
+
+    /// Passes multicast frames sent to `address` with the hash filter, which also passes
+    /// some other multicast addresses
+    pub fn add_multicast(&mut self, address: &[u8; 6]) {
+        let bit = multicast_hash(address);
+        if bit < 32 {
+            self.mac
+                .machtlr()
+                .modify(|r, w| w.htl().set(r.htl().bits() | 1 << bit));
+        } else {
+            self.mac
+                .machthr()
+                .modify(|r, w| w.hth().set(r.hth().bits() | 1 << (bit - 32)));
+        }
+        self.mac.macffr().modify(|_, w| {
+            w.hm().set_bit();
+            w.hpf().set_bit()
+        });
+    }
+
+    /// Removes all addresses of [`add_multicast`](Self::add_multicast)
+    pub fn clear_multicast(&mut self) {
+        self.mac.macffr().modify(|_, w| {
+            w.hm().clear_bit();
+            w.hpf().clear_bit()
+        });
+        self.mac.machtlr().write(|w| w.htl().set(0));
+        self.mac.machthr().write(|w| w.hth().set(0));
+    }
+
+    /// Passes all multicast frames
+    pub fn set_pass_all_multicast(&mut self, enable: bool) {
+        self.mac.macffr().modify(|_, w| w.pam().bit(enable));
+    }
+
+    /// Passes broadcast frames, which is the default
+    pub fn set_broadcast(&mut self, enable: bool) {
+        self.mac.macffr().modify(|_, w| w.bfd().bit(!enable));
+    }
+
+    /// Passes all frames whatever their addresses
+    pub fn set_promiscuous(&mut self, enable: bool) {
+        self.mac.macffr().modify(|_, w| w.pm().bit(enable));
+    }
+}
+
+/// Bit of the hash table for `address`: the upper 6 bits of the bit reversed CRC-32
+fn multicast_hash(address: &[u8; 6]) -> u32 {
+    let mut crc = u32::MAX;
+    for byte in address {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    (!crc).reverse_bits() >> 26
+}
@@ -0,0 +1,50 @@
+use crate::gpio::alt::eth as alt;
+use crate::pac::ETHERNET_MAC;
+
+/// Station management interface of the PHYs
+pub struct Mdio {
+    mdio: alt::Mdio,
+    mdc: alt::Mdc,
+    /// Clock range of MDC
+    cr: u8,
+}
+
+impl Mdio {
+    pub(super) fn new(mdio: alt::Mdio, mdc: alt::Mdc, cr: u8) -> Self {
+        Self { mdio, mdc, cr }
+    }
+
+    /// Releases the `mdio` and `mdc` pins
+    pub fn release(self) -> (alt::Mdio, alt::Mdc) {
+        (self.mdio, self.mdc)
+    }
+
+    /// Reads register `reg` of the PHY at address `phy`
+    pub fn read(&mut self, phy: u8, reg: u8) -> u16 {
+        self.transfer(phy, reg, false);
+        self.mac().macmiidr().read().md().bits()
+    }
+
+    /// Writes `value` to register `reg` of the PHY at address `phy`
+    pub fn write(&mut self, phy: u8, reg: u8, value: u16) {
+        self.mac().macmiidr().write(|w| w.md().set(value));
+        self.transfer(phy, reg, true);
+    }
+
+    fn transfer(&self, phy: u8, reg: u8, write: bool) {
+        let mac = self.mac();
+        mac.macmiiar().write(|w| {
+            w.pa().set(phy & 0x1f);
+            w.mr().set(reg & 0x1f);
+            unsafe { w.cr().bits(self.cr) };
+            w.mw().bit(write);
+            w.mb().set_bit()
+        });
+        while mac.macmiiar().read().mb().bit_is_set() {}
+    }
+
+    fn mac(&self) -> &crate::pac::ethernet_mac::RegisterBlock {
+        // NOTE(unsafe) only the MII registers are used, which `EthernetMac` never touches
+        unsafe { &*ETHERNET_MAC::ptr() }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::pac::{ETHERNET_MAC, ETHERNET_PTP};
+
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+/// Time of the PTP clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    seconds: u32,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// # Panics
+    ///
+    /// If `nanos` is not less than one second.
+    pub const fn new(seconds: u32, nanos: u32) -> Self {
+        assert!(nanos < NANOS_PER_SECOND);
+        Self { seconds, nanos }
+    }
+
+    pub const fn seconds(&self) -> u32 {
+        self.seconds
+    }
+
+    /// Nanoseconds of the second
+    pub const fn nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    pub const fn total_nanos(&self) -> u64 {
+        self.seconds as u64 * NANOS_PER_SECOND as u64 + self.nanos as u64
+    }
+
+    pub(super) fn from_parts(seconds: u32, nanos: u32) -> Self {
+        Self {
+            seconds,
+            nanos: nanos & 0x7fff_ffff,
+        }
+    }
+}
+
+/// IEEE 1588 clock of the MAC
+///
+/// The clock is incremented in nanoseconds from HCLK, with a fine correction of its
+/// frequency by the [`addend`](Self::addend). It starts at 0 after [`new`](super::new).
+pub struct EthernetPtp {
+    ptp: ETHERNET_PTP,
+}
+
+impl EthernetPtp {
+    pub(super) fn new(ptp: ETHERNET_PTP, hclk: u32) -> Self {
+        // The clock is incremented at about half the HCLK frequency, and the addend
+        // makes up for the rounding of the increment
+        let increment = 2 * ((NANOS_PER_SECOND + hclk - 1) / hclk);
+        let addend =
+            ((1u64 << 32) * NANOS_PER_SECOND as u64 / (increment as u64 * hclk as u64)) as u32;
+
+        ptp.ptptscr().write(|w| {
+            w.tse().set_bit();
+            w.tsfcu().set_bit();
+            w.tsssr().set_bit();
+            w.tssarfe().set_bit()
+        });
+        ptp.ptpssir()
+            .write(|w| unsafe { w.stssi().bits(increment as u8) });
+
+        let mut ptp = Self { ptp };
+        ptp.set_addend(addend);
+        ptp.set_time(Timestamp::new(0, 0));
+        ptp
+    }
+
+    /// Releases the PTP peripheral
+    pub fn release(self) -> ETHERNET_PTP {
+        self.ptp
+    }
+
+    /// Current time
+    pub fn now(&self) -> Timestamp {
+        loop {
+            let seconds = self.ptp.ptptshr().read().bits();
+            let nanos = self.ptp.ptptslr().read().bits();
+            if seconds == self.ptp.ptptshr().read().bits() {
+                return Timestamp::from_parts(seconds, nanos);
+            }
+        }
+    }
+
+    /// Sets the time
+    pub fn set_time(&mut self, time: Timestamp) {
+        self.write_update(time.seconds, time.nanos, false);
+        self.ptp.ptptscr().modify(|_, w| w.tssti().set_bit());
+        while self.ptp.ptptscr().read().tssti().bit_is_set() {}
+    }
+
+    /// Moves the time by `nanos` at once
+    pub fn step(&mut self, nanos: i64) {
+        let magnitude = nanos.unsigned_abs();
+        let seconds = (magnitude / NANOS_PER_SECOND as u64) as u32;
+        let mut nanos_part = (magnitude % NANOS_PER_SECOND as u64) as u32;
+        // With the digital rollover, the subseconds are subtracted as their complement
+        if nanos < 0 && nanos_part != 0 {
+            nanos_part = NANOS_PER_SECOND - nanos_part;
+        }
+        self.write_update(seconds, nanos_part, nanos < 0);
+        self.ptp.ptptscr().modify(|_, w| w.tsstu().set_bit());
+        while self.ptp.ptptscr().read().tsstu().bit_is_set() {}
+    }
+
+    /// Frequency correction, `2^32` makes the clock run at HCLK times the increment
+    pub fn addend(&self) -> u32 {
+        self.ptp.ptptsar().read().tsa().bits()
+    }
+
+    /// Changes the frequency correction
+    pub fn set_addend(&mut self, addend: u32) {
+        self.ptp
+            .ptptsar()
+            .write(|w| unsafe { w.tsa().bits(addend) });
+        self.ptp.ptptscr().modify(|_, w| w.ttsaru().set_bit());
+        while self.ptp.ptptscr().read().ttsaru().bit_is_set() {}
+    }
+
+    /// Sets the time of the target time interrupt
+    pub fn set_target_time(&mut self, time: Timestamp) {
+        self.ptp
+            .ptptthr()
+            .write(|w| unsafe { w.ttsh().bits(time.seconds) });
+        self.ptp
+            .ptpttlr()
+            .write(|w| unsafe { w.ttsl().bits(time.nanos) });
+    }
+
+    /// Triggers the `ETH` interrupt once at the target time
+    pub fn listen_target(&mut self) {
+        self.ptp.ptptscr().modify(|_, w| w.tsite().set_bit());
+        self.mac().macimr().modify(|_, w| w.tstim().clear_bit());
+    }
+
+    /// Stops the target time interrupt
+    pub fn unlisten_target(&mut self) {
+        self.ptp.ptptscr().modify(|_, w| w.tsite().clear_bit());
+        self.mac().macimr().modify(|_, w| w.tstim().set_bit());
+    }
+
+    /// Returns `true` once when the target time was reached, which also clears the
+    /// interrupt
+    pub fn is_target_reached(&mut self) -> bool {
+        self.ptp.ptptssr().read().tsttr().bit_is_set()
+    }
+
+    fn write_update(&self, seconds: u32, nanos: u32, subtract: bool) {
+        self.ptp
+            .ptptshur()
+            .write(|w| unsafe { w.tsus().bits(seconds) });
+        self.ptp.ptptslur().write(|w| {
+            unsafe { w.tsuss().bits(nanos) };
+            w.tsupns().bit(subtract)
+        });
+    }
+
+    fn mac(&self) -> &crate::pac::ethernet_mac::RegisterBlock {
+        // NOTE(unsafe) `EthernetMac` only writes `MACIMR` while it is created
+        unsafe { &*ETHERNET_MAC::ptr() }
+    }
+}
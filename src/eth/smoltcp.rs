@@ -0,0 +1,94 @@
+use ::smoltcp::phy::{ChecksumCapabilities, Device, DeviceCapabilities, Medium};
+use ::smoltcp::time::Instant;
+
+use super::dma::{RxRing, TxRing};
+use super::{EthernetDma, MTU};
+use crate::pac::ETHERNET_DMA;
+
+/// Drops the erroneous frames until a good one is received
+fn rx_is_available(rx: &mut RxRing, dma: &ETHERNET_DMA) -> bool {
+    loop {
+        match rx.poll(dma) {
+            Ok(()) => return true,
+            Err(nb::Error::WouldBlock) => return false,
+            Err(nb::Error::Other(_)) => {}
+        }
+    }
+}
+
+impl<'rx, 'tx> Device for EthernetDma<'rx, 'tx> {
+    type RxToken<'a>
+        = RxToken<'a, 'rx>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a, 'tx>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        if rx_is_available(&mut self.rx, &self.dma) && self.tx.is_available() {
+            Some((
+                RxToken {
+                    rx: &mut self.rx,
+                    dma: &self.dma,
+                },
+                TxToken {
+                    tx: &mut self.tx,
+                    dma: &self.dma,
+                },
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.tx.is_available().then_some(TxToken {
+            tx: &mut self.tx,
+            dma: &self.dma,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = MTU;
+        caps.max_burst_size = Some(1);
+        if self.tx.checksum_offload() {
+            caps.checksum = ChecksumCapabilities::ignored();
+        }
+        caps
+    }
+}
+
+/// Frame received by [`EthernetDma`]
+pub struct RxToken<'a, 'rx> {
+    rx: &'a mut RxRing<'rx>,
+    dma: &'a ETHERNET_DMA,
+}
+
+impl ::smoltcp::phy::RxToken for RxToken<'_, '_> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        let packet = self.rx.take(self.dma);
+        f(&packet)
+    }
+}
+
+/// Frame sent by [`EthernetDma`]
+pub struct TxToken<'a, 'tx> {
+    tx: &'a mut TxRing<'tx>,
+    dma: &'a ETHERNET_DMA,
+}
+
+impl ::smoltcp::phy::TxToken for TxToken<'_, '_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.tx.send(self.dma, len, false, f).0
+    }
+}
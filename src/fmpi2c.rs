@@ -22,6 +22,11 @@ mod hal_02;
 #[path = "i2c/hal_1.rs"]
 mod hal_1;
 
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{AsyncDma, DmaStream, I2cAsync};
+
 type I2cSel = rcc::dckcfgr2::FMPI2C1SEL;
 
 pub trait Instance:
@@ -34,6 +39,10 @@ pub trait Instance:
     + gpio::alt::I2cCommon
 {
     fn set_clock_source(rcc: &rcc::RegisterBlock, source: I2cSel);
+
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn waker() -> &'static atomic_waker::AtomicWaker;
 }
 
 macro_rules! i2c {
@@ -44,6 +53,12 @@ macro_rules! i2c {
             fn set_clock_source(rcc: &rcc::RegisterBlock, source: I2cSel) {
                 rcc.dckcfgr2().modify(|_, w| w.$i2csel().variant(source));
             }
+
+            #[cfg(feature = "async")]
+            fn waker() -> &'static atomic_waker::AtomicWaker {
+                static WAKER: atomic_waker::AtomicWaker = atomic_waker::AtomicWaker::new();
+                &WAKER
+            }
         }
     };
 }
@@ -0,0 +1,596 @@
+//! Async FMPI2C master driven by the FMPI2C interrupts.
+//!
+//! The task waiting for the bus is woken by [`I2c::on_interrupt`], which must be called from
+//! both `FMPI2C1_EV` and `FMPI2C1_ER` interrupt handlers:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn FMPI2C1_EV() {
+//!     fmpi2c::FMPI2c1::on_interrupt();
+//! }
+//!
+//! #[interrupt]
+//! fn FMPI2C1_ER() {
+//!     fmpi2c::FMPI2c1::on_interrupt();
+//! }
+//! ```
+//!
+//! When data is moved by DMA, the interrupts of both streams must also call
+//! [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt).
+
+use core::{future::poll_fn, task::Poll};
+
+use embedded_hal::i2c::{ErrorType, Operation, SevenBitAddress, TenBitAddress};
+
+use super::{i2c1, Address, Error, I2c, Instance, NoAcknowledgeSource};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use crate::i2c::dma::NoDMA;
+
+/// Most bytes of one NBYTES transfer
+const MAX_CHUNK: usize = 255;
+
+/// Event the task waits for
+#[derive(Clone, Copy)]
+enum Wait {
+    /// TXIS, transmit register is empty
+    Transmit,
+    /// RXNE, a byte was received
+    Receive,
+    /// TC or TCR, NBYTES are transferred
+    Complete,
+    /// STOPF, STOP condition was sent
+    Stop,
+}
+
+impl<I2C: Instance> I2c<I2C> {
+    /// Wakes the task waiting for the bus.
+    ///
+    /// Must be called from both `FMPI2Cx_EV` and `FMPI2Cx_ER` interrupt handlers when the
+    /// bus is used by [`I2cAsync`]. Interrupts are disabled, the woken task enables them again
+    /// if it still has to wait.
+    pub fn on_interrupt() {
+        let i2c = unsafe { &*I2C::ptr() };
+        i2c.cr1().modify(|_, w| {
+            w.txie().clear_bit();
+            w.rxie().clear_bit();
+            w.tcie().clear_bit();
+            w.stopie().clear_bit();
+            w.nackie().clear_bit();
+            w.errie().clear_bit()
+        });
+        I2C::waker().wake();
+    }
+
+    /// Converts blocking [`I2c`] to [`I2cAsync`] that moves every byte from the interrupts
+    pub fn into_async(self) -> I2cAsync<I2C> {
+        I2cAsync {
+            i2c: self,
+            tx: NoDMA,
+            rx: NoDMA,
+        }
+    }
+
+    /// Converts blocking [`I2c`] to [`I2cAsync`] that uses `tx_stream` and `rx_stream` to send/receive data
+    pub fn into_async_dma<TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8>(
+        self,
+        tx_stream: TX_STREAM,
+        rx_stream: RX_STREAM,
+    ) -> I2cAsync<I2C, DmaStream<TX_STREAM, TX_CH>, DmaStream<RX_STREAM, RX_CH>>
+    where
+        TX_STREAM: Stream,
+        ChannelX<TX_CH>: Channel,
+        I2C: DMASet<TX_STREAM, TX_CH, MemoryToPeripheral>,
+
+        RX_STREAM: Stream,
+        ChannelX<RX_CH>: Channel,
+        I2C: DMASet<RX_STREAM, RX_CH, PeripheralToMemory>,
+    {
+        I2cAsync {
+            i2c: self,
+            tx: DmaStream(tx_stream),
+            rx: DmaStream(rx_stream),
+        }
+    }
+
+    fn disable_interrupts(&self) {
+        self.i2c.cr1().modify(|_, w| {
+            w.txie().clear_bit();
+            w.rxie().clear_bit();
+            w.tcie().clear_bit();
+            w.stopie().clear_bit();
+            w.nackie().clear_bit();
+            w.errie().clear_bit()
+        });
+    }
+
+    /// Clears and reports the first error flag
+    fn check_errors(&self, isr: &i2c1::isr::R) -> Result<(), Error> {
+        let icr = self.i2c.icr();
+        if isr.nackf().bit_is_set() {
+            icr.write(|w| w.nackcf().clear_bit_by_one());
+            Err(Error::NoAcknowledge(NoAcknowledgeSource::Unknown))
+        } else if isr.arlo().bit_is_set() {
+            icr.write(|w| w.arlocf().clear_bit_by_one());
+            Err(Error::ArbitrationLoss)
+        } else if isr.berr().bit_is_set() {
+            icr.write(|w| w.berrcf().clear_bit_by_one());
+            Err(Error::Bus)
+        } else if isr.ovr().bit_is_set() {
+            icr.write(|w| w.ovrcf().clear_bit_by_one());
+            Err(Error::Overrun)
+        } else if isr.timeout().bit_is_set() {
+            icr.write(|w| w.timoutcf().clear_bit_by_one());
+            Err(Error::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Waits for `event` or an error
+    async fn wait_for(&self, event: Wait) -> Result<(), Error> {
+        poll_fn(|cx| {
+            I2C::waker().register(cx.waker());
+            let isr = self.i2c.isr().read();
+            if let Err(e) = self.check_errors(&isr) {
+                self.disable_interrupts();
+                return Poll::Ready(Err(e));
+            }
+            let done = match event {
+                Wait::Transmit => isr.txis().bit_is_set(),
+                Wait::Receive => isr.rxne().bit_is_set(),
+                Wait::Complete => isr.tc().bit_is_set() || isr.tcr().bit_is_set(),
+                Wait::Stop => isr.stopf().bit_is_set(),
+            };
+            if done {
+                self.disable_interrupts();
+                return Poll::Ready(Ok(()));
+            }
+            self.i2c.cr1().modify(|_, w| {
+                match event {
+                    Wait::Transmit => w.txie().set_bit(),
+                    Wait::Receive => w.rxie().set_bit(),
+                    Wait::Complete => w.tcie().set_bit(),
+                    Wait::Stop => w.stopie().set_bit(),
+                };
+                w.nackie().set_bit();
+                w.errie().set_bit()
+            });
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Waits for the end of a DMA `transfer` or an error on the bus
+    async fn wait_dma<STREAM: Stream>(
+        &self,
+        transfer: &mut OneShot<'_, STREAM>,
+    ) -> Result<(), Error> {
+        poll_fn(|cx| {
+            I2C::waker().register(cx.waker());
+            if let Err(e) = self.check_errors(&self.i2c.isr().read()) {
+                self.disable_interrupts();
+                return Poll::Ready(Err(e));
+            }
+            match transfer.poll(cx) {
+                Poll::Ready(result) => {
+                    self.disable_interrupts();
+                    Poll::Ready(result.map_err(|_| Error::Dma))
+                }
+                Poll::Pending => {
+                    self.i2c.cr1().modify(|_, w| {
+                        w.nackie().set_bit();
+                        w.errie().set_bit()
+                    });
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Releases the bus after an error
+    fn abort(&self, error: Error) {
+        self.disable_interrupts();
+        self.i2c.cr1().modify(|_, w| {
+            w.txdmaen().clear_bit();
+            w.rxdmaen().clear_bit()
+        });
+        // STOP follows NACK automatically and the bus is lost after arbitration loss
+        if !matches!(error, Error::NoAcknowledge(_) | Error::ArbitrationLoss) {
+            self.i2c.cr2().modify(|_, w| w.stop().set_bit());
+        }
+        // Flush the transmit register
+        self.i2c.isr().write(|w| w.txe().set_bit());
+    }
+}
+
+/// Stream and channel used by [`I2cAsync`] to move data with DMA
+pub struct DmaStream<STREAM, const CH: u8>(STREAM);
+
+impl<STREAM, const CH: u8> DmaStream<STREAM, CH> {
+    /// Returns the DMA stream
+    pub fn release(self) -> STREAM {
+        self.0
+    }
+}
+
+impl<STREAM, const CH: u8> crate::Sealed for DmaStream<STREAM, CH> {}
+
+/// Data transfer of [`I2cAsync`] in one direction, by DMA with [`DmaStream`] or by
+/// interrupts with [`NoDMA`]
+pub trait AsyncDma<I2C: Instance, DIR>: crate::Sealed {
+    #[doc(hidden)]
+    const DMA: bool;
+
+    #[doc(hidden)]
+    #[allow(async_fn_in_trait)]
+    async fn transfer(&mut self, i2c: &I2c<I2C>, buf: *mut u8, len: u16) -> Result<(), Error>;
+}
+
+impl<I2C: Instance, DIR> AsyncDma<I2C, DIR> for NoDMA {
+    const DMA: bool = false;
+
+    async fn transfer(&mut self, _: &I2c<I2C>, _: *mut u8, _: u16) -> Result<(), Error> {
+        unreachable!()
+    }
+}
+
+impl<I2C: Instance, STREAM, const CH: u8> AsyncDma<I2C, MemoryToPeripheral>
+    for DmaStream<STREAM, CH>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    I2C: DMASet<STREAM, CH, MemoryToPeripheral>,
+{
+    const DMA: bool = true;
+
+    async fn transfer(&mut self, i2c: &I2c<I2C>, buf: *mut u8, len: u16) -> Result<(), Error> {
+        let txdr = i2c.i2c.txdr().as_ptr() as u32;
+        i2c.i2c.cr1().modify(|_, w| w.txdmaen().set_bit());
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.0,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::MemoryToPeripheral,
+                txdr,
+                buf as *const u8,
+                len,
+                true,
+            )
+        };
+        let result = i2c.wait_dma(&mut transfer).await;
+        i2c.i2c.cr1().modify(|_, w| w.txdmaen().clear_bit());
+        result
+    }
+}
+
+impl<I2C: Instance, STREAM, const CH: u8> AsyncDma<I2C, PeripheralToMemory>
+    for DmaStream<STREAM, CH>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    I2C: DMASet<STREAM, CH, PeripheralToMemory>,
+{
+    const DMA: bool = true;
+
+    async fn transfer(&mut self, i2c: &I2c<I2C>, buf: *mut u8, len: u16) -> Result<(), Error> {
+        let rxdr = i2c.i2c.rxdr().as_ptr() as u32;
+        i2c.i2c.cr1().modify(|_, w| w.rxdmaen().set_bit());
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.0,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::PeripheralToMemory,
+                rxdr,
+                buf as *const u8,
+                len,
+                true,
+            )
+        };
+        let result = i2c.wait_dma(&mut transfer).await;
+        i2c.i2c.cr1().modify(|_, w| w.rxdmaen().clear_bit());
+        result
+    }
+}
+
+/// FMPI2C in Master mode that waits for the bus with interrupts
+///
+/// Implements [`embedded_hal_async::i2c::I2c`] with 7 and 10-bit addresses. Bytes are
+/// moved by the interrupts unless DMA streams are given to [`I2c::into_async_dma`].
+/// A client must:
+/// * Enable interrupts FMPI2Cx_EV and FMPI2Cx_ER and call [`I2c::on_interrupt`] in both of them.
+/// * With DMA, enable interrupts of both streams and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in them.
+///
+/// Dropping a pending transaction leaves the bus in an undefined state.
+pub struct I2cAsync<I2C: Instance, TX = NoDMA, RX = NoDMA> {
+    i2c: I2c<I2C>,
+    tx: TX,
+    rx: RX,
+}
+
+impl<I2C, TX, RX> I2cAsync<I2C, TX, RX>
+where
+    I2C: Instance,
+    TX: AsyncDma<I2C, MemoryToPeripheral>,
+    RX: AsyncDma<I2C, PeripheralToMemory>,
+{
+    /// Returns the blocking [`I2c`] and the DMA streams
+    pub fn release(self) -> (I2c<I2C>, TX, RX) {
+        (self.i2c, self.tx, self.rx)
+    }
+
+    pub async fn write(&mut self, addr: impl Into<Address>, bytes: &[u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Write(bytes)]).await
+    }
+
+    /// Reads `buffer`, an empty buffer returns `Ok(())` without using the bus
+    pub async fn read(&mut self, addr: impl Into<Address>, buffer: &mut [u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Read(buffer)]).await
+    }
+
+    pub async fn write_read(
+        &mut self,
+        addr: impl Into<Address>,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        self.transaction(
+            addr,
+            &mut [Operation::Write(bytes), Operation::Read(buffer)],
+        )
+        .await
+    }
+
+    /// Executes `operations` as one transaction.
+    ///
+    /// Adjacent operations of the same type are merged, a repeated START is only sent
+    /// when the type changes. Empty reads are skipped. The transaction ends with STOP, also
+    /// on errors.
+    pub async fn transaction(
+        &mut self,
+        addr: impl Into<Address>,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let result = self.transaction_inner(addr.into(), operations).await;
+        if let Err(e) = result {
+            self.i2c.abort(e);
+        }
+        result
+    }
+
+    async fn transaction_inner(
+        &mut self,
+        addr: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        if operations.iter().all(is_empty_read) {
+            return Ok(());
+        }
+        // Clear flags left by a previous transaction
+        self.i2c.i2c.icr().write(|w| {
+            w.stopcf().clear_bit_by_one();
+            w.nackcf().clear_bit_by_one();
+            w.berrcf().clear_bit_by_one();
+            w.arlocf().clear_bit_by_one();
+            w.ovrcf().clear_bit_by_one()
+        });
+
+        let mut first = 0;
+        let mut first_transaction = true;
+        while first < operations.len() {
+            let read = matches!(operations[first], Operation::Read(_));
+            let end = operations[first..]
+                .iter()
+                .position(|op| matches!(op, Operation::Read(_)) != read)
+                .map_or(operations.len(), |n| first + n);
+            let group = &mut operations[first..end];
+            if read && group.iter().all(is_empty_read) {
+                first = end;
+                continue;
+            }
+            if read {
+                self.read_group(addr, group, first_transaction).await?;
+            } else {
+                self.write_group(addr, group).await?;
+            }
+            first_transaction = false;
+            first = end;
+        }
+
+        self.i2c.i2c.cr2().modify(|_, w| w.stop().set_bit());
+        self.i2c.wait_for(Wait::Stop).await?;
+        self.i2c.i2c.icr().write(|w| w.stopcf().clear_bit_by_one());
+        Ok(())
+    }
+
+    /// Sends START with the address, the transfer direction and the first `nbytes`
+    fn start(
+        &self,
+        addr: Address,
+        read: bool,
+        first_transaction: bool,
+        nbytes: usize,
+        reload: bool,
+    ) {
+        self.i2c.i2c.cr2().modify(|_, w| {
+            match addr {
+                Address::Seven(addr) => {
+                    w.add10().clear_bit();
+                    w.sadd().set(u16::from(addr) << 1);
+                }
+                Address::Ten(addr) => {
+                    w.add10().set_bit();
+                    w.head10r().bit(read && !first_transaction);
+                    w.sadd().set(addr);
+                }
+            }
+            w.rd_wrn().bit(read);
+            w.nbytes().set(nbytes as u8);
+            w.reload().bit(reload);
+            w.autoend().clear_bit();
+            w.start().set_bit()
+        });
+    }
+
+    /// Continues the transfer with the next `nbytes` after TCR
+    fn reload(&self, nbytes: usize, reload: bool) {
+        self.i2c.i2c.cr2().modify(|_, w| {
+            w.nbytes().set(nbytes as u8);
+            w.reload().bit(reload)
+        });
+    }
+
+    /// Sends all write buffers of `group` and waits until the last byte is transmitted
+    async fn write_group(
+        &mut self,
+        addr: Address,
+        group: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let mut remaining: usize = group.iter().map(op_len).sum();
+        if remaining == 0 {
+            self.start(addr, false, true, 0, false);
+            return self
+                .i2c
+                .wait_for(Wait::Complete)
+                .await
+                .map_err(Error::nack_addr);
+        }
+
+        let mut started = false;
+        for op in group.iter() {
+            let Operation::Write(bytes) = op else {
+                unreachable!()
+            };
+            for chunk in bytes.chunks(MAX_CHUNK) {
+                remaining -= chunk.len();
+                if started {
+                    self.reload(chunk.len(), remaining > 0);
+                } else {
+                    self.start(addr, false, true, chunk.len(), remaining > 0);
+                    started = true;
+                    // TXIS is only set once the address is acknowledged
+                    self.i2c
+                        .wait_for(Wait::Transmit)
+                        .await
+                        .map_err(Error::nack_addr)?;
+                }
+                if TX::DMA {
+                    self.tx
+                        .transfer(&self.i2c, chunk.as_ptr() as *mut u8, chunk.len() as u16)
+                        .await
+                        .map_err(Error::nack_data)?;
+                } else {
+                    for &byte in chunk {
+                        self.i2c
+                            .wait_for(Wait::Transmit)
+                            .await
+                            .map_err(Error::nack_data)?;
+                        self.i2c
+                            .i2c
+                            .txdr()
+                            .write(|w| unsafe { w.txdata().bits(byte) });
+                    }
+                }
+                self.i2c
+                    .wait_for(Wait::Complete)
+                    .await
+                    .map_err(Error::nack_data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives all read buffers of `group` as one transfer of at least one byte
+    async fn read_group(
+        &mut self,
+        addr: Address,
+        group: &mut [Operation<'_>],
+        first_transaction: bool,
+    ) -> Result<(), Error> {
+        let mut remaining: usize = group.iter().map(op_len).sum();
+
+        let mut started = false;
+        for op in group.iter_mut() {
+            let Operation::Read(buffer) = op else {
+                unreachable!()
+            };
+            for chunk in buffer.chunks_mut(MAX_CHUNK) {
+                remaining -= chunk.len();
+                if started {
+                    self.reload(chunk.len(), remaining > 0);
+                } else {
+                    self.start(addr, true, first_transaction, chunk.len(), remaining > 0);
+                    started = true;
+                }
+                if RX::DMA {
+                    self.rx
+                        .transfer(&self.i2c, chunk.as_mut_ptr(), chunk.len() as u16)
+                        .await
+                        .map_err(Error::nack_addr)?;
+                } else {
+                    for byte in chunk.iter_mut() {
+                        self.i2c
+                            .wait_for(Wait::Receive)
+                            .await
+                            .map_err(Error::nack_addr)?;
+                        *byte = self.i2c.i2c.rxdr().read().rxdata().bits();
+                    }
+                }
+                self.i2c.wait_for(Wait::Complete).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Empty reads are skipped, they do not use the bus
+fn is_empty_read(op: &Operation<'_>) -> bool {
+    matches!(op, Operation::Read(buffer) if buffer.is_empty())
+}
+
+fn op_len(op: &Operation<'_>) -> usize {
+    match op {
+        Operation::Read(buffer) => buffer.len(),
+        Operation::Write(bytes) => bytes.len(),
+    }
+}
+
+impl<I2C: Instance, TX, RX> ErrorType for I2cAsync<I2C, TX, RX> {
+    type Error = Error;
+}
+
+impl<I2C, TX, RX> embedded_hal_async::i2c::I2c for I2cAsync<I2C, TX, RX>
+where
+    I2C: Instance,
+    TX: AsyncDma<I2C, MemoryToPeripheral>,
+    RX: AsyncDma<I2C, PeripheralToMemory>,
+{
+    async fn transaction(
+        &mut self,
+        addr: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transaction(addr, operations).await
+    }
+}
+
+impl<I2C, TX, RX> embedded_hal_async::i2c::I2c<TenBitAddress> for I2cAsync<I2C, TX, RX>
+where
+    I2C: Instance,
+    TX: AsyncDma<I2C, MemoryToPeripheral>,
+    RX: AsyncDma<I2C, PeripheralToMemory>,
+{
+    async fn transaction(
+        &mut self,
+        addr: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transaction(addr, operations).await
+    }
+}
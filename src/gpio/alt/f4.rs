@@ -113,6 +113,19 @@ pub mod can3 {
     }
 }
 
+#[cfg(feature = "cec")]
+pub mod cec {
+    use super::*;
+
+    pin! {
+        <Cec, OpenDrain> for [
+            PA15<4>,
+
+            PB6<3>,
+        ],
+    }
+}
+
 #[cfg(any(
     feature = "gpio-f417",
     feature = "gpio-f427",
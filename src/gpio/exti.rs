@@ -4,6 +4,11 @@ use crate::{
     syscfg::SysCfg,
 };
 
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_exti_interrupt, ExtiInput};
+
 impl<const P: char, const N: u8, MODE> Pin<P, N, MODE> {
     /// NVIC interrupt number of interrupt from this pin
     ///
@@ -0,0 +1,172 @@
+//! Async waiting for pin edges and levels with [`ExtiInput`]
+//!
+//! [`gpio::on_exti_interrupt`](on_exti_interrupt) must be called from the `EXTI0`..`EXTI4`,
+//! `EXTI9_5` and `EXTI15_10` interrupt handlers of the lines in use.
+
+use core::{future::poll_fn, task::Poll};
+
+use atomic_waker::AtomicWaker;
+use embedded_hal::digital::{ErrorType, InputPin};
+
+use super::super::{Edge, PinExt};
+use super::ExtiPin;
+use crate::{pac::EXTI, syscfg::SysCfg};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_WAKER: AtomicWaker = AtomicWaker::new();
+static WAKERS: [AtomicWaker; 16] = [NEW_WAKER; 16];
+
+/// Wakes the tasks waiting for edges on EXTI lines 0 to 15.
+///
+/// Must be called from every `EXTI0`..`EXTI4`, `EXTI9_5` and `EXTI15_10` interrupt handler
+/// used by an [`ExtiInput`]:
+///
+/// ```ignore
+/// #[interrupt]
+/// fn EXTI15_10() {
+///     gpio::on_exti_interrupt();
+/// }
+/// ```
+///
+/// Pending lines are masked and left pending for the woken task, other lines are not touched.
+pub fn on_exti_interrupt() {
+    let exti = unsafe { &*EXTI::ptr() };
+    let pending = cortex_m::interrupt::free(|_| {
+        let pending = exti.pr().read().bits() & exti.imr().read().bits() & 0xffff;
+        exti.imr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !pending) });
+        pending
+    });
+    for (line, waker) in WAKERS.iter().enumerate() {
+        if pending & (1 << line) != 0 {
+            waker.wake();
+        }
+    }
+}
+
+/// Input pin which waits for edges and levels with EXTI interrupts
+///
+/// Implements [`embedded_hal_async::digital::Wait`]. A client must enable the `EXTIx`
+/// interrupt of the pin (see [`Pin::interrupt`](super::super::Pin::interrupt)) and call
+/// [`on_exti_interrupt`] in it.
+///
+/// Only one pin can use an EXTI line, the line of another pin with the same number is taken over.
+pub struct ExtiInput<PIN> {
+    pin: PIN,
+}
+
+impl<PIN> ExtiInput<PIN>
+where
+    PIN: ExtiPin + PinExt + InputPin,
+{
+    /// Makes the EXTI line sensitive to `pin`
+    pub fn new(mut pin: PIN, syscfg: &mut SysCfg) -> Self {
+        pin.make_interrupt_source(syscfg);
+        Self { pin }
+    }
+
+    /// Masks the EXTI line and returns the pin
+    pub fn release(self) -> PIN {
+        mask(1 << self.pin.pin_id());
+        self.pin
+    }
+
+    /// Waits for `edge` or until the pin is at `level` if given
+    async fn wait(&mut self, edge: Edge, level: Option<bool>) -> Result<(), PIN::Error> {
+        let line = self.pin.pin_id() as usize;
+        let bit = 1 << line;
+        let exti = unsafe { &*EXTI::ptr() };
+        let (rising, falling) = match edge {
+            Edge::Rising => (bit, 0),
+            Edge::Falling => (0, bit),
+            Edge::RisingFalling => (bit, bit),
+        };
+        cortex_m::interrupt::free(|_| {
+            exti.rtsr()
+                .modify(|r, w| unsafe { w.bits((r.bits() & !bit) | rising) });
+            exti.ftsr()
+                .modify(|r, w| unsafe { w.bits((r.bits() & !bit) | falling) });
+        });
+        exti.pr().write(|w| unsafe { w.bits(bit) });
+
+        let _mask = MaskOnDrop(bit);
+        poll_fn(|cx| {
+            WAKERS[line].register(cx.waker());
+            if exti.pr().read().bits() & bit != 0 {
+                exti.pr().write(|w| unsafe { w.bits(bit) });
+                return Poll::Ready(Ok(()));
+            }
+            // Level is read after the trigger is armed, so a following edge is not missed
+            if let Some(high) = level {
+                match self.pin.is_high() {
+                    Ok(is_high) if is_high == high => return Poll::Ready(Ok(())),
+                    Err(e) => return Poll::Ready(Err(e)),
+                    _ => {}
+                }
+            }
+            cortex_m::interrupt::free(|_| {
+                exti.imr().modify(|r, w| unsafe { w.bits(r.bits() | bit) });
+            });
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+fn mask(bit: u32) {
+    let exti = unsafe { &*EXTI::ptr() };
+    cortex_m::interrupt::free(|_| {
+        exti.imr().modify(|r, w| unsafe { w.bits(r.bits() & !bit) });
+    });
+}
+
+/// Masks the EXTI line when `ExtiInput::wait` returns or its future is dropped
+struct MaskOnDrop(u32);
+
+impl Drop for MaskOnDrop {
+    fn drop(&mut self) {
+        mask(self.0);
+    }
+}
+
+impl<PIN: ErrorType> ErrorType for ExtiInput<PIN> {
+    type Error = PIN::Error;
+}
+
+impl<PIN> InputPin for ExtiInput<PIN>
+where
+    PIN: ExtiPin + PinExt + InputPin,
+{
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_low()
+    }
+}
+
+impl<PIN> embedded_hal_async::digital::Wait for ExtiInput<PIN>
+where
+    PIN: ExtiPin + PinExt + InputPin,
+{
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait(Edge::Rising, Some(true)).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait(Edge::Falling, Some(false)).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait(Edge::Rising, None).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait(Edge::Falling, None).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait(Edge::RisingFalling, None).await
+    }
+}
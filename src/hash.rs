@@ -0,0 +1,473 @@
+//! Hash processor (HASH)
+//!
+//! Computes SHA-1 and MD5 digests, and SHA-224 and SHA-256 on STM32F43x and STM32F479,
+//! optionally as HMAC with a key.
+//!
+//! Every message has its own [`Context`], which holds the state of the processor between
+//! calls, so several messages can be hashed interleaved:
+//!
+//! ```ignore
+//! let mut hash = Hash::new(dp.HASH, &mut rcc);
+//!
+//! let mut image = hash.start(Algorithm::Sha256);
+//! let mut mac = hash.start_hmac(Algorithm::Sha1, &key);
+//! hash.update(&mut image, &header);
+//! hash.update(&mut mac, &message);
+//! hash.update(&mut image, &body);
+//!
+//! let mut digest = [0; 32];
+//! hash.finalize(image, &mut digest);
+//! ```
+//!
+//! With the `async` feature, [`Hash::into_async`] feeds large buffers with DMA2 stream 7.
+//!
+//! With the `digest` feature, [`Hasher`] implements the `Update`, `FixedOutput` and `Reset`
+//! traits of the `digest` crate, and [`Hmac`] also implements `Mac`:
+//!
+//! ```ignore
+//! use digest::{FixedOutput, Mac, Update};
+//!
+//! let hash = Hash::new(dp.HASH, &mut rcc);
+//! let mut sha = Hasher::<Sha256>::new(&hash);
+//! sha.update(b"message");
+//! let digest = sha.finalize_fixed();
+//!
+//! let mut mac = Hmac::<Sha1>::new(&hash, &key);
+//! mac.update(b"message");
+//! mac.verify_slice(&received)?;
+//! ```
+
+use crate::pac::HASH;
+use crate::rcc::{Enable, Rcc, Reset};
+#[cfg(feature = "digest")]
+use core::marker::PhantomData;
+#[cfg(feature = "digest")]
+use digest::{
+    generic_array::ArrayLength, FixedOutput, FixedOutputReset, HashMarker, MacMarker, Output,
+    OutputSizeUser, Update,
+};
+
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::HashAsync;
+
+/// Bytes of a block processed at once
+const BLOCK: usize = 64;
+
+/// Context swap registers
+const CSR_COUNT: usize = 54;
+
+/// `DATATYPE` of byte strings, the processor swaps the bytes of every word
+const DATATYPE_BYTES: u8 = 0b10;
+
+/// Hash algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Algorithm {
+    Sha1,
+    Md5,
+    #[cfg(not(feature = "gpio-f417"))]
+    Sha224,
+    #[cfg(not(feature = "gpio-f417"))]
+    Sha256,
+}
+
+impl Algorithm {
+    /// Length of the digest in bytes
+    pub const fn digest_len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Md5 => 16,
+            #[cfg(not(feature = "gpio-f417"))]
+            Self::Sha224 => 28,
+            #[cfg(not(feature = "gpio-f417"))]
+            Self::Sha256 => 32,
+        }
+    }
+
+    /// `ALGO` field, bit 1 is `ALGO1`
+    const fn bits(self) -> u8 {
+        match self {
+            Self::Sha1 => 0b00,
+            Self::Md5 => 0b01,
+            #[cfg(not(feature = "gpio-f417"))]
+            Self::Sha224 => 0b10,
+            #[cfg(not(feature = "gpio-f417"))]
+            Self::Sha256 => 0b11,
+        }
+    }
+}
+
+/// State of one message
+///
+/// The processor only starts a block once the first word of the next one is written, so
+/// the first 17 words are written together and the rest is written in blocks.
+pub struct Context<'k> {
+    algorithm: Algorithm,
+    key: Option<&'k [u8]>,
+    buffer: [u8; BLOCK + 4],
+    buffer_len: usize,
+    first_sent: bool,
+    imr: u32,
+    str: u32,
+    cr: u32,
+    csr: [u32; CSR_COUNT],
+}
+
+impl Context<'_> {
+    /// Algorithm of the message
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn capacity(&self) -> usize {
+        if self.first_sent {
+            BLOCK
+        } else {
+            BLOCK + 4
+        }
+    }
+}
+
+/// Hash processor
+pub struct Hash {
+    hash: HASH,
+}
+
+impl Hash {
+    /// Enables the clock of the processor and resets it
+    pub fn new(hash: HASH, rcc: &mut Rcc) -> Self {
+        HASH::enable(rcc);
+        HASH::reset(rcc);
+        Self { hash }
+    }
+
+    /// Releases the HASH peripheral
+    pub fn release(self) -> HASH {
+        self.hash
+    }
+
+    /// Starts a message
+    pub fn start(&mut self, algorithm: Algorithm) -> Context<'static> {
+        self.init(algorithm, None)
+    }
+
+    /// Starts a message authenticated with `key`.
+    ///
+    /// The key is hashed again by [`finalize`](Self::finalize).
+    pub fn start_hmac<'k>(&mut self, algorithm: Algorithm, key: &'k [u8]) -> Context<'k> {
+        self.init(algorithm, Some(key))
+    }
+
+    fn init<'k>(&self, algorithm: Algorithm, key: Option<&'k [u8]>) -> Context<'k> {
+        let algo = algorithm.bits();
+        self.hash.cr().write(|w| unsafe {
+            w.datatype().bits(DATATYPE_BYTES);
+            w.algo0().bit(algo & 0b01 != 0);
+            w.algo1().bit(algo & 0b10 != 0);
+            w.mode().bit(key.is_some());
+            w.lkey().bit(matches!(key, Some(key) if key.len() > BLOCK))
+        });
+        self.hash.cr().modify(|_, w| w.init().set_bit());
+        if let Some(key) = key {
+            self.write_last(key);
+        }
+
+        let mut ctx = Context {
+            algorithm,
+            key,
+            buffer: [0; BLOCK + 4],
+            buffer_len: 0,
+            first_sent: false,
+            imr: 0,
+            str: 0,
+            cr: 0,
+            csr: [0; CSR_COUNT],
+        };
+        self.save(&mut ctx);
+        ctx
+    }
+
+    /// Adds `data` to the message of `ctx`
+    pub fn update(&mut self, ctx: &mut Context<'_>, data: &[u8]) {
+        self.process(ctx, data);
+    }
+
+    fn process(&self, ctx: &mut Context<'_>, data: &[u8]) {
+        self.restore(ctx);
+        let data = self.absorb(ctx, data);
+        let blocks = data.len() - data.len() % BLOCK;
+        self.write(&data[..blocks]);
+        self.absorb(ctx, &data[blocks..]);
+        self.save(ctx);
+    }
+
+    /// Ends the message of `ctx`, writes its digest to the start of `digest` and returns
+    /// the digest length.
+    ///
+    /// # Panics
+    ///
+    /// If `digest` is shorter than [`Algorithm::digest_len`].
+    pub fn finalize(&mut self, ctx: Context<'_>, digest: &mut [u8]) -> usize {
+        self.finish(&ctx, digest)
+    }
+
+    fn finish(&self, ctx: &Context<'_>, digest: &mut [u8]) -> usize {
+        let len = ctx.algorithm.digest_len();
+        assert!(digest.len() >= len);
+
+        self.restore(ctx);
+        self.write_last(&ctx.buffer[..ctx.buffer_len]);
+        if let Some(key) = ctx.key {
+            while self.hash.sr().read().dinis().bit_is_clear() {}
+            self.write_last(key);
+        }
+        while self.hash.sr().read().dcis().bit_is_clear() {}
+
+        for (i, bytes) in digest[..len].chunks_exact_mut(4).enumerate() {
+            let word = if i < 5 {
+                self.hash.hr(i).read().bits()
+            } else {
+                self.hash.hash_hr(i).read().bits()
+            };
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        len
+    }
+
+    /// Computes the digest of `data` in a single message, see [`finalize`](Self::finalize)
+    pub fn digest(&mut self, algorithm: Algorithm, data: &[u8], digest: &mut [u8]) -> usize {
+        let mut ctx = self.start(algorithm);
+        self.update(&mut ctx, data);
+        self.finalize(ctx, digest)
+    }
+
+    /// Writes whole words
+    fn write(&self, data: &[u8]) {
+        for word in data.chunks_exact(4) {
+            self.hash
+                .din()
+                .write(|w| unsafe { w.bits(u32::from_le_bytes(word.try_into().unwrap())) });
+        }
+    }
+
+    /// Writes the end of a message and starts the digest calculation
+    fn write_last(&self, data: &[u8]) {
+        self.hash
+            .str()
+            .write(|w| unsafe { w.nblw().bits((data.len() % 4 * 8) as u8) });
+        let words = data.len() - data.len() % 4;
+        self.write(&data[..words]);
+        if words != data.len() {
+            let mut word = [0; 4];
+            word[..data.len() - words].copy_from_slice(&data[words..]);
+            self.write(&word);
+        }
+        self.hash.str().modify(|_, w| w.dcal().set_bit());
+    }
+
+    /// Writes `data` through the buffer of `ctx` until whole blocks can be written
+    /// directly, returns them and buffers the rest
+    fn absorb<'d>(&self, ctx: &mut Context<'_>, mut data: &'d [u8]) -> &'d [u8] {
+        // Blocks are written directly once the buffer is empty
+        while !(data.is_empty() || ctx.first_sent && ctx.buffer_len == 0 && data.len() >= BLOCK) {
+            let capacity = ctx.capacity();
+            let n = data.len().min(capacity - ctx.buffer_len);
+            ctx.buffer[ctx.buffer_len..ctx.buffer_len + n].copy_from_slice(&data[..n]);
+            ctx.buffer_len += n;
+            data = &data[n..];
+            if ctx.buffer_len == capacity {
+                self.write(&ctx.buffer[..capacity]);
+                ctx.buffer_len = 0;
+                ctx.first_sent = true;
+            }
+        }
+        data
+    }
+
+    /// Saves the state of the processor once the written blocks are processed
+    fn save(&self, ctx: &mut Context<'_>) {
+        while self.hash.sr().read().dinis().bit_is_clear() {}
+        ctx.imr = self.hash.imr().read().bits();
+        ctx.str = self.hash.str().read().bits();
+        ctx.cr = self.hash.cr().read().bits();
+        for (i, csr) in ctx.csr.iter_mut().enumerate() {
+            *csr = self.hash.csr(i).read().bits();
+        }
+    }
+
+    fn restore(&self, ctx: &Context<'_>) {
+        self.hash.imr().write(|w| unsafe { w.bits(ctx.imr) });
+        self.hash.str().write(|w| unsafe { w.bits(ctx.str) });
+        self.hash.cr().write(|w| unsafe { w.bits(ctx.cr) });
+        self.hash.cr().modify(|_, w| w.init().set_bit());
+        for (i, csr) in ctx.csr.iter().enumerate() {
+            self.hash.csr(i).write(|w| unsafe { w.bits(*csr) });
+        }
+    }
+}
+
+/// Algorithm of a [`Hasher`] or an [`Hmac`]
+#[cfg(feature = "digest")]
+pub trait DigestAlgorithm: crate::Sealed {
+    #[doc(hidden)]
+    const ALGORITHM: Algorithm;
+    /// Digest length
+    type OutputSize: ArrayLength<u8> + 'static;
+}
+
+#[cfg(feature = "digest")]
+macro_rules! digest_algorithm {
+    ($($(#[$attr:meta])* $Algorithm:ident, $OutputSize:ident;)*) => {
+        $(
+            $(#[$attr])*
+            pub struct $Algorithm;
+
+            impl crate::Sealed for $Algorithm {}
+
+            impl DigestAlgorithm for $Algorithm {
+                const ALGORITHM: Algorithm = Algorithm::$Algorithm;
+                type OutputSize = digest::consts::$OutputSize;
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "digest")]
+digest_algorithm! {
+    /// SHA-1 for [`Hasher`] and [`Hmac`]
+    Sha1, U20;
+    /// MD5 for [`Hasher`] and [`Hmac`]
+    Md5, U16;
+}
+
+#[cfg(all(feature = "digest", not(feature = "gpio-f417")))]
+digest_algorithm! {
+    /// SHA-224 for [`Hasher`] and [`Hmac`]
+    Sha224, U28;
+    /// SHA-256 for [`Hasher`] and [`Hmac`]
+    Sha256, U32;
+}
+
+/// A message hashed with `A`, implements the `Update`, `FixedOutput`, `FixedOutputReset`
+/// and `Reset` traits of the `digest` crate
+///
+/// The processor is borrowed, several messages can be hashed interleaved.
+#[cfg(feature = "digest")]
+pub struct Hasher<'h, A> {
+    hash: &'h Hash,
+    ctx: Context<'static>,
+    _algorithm: PhantomData<A>,
+}
+
+#[cfg(feature = "digest")]
+impl<'h, A: DigestAlgorithm> Hasher<'h, A> {
+    /// Starts a message
+    pub fn new(hash: &'h Hash) -> Self {
+        Self {
+            hash,
+            ctx: hash.init(A::ALGORITHM, None),
+            _algorithm: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> OutputSizeUser for Hasher<'_, A> {
+    type OutputSize = A::OutputSize;
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> HashMarker for Hasher<'_, A> {}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> Update for Hasher<'_, A> {
+    fn update(&mut self, data: &[u8]) {
+        self.hash.process(&mut self.ctx, data);
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> FixedOutput for Hasher<'_, A> {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        self.hash.finish(&self.ctx, out);
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> digest::Reset for Hasher<'_, A> {
+    fn reset(&mut self) {
+        self.ctx = self.hash.init(A::ALGORITHM, None);
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> FixedOutputReset for Hasher<'_, A> {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        self.hash.finish(&self.ctx, out);
+        digest::Reset::reset(self);
+    }
+}
+
+/// A message authenticated with `A` and a key, implements the `Mac` trait of the `digest`
+/// crate
+///
+/// The processor is borrowed, several messages can be authenticated interleaved.
+#[cfg(feature = "digest")]
+pub struct Hmac<'h, 'k, A> {
+    hash: &'h Hash,
+    ctx: Context<'k>,
+    key: &'k [u8],
+    _algorithm: PhantomData<A>,
+}
+
+#[cfg(feature = "digest")]
+impl<'h, 'k, A: DigestAlgorithm> Hmac<'h, 'k, A> {
+    /// Starts a message authenticated with `key`
+    pub fn new(hash: &'h Hash, key: &'k [u8]) -> Self {
+        Self {
+            hash,
+            ctx: hash.init(A::ALGORITHM, Some(key)),
+            key,
+            _algorithm: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> OutputSizeUser for Hmac<'_, '_, A> {
+    type OutputSize = A::OutputSize;
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> MacMarker for Hmac<'_, '_, A> {}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> Update for Hmac<'_, '_, A> {
+    fn update(&mut self, data: &[u8]) {
+        self.hash.process(&mut self.ctx, data);
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> FixedOutput for Hmac<'_, '_, A> {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        self.hash.finish(&self.ctx, out);
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> digest::Reset for Hmac<'_, '_, A> {
+    fn reset(&mut self) {
+        self.ctx = self.hash.init(A::ALGORITHM, Some(self.key));
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<A: DigestAlgorithm> FixedOutputReset for Hmac<'_, '_, A> {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        self.hash.finish(&self.ctx, out);
+        digest::Reset::reset(self);
+    }
+}
@@ -0,0 +1,115 @@
+//! Async message feeding with DMA.
+//!
+//! Transfers are awaited with the interrupt of DMA2 stream 7, which must call
+//! [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt):
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn DMA2_STREAM7() {
+//!     dma::Stream7::<pac::DMA2>::on_interrupt();
+//! }
+//! ```
+//!
+//! Data that does not fill a block is still written by the CPU.
+
+use core::ops::{Deref, DerefMut};
+
+use super::{Context, Hash, BLOCK};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DMAError, DmaDirection, MemoryToPeripheral, OneShot,
+};
+use crate::pac::HASH;
+
+/// Most bytes of one DMA transfer, a whole number of blocks
+const MAX_BYTES: usize = 0xFFC0 * 4;
+
+impl Hash {
+    /// Converts [`Hash`] to [`HashAsync`] that feeds messages with `stream`
+    pub fn into_async<STREAM, const CH: u8>(self, stream: STREAM) -> HashAsync<STREAM, CH>
+    where
+        STREAM: Stream,
+        ChannelX<CH>: Channel,
+        HASH: DMASet<STREAM, CH, MemoryToPeripheral>,
+    {
+        HashAsync { hash: self, stream }
+    }
+}
+
+/// Hash processor that feeds messages with DMA
+///
+/// Derefs to [`Hash`], so the blocking API stays available. A client must enable the
+/// interrupt of the DMA stream and call
+/// [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in it.
+pub struct HashAsync<STREAM, const CH: u8> {
+    hash: Hash,
+    stream: STREAM,
+}
+
+impl<STREAM, const CH: u8> HashAsync<STREAM, CH>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Returns the [`Hash`] and the DMA stream
+    pub fn release(self) -> (Hash, STREAM) {
+        (self.hash, self.stream)
+    }
+
+    /// Adds `data` to the message of `ctx`, whole blocks are written with DMA.
+    ///
+    /// The message of `ctx` can't be used any more after an error.
+    pub async fn update(&mut self, ctx: &mut Context<'_>, data: &[u8]) -> Result<(), DMAError<()>> {
+        self.hash.restore(ctx);
+        let data = self.hash.absorb(ctx, data);
+        let blocks = data.len() - data.len() % BLOCK;
+
+        let hash = &self.hash.hash;
+        // Further transfers follow, the digest is not calculated at the end of one
+        hash.cr().modify(|_, w| {
+            w.dmae().set_bit();
+            w.mdmat().set_bit()
+        });
+        let mut result = Ok(());
+        for chunk in data[..blocks].chunks(MAX_BYTES) {
+            // NOTE(unsafe) the data outlives the transfer which is stopped on drop
+            let mut transfer = unsafe {
+                OneShot::start_packed(
+                    &mut self.stream,
+                    ChannelX::<CH>::VALUE,
+                    DmaDirection::MemoryToPeripheral,
+                    hash.din().as_ptr() as u32,
+                    chunk.as_ptr(),
+                    (chunk.len() / 4) as u16,
+                )
+            };
+            result = transfer.wait().await;
+            if result.is_err() {
+                break;
+            }
+        }
+        hash.cr().modify(|_, w| {
+            w.dmae().clear_bit();
+            w.mdmat().clear_bit()
+        });
+        result?;
+
+        self.hash.absorb(ctx, &data[blocks..]);
+        self.hash.save(ctx);
+        Ok(())
+    }
+}
+
+impl<STREAM, const CH: u8> Deref for HashAsync<STREAM, CH> {
+    type Target = Hash;
+
+    fn deref(&self) -> &Hash {
+        &self.hash
+    }
+}
+
+impl<STREAM, const CH: u8> DerefMut for HashAsync<STREAM, CH> {
+    fn deref_mut(&mut self) -> &mut Hash {
+        &mut self.hash
+    }
+}
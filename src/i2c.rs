@@ -15,7 +15,11 @@ mod hal_1;
 pub use common::{Address, Error, NoAcknowledgeSource};
 use common::{Hal02Operation, Hal1Operation};
 
+#[cfg(feature = "async")]
+mod asynch;
 pub mod dma;
+#[cfg(feature = "async")]
+pub use asynch::{AsyncDma, DmaStream, I2cAsync};
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum DutyCycle {
@@ -82,6 +86,9 @@ pub trait Instance:
     + Reset
     + gpio::alt::I2cCommon
 {
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn waker() -> &'static atomic_waker::AtomicWaker;
 }
 
 // Implemented by all I2C instances
@@ -89,7 +96,13 @@ macro_rules! i2c {
     ($I2C:ty: $I2c:ident) => {
         pub type $I2c = I2c<$I2C>;
 
-        impl Instance for $I2C {}
+        impl Instance for $I2C {
+            #[cfg(feature = "async")]
+            fn waker() -> &'static atomic_waker::AtomicWaker {
+                static WAKER: atomic_waker::AtomicWaker = atomic_waker::AtomicWaker::new();
+                &WAKER
+            }
+        }
     };
 }
 
@@ -0,0 +1,621 @@
+//! Async I2C master driven by the event and error interrupts.
+//!
+//! The task waiting for the bus is woken by [`I2c::on_interrupt`], which must be called from
+//! both `I2Cx_EV` and `I2Cx_ER` interrupt handlers:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn I2C1_EV() {
+//!     i2c::I2c1::on_interrupt();
+//! }
+//!
+//! #[interrupt]
+//! fn I2C1_ER() {
+//!     i2c::I2c1::on_interrupt();
+//! }
+//! ```
+//!
+//! When data is moved by DMA, the interrupts of both streams must also call
+//! [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt).
+
+use core::{future::poll_fn, task::Poll};
+
+use embedded_hal::i2c::{ErrorType, Operation, SevenBitAddress, TenBitAddress};
+
+use super::{
+    dma::{NoDMA, Rx, Tx},
+    Address, Error, I2c, Instance,
+};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use crate::pac::i2c1;
+
+impl<I2C: Instance> I2c<I2C> {
+    /// Wakes the task waiting for the bus.
+    ///
+    /// Must be called from both `I2Cx_EV` and `I2Cx_ER` interrupt handlers when the bus is
+    /// used by [`I2cAsync`]. Interrupts are disabled, the woken task enables them again if it
+    /// still has to wait.
+    pub fn on_interrupt() {
+        let i2c = unsafe { &*I2C::ptr() };
+        i2c.cr2().modify(|_, w| {
+            w.itevten().disabled();
+            w.itbufen().disabled();
+            w.iterren().disabled()
+        });
+        I2C::waker().wake();
+    }
+
+    /// Converts blocking [`I2c`] to [`I2cAsync`] that moves every byte from the interrupts
+    pub fn into_async(self) -> I2cAsync<I2C> {
+        I2cAsync {
+            i2c: self,
+            tx: NoDMA,
+            rx: NoDMA,
+        }
+    }
+
+    /// Converts blocking [`I2c`] to [`I2cAsync`] that uses `tx_stream` and `rx_stream` to send/receive data
+    pub fn into_async_dma<TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8>(
+        self,
+        tx_stream: TX_STREAM,
+        rx_stream: RX_STREAM,
+    ) -> I2cAsync<I2C, DmaStream<TX_STREAM, TX_CH>, DmaStream<RX_STREAM, RX_CH>>
+    where
+        TX_STREAM: Stream,
+        ChannelX<TX_CH>: Channel,
+        Tx<I2C>: DMASet<TX_STREAM, TX_CH, MemoryToPeripheral>,
+
+        RX_STREAM: Stream,
+        ChannelX<RX_CH>: Channel,
+        Rx<I2C>: DMASet<RX_STREAM, RX_CH, PeripheralToMemory>,
+    {
+        I2cAsync {
+            i2c: self,
+            tx: DmaStream(tx_stream),
+            rx: DmaStream(rx_stream),
+        }
+    }
+
+    fn disable_interrupts(&self) {
+        self.i2c.cr2().modify(|_, w| {
+            w.itevten().disabled();
+            w.itbufen().disabled();
+            w.iterren().disabled()
+        });
+    }
+
+    /// Waits until `f` returns `true` for the status register or an error is flagged.
+    /// `buffer` also wakes the task on TXE and RXNE.
+    async fn wait_for(&self, buffer: bool, f: impl Fn(&i2c1::sr1::R) -> bool) -> Result<(), Error> {
+        poll_fn(|cx| {
+            I2C::waker().register(cx.waker());
+            match self.check_and_clear_error_flags() {
+                Ok(sr1) if !f(&sr1) => {
+                    self.i2c.cr2().modify(|_, w| {
+                        w.itevten().enabled();
+                        w.itbufen().bit(buffer);
+                        w.iterren().enabled()
+                    });
+                    Poll::Pending
+                }
+                result => {
+                    self.disable_interrupts();
+                    Poll::Ready(result.map(|_| ()))
+                }
+            }
+        })
+        .await
+    }
+
+    /// Waits for the end of a DMA `transfer` or an error on the bus
+    async fn wait_dma<STREAM: Stream>(
+        &self,
+        transfer: &mut OneShot<'_, STREAM>,
+    ) -> Result<(), Error> {
+        poll_fn(|cx| {
+            I2C::waker().register(cx.waker());
+            if let Err(e) = self.check_and_clear_error_flags() {
+                self.disable_interrupts();
+                return Poll::Ready(Err(e));
+            }
+            match transfer.poll(cx) {
+                Poll::Ready(result) => {
+                    self.disable_interrupts();
+                    Poll::Ready(result.map_err(|_| Error::Dma))
+                }
+                Poll::Pending => {
+                    self.i2c.cr2().modify(|_, w| w.iterren().enabled());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Clears ADDR by reading SR1 and SR2
+    fn clear_addr(&self) {
+        self.i2c.sr1().read();
+        self.i2c.sr2().read();
+    }
+
+    /// Sends the address for writing after START, ADDR is cleared
+    async fn address_write(&self, addr: Address) -> Result<(), Error> {
+        self.wait_for(false, |sr1| sr1.sb().bit_is_set()).await?;
+        match addr {
+            Address::Seven(addr) => {
+                self.i2c
+                    .dr()
+                    .write(|w| unsafe { w.bits(u16::from(addr) << 1) });
+            }
+            Address::Ten(addr) => {
+                let [msbs, lsbs] = addr.to_be_bytes();
+                self.i2c
+                    .dr()
+                    .write(|w| unsafe { w.bits(u16::from(0xf0 | ((msbs & 0b11) << 1))) });
+                self.wait_for(false, |sr1| sr1.add10().bit_is_set())
+                    .await
+                    .map_err(Error::nack_addr)?;
+                self.i2c.dr().write(|w| unsafe { w.bits(u16::from(lsbs)) });
+            }
+        }
+        self.wait_for(false, |sr1| sr1.addr().bit_is_set())
+            .await
+            .map_err(Error::nack_addr)?;
+        self.clear_addr();
+        Ok(())
+    }
+
+    /// Sends the address for reading after START. ADDR is left set because the receiver
+    /// must be configured before it is cleared.
+    ///
+    /// A 10-bit address is sent for writing first unless the slave was `addressed` earlier
+    /// in the transaction.
+    async fn address_read(&self, addr: Address, addressed: bool) -> Result<(), Error> {
+        let header = match addr {
+            Address::Seven(addr) => (addr << 1) | 1,
+            Address::Ten(addr) => {
+                if !addressed {
+                    self.address_write(addr.into()).await?;
+                    self.i2c.cr1().modify(|_, w| w.start().set_bit());
+                }
+                0xf0 | (((addr >> 8) as u8 & 0b11) << 1) | 1
+            }
+        };
+        self.wait_for(false, |sr1| sr1.sb().bit_is_set()).await?;
+        self.i2c
+            .dr()
+            .write(|w| unsafe { w.bits(u16::from(header)) });
+        self.wait_for(false, |sr1| sr1.addr().bit_is_set())
+            .await
+            .map_err(Error::nack_addr)
+    }
+
+    /// Generates STOP at the end of the transaction or a repeated START otherwise
+    fn end(&self, stop: bool) {
+        if stop {
+            self.i2c.cr1().modify(|_, w| w.stop().set_bit());
+        } else {
+            self.i2c.cr1().modify(|_, w| w.start().set_bit());
+        }
+    }
+
+    /// Releases the bus after an error
+    fn abort(&self) {
+        self.disable_interrupts();
+        self.i2c.cr2().modify(|_, w| {
+            w.dmaen().disabled();
+            w.last().not_last()
+        });
+        self.i2c.cr1().modify(|_, w| {
+            w.pos().clear_bit();
+            w.stop().set_bit()
+        });
+    }
+}
+
+/// Stream and channel used by [`I2cAsync`] to move data with DMA
+pub struct DmaStream<STREAM, const CH: u8>(STREAM);
+
+impl<STREAM, const CH: u8> DmaStream<STREAM, CH> {
+    /// Returns the DMA stream
+    pub fn release(self) -> STREAM {
+        self.0
+    }
+}
+
+impl<STREAM, const CH: u8> crate::Sealed for DmaStream<STREAM, CH> {}
+impl crate::Sealed for NoDMA {}
+
+/// Data transfer of [`I2cAsync`] in one direction, by DMA with [`DmaStream`] or by
+/// interrupts with [`NoDMA`]
+pub trait AsyncDma<I2C: Instance, DIR>: crate::Sealed {
+    #[doc(hidden)]
+    const DMA: bool;
+
+    #[doc(hidden)]
+    #[allow(async_fn_in_trait)]
+    async fn transfer(&mut self, i2c: &I2c<I2C>, buf: *mut u8, len: u16) -> Result<(), Error>;
+}
+
+impl<I2C: Instance, DIR> AsyncDma<I2C, DIR> for NoDMA {
+    const DMA: bool = false;
+
+    async fn transfer(&mut self, _: &I2c<I2C>, _: *mut u8, _: u16) -> Result<(), Error> {
+        unreachable!()
+    }
+}
+
+impl<I2C: Instance, STREAM, const CH: u8> AsyncDma<I2C, MemoryToPeripheral>
+    for DmaStream<STREAM, CH>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    Tx<I2C>: DMASet<STREAM, CH, MemoryToPeripheral>,
+{
+    const DMA: bool = true;
+
+    async fn transfer(&mut self, i2c: &I2c<I2C>, buf: *mut u8, len: u16) -> Result<(), Error> {
+        let dr = i2c.i2c.dr().as_ptr() as u32;
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.0,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::MemoryToPeripheral,
+                dr,
+                buf as *const u8,
+                len,
+                true,
+            )
+        };
+        i2c.wait_dma(&mut transfer).await
+    }
+}
+
+impl<I2C: Instance, STREAM, const CH: u8> AsyncDma<I2C, PeripheralToMemory>
+    for DmaStream<STREAM, CH>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    Rx<I2C>: DMASet<STREAM, CH, PeripheralToMemory>,
+{
+    const DMA: bool = true;
+
+    async fn transfer(&mut self, i2c: &I2c<I2C>, buf: *mut u8, len: u16) -> Result<(), Error> {
+        let dr = i2c.i2c.dr().as_ptr() as u32;
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.0,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::PeripheralToMemory,
+                dr,
+                buf as *const u8,
+                len,
+                true,
+            )
+        };
+        i2c.wait_dma(&mut transfer).await
+    }
+}
+
+/// I2c in Master mode that waits for the bus with interrupts
+///
+/// Implements [`embedded_hal_async::i2c::I2c`] with 7 and 10-bit addresses. Bytes are
+/// moved by the event interrupt unless DMA streams are given to
+/// [`I2c::into_async_dma`]. A client must:
+/// * Enable interrupts I2Cx_EV and I2Cx_ER and call [`I2c::on_interrupt`] in both of them.
+/// * With DMA, enable interrupts of both streams and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in them.
+///
+/// Dropping a pending transaction leaves the bus in an undefined state.
+pub struct I2cAsync<I2C: Instance, TX = NoDMA, RX = NoDMA> {
+    i2c: I2c<I2C>,
+    tx: TX,
+    rx: RX,
+}
+
+impl<I2C, TX, RX> I2cAsync<I2C, TX, RX>
+where
+    I2C: Instance,
+    TX: AsyncDma<I2C, MemoryToPeripheral>,
+    RX: AsyncDma<I2C, PeripheralToMemory>,
+{
+    /// Returns the blocking [`I2c`] and the DMA streams
+    pub fn release(self) -> (I2c<I2C>, TX, RX) {
+        (self.i2c, self.tx, self.rx)
+    }
+
+    pub async fn write(&mut self, addr: impl Into<Address>, bytes: &[u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Write(bytes)]).await
+    }
+
+    /// Reads `buffer`, an empty buffer returns `Ok(())` without using the bus
+    pub async fn read(&mut self, addr: impl Into<Address>, buffer: &mut [u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Read(buffer)]).await
+    }
+
+    pub async fn write_read(
+        &mut self,
+        addr: impl Into<Address>,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        self.transaction(
+            addr,
+            &mut [Operation::Write(bytes), Operation::Read(buffer)],
+        )
+        .await
+    }
+
+    /// Executes `operations` as one transaction.
+    ///
+    /// Adjacent operations of the same type are merged, a repeated START is only sent
+    /// when the type changes. Empty reads are skipped. The transaction ends with STOP, also
+    /// on errors.
+    pub async fn transaction(
+        &mut self,
+        addr: impl Into<Address>,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let result = self.transaction_inner(addr.into(), operations).await;
+        if result.is_err() {
+            self.i2c.abort();
+        }
+        result
+    }
+
+    async fn transaction_inner(
+        &mut self,
+        addr: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        // Wait until a previous STOP condition finishes
+        while self.i2c.i2c.cr1().read().stop().bit_is_set() {}
+        // Clear all pending error bits
+        self.i2c.i2c.sr1().write(|w| unsafe { w.bits(0) });
+
+        let mut start_sent = false;
+        let mut first = 0;
+        while first < operations.len() {
+            let read = matches!(operations[first], Operation::Read(_));
+            let end = operations[first..]
+                .iter()
+                .position(|op| matches!(op, Operation::Read(_)) != read)
+                .map_or(operations.len(), |n| first + n);
+            let last = operations[end..].iter().all(is_empty_read);
+            if read && operations[first..end].iter().all(is_empty_read) {
+                first = end;
+                continue;
+            }
+
+            if !start_sent {
+                self.i2c.i2c.cr1().modify(|_, w| w.start().set_bit());
+            }
+            let group = &mut operations[first..end];
+            if read {
+                self.i2c.address_read(addr, first != 0).await?;
+                self.read_group(group, last).await?;
+                // Repeated START for the next write is sent by the receiver
+                start_sent = !last;
+            } else {
+                self.i2c.address_write(addr).await?;
+                self.write_group(group).await?;
+                if last {
+                    self.i2c.end(true);
+                }
+                start_sent = false;
+            }
+            first = end;
+        }
+        Ok(())
+    }
+
+    /// Sends all write buffers of `group` and waits until the last byte is transmitted
+    async fn write_group(&mut self, group: &mut [Operation<'_>]) -> Result<(), Error> {
+        let i2c = &self.i2c;
+        let mut total = 0;
+        if TX::DMA {
+            i2c.i2c.cr2().modify(|_, w| w.dmaen().enabled());
+        }
+        for op in group.iter() {
+            let Operation::Write(bytes) = op else {
+                unreachable!()
+            };
+            total += bytes.len();
+            if TX::DMA {
+                for chunk in bytes.chunks(u16::MAX as usize) {
+                    self.tx
+                        .transfer(i2c, chunk.as_ptr() as *mut u8, chunk.len() as u16)
+                        .await
+                        .map_err(Error::nack_data)?;
+                }
+            } else {
+                for &byte in bytes.iter() {
+                    i2c.wait_for(true, |sr1| sr1.tx_e().bit_is_set())
+                        .await
+                        .map_err(Error::nack_data)?;
+                    i2c.i2c.dr().write(|w| unsafe { w.bits(u16::from(byte)) });
+                }
+            }
+        }
+        if TX::DMA {
+            i2c.i2c.cr2().modify(|_, w| w.dmaen().disabled());
+        }
+        if total > 0 {
+            i2c.wait_for(false, |sr1| sr1.btf().bit_is_set())
+                .await
+                .map_err(Error::nack_data)?;
+        }
+        Ok(())
+    }
+
+    /// Receives all read buffers of `group` as one transfer of at least one byte, ADDR must
+    /// be still set.
+    /// The last byte is NACKed and followed by STOP when `last` or a repeated START otherwise.
+    async fn read_group(&mut self, group: &mut [Operation<'_>], last: bool) -> Result<(), Error> {
+        let len = |op: &Operation<'_>| match op {
+            Operation::Read(buffer) => buffer.len(),
+            Operation::Write(_) => unreachable!(),
+        };
+        let total: usize = group.iter().map(len).sum();
+        // DMA needs at least 2 bytes in the final transfer to NACK the last byte
+        let last_len = group.iter().rev().map(len).find(|&n| n > 0).unwrap_or(0);
+        if RX::DMA && last_len >= 2 {
+            self.read_dma(group, total, last).await
+        } else {
+            self.read_interrupts(group, total, last).await
+        }
+    }
+
+    async fn read_dma(
+        &mut self,
+        group: &mut [Operation<'_>],
+        total: usize,
+        last: bool,
+    ) -> Result<(), Error> {
+        let i2c = &self.i2c;
+        i2c.i2c.cr1().modify(|_, w| w.ack().set_bit());
+        i2c.i2c.cr2().modify(|_, w| w.dmaen().enabled());
+        i2c.clear_addr();
+
+        let mut remaining = total;
+        for op in group.iter_mut() {
+            let Operation::Read(buffer) = op else {
+                unreachable!()
+            };
+            let mut done = 0;
+            while done < buffer.len() {
+                let mut n = (buffer.len() - done).min(u16::MAX as usize);
+                // Keep at least 2 bytes for the final transfer
+                if remaining - n == 1 {
+                    n -= 1;
+                }
+                remaining -= n;
+                if remaining == 0 {
+                    i2c.i2c.cr2().modify(|_, w| w.last().last());
+                }
+                self.rx
+                    .transfer(i2c, buffer[done..].as_mut_ptr(), n as u16)
+                    .await?;
+                done += n;
+            }
+        }
+
+        i2c.end(last);
+        i2c.i2c.cr2().modify(|_, w| {
+            w.dmaen().disabled();
+            w.last().not_last()
+        });
+        Ok(())
+    }
+
+    /// Receives bytes with the sequences of the reference manual which configure NACK while
+    /// the clock is stretched
+    async fn read_interrupts(
+        &mut self,
+        group: &mut [Operation<'_>],
+        total: usize,
+        last: bool,
+    ) -> Result<(), Error> {
+        let i2c = &self.i2c;
+        let read_dr = || i2c.i2c.dr().read().bits() as u8;
+        let rxne = |sr1: &i2c1::sr1::R| sr1.rx_ne().bit_is_set();
+        let btf = |sr1: &i2c1::sr1::R| sr1.btf().bit_is_set();
+
+        match total {
+            1 => {
+                i2c.i2c.cr1().modify(|_, w| w.ack().clear_bit());
+                i2c.clear_addr();
+                i2c.end(last);
+                i2c.wait_for(true, rxne).await?;
+                *byte_at(group, 0) = read_dr();
+            }
+            2 => {
+                i2c.i2c.cr1().modify(|_, w| {
+                    w.pos().set_bit();
+                    w.ack().clear_bit()
+                });
+                i2c.clear_addr();
+                i2c.wait_for(false, btf).await?;
+                i2c.end(last);
+                *byte_at(group, 0) = read_dr();
+                *byte_at(group, 1) = read_dr();
+                i2c.i2c.cr1().modify(|_, w| w.pos().clear_bit());
+            }
+            _ => {
+                i2c.i2c.cr1().modify(|_, w| w.ack().set_bit());
+                i2c.clear_addr();
+                for i in 0..total - 3 {
+                    i2c.wait_for(true, rxne).await?;
+                    *byte_at(group, i) = read_dr();
+                }
+                // DataN-2 in DR, DataN-1 in the shift register
+                i2c.wait_for(false, btf).await?;
+                i2c.i2c.cr1().modify(|_, w| w.ack().clear_bit());
+                *byte_at(group, total - 3) = read_dr();
+                // DataN-1 in DR, DataN in the shift register
+                i2c.wait_for(false, btf).await?;
+                i2c.end(last);
+                *byte_at(group, total - 2) = read_dr();
+                *byte_at(group, total - 1) = read_dr();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Empty reads are skipped, they do not use the bus
+fn is_empty_read(op: &Operation<'_>) -> bool {
+    matches!(op, Operation::Read(buffer) if buffer.is_empty())
+}
+
+/// Byte at `index` of the read buffers of `group` taken as one
+fn byte_at<'a>(group: &'a mut [Operation<'_>], mut index: usize) -> &'a mut u8 {
+    for op in group.iter_mut() {
+        if let Operation::Read(buffer) = op {
+            if index < buffer.len() {
+                return &mut buffer[index];
+            }
+            index -= buffer.len();
+        }
+    }
+    unreachable!()
+}
+
+impl<I2C: Instance, TX, RX> ErrorType for I2cAsync<I2C, TX, RX> {
+    type Error = Error;
+}
+
+impl<I2C, TX, RX> embedded_hal_async::i2c::I2c for I2cAsync<I2C, TX, RX>
+where
+    I2C: Instance,
+    TX: AsyncDma<I2C, MemoryToPeripheral>,
+    RX: AsyncDma<I2C, PeripheralToMemory>,
+{
+    async fn transaction(
+        &mut self,
+        addr: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transaction(addr, operations).await
+    }
+}
+
+impl<I2C, TX, RX> embedded_hal_async::i2c::I2c<TenBitAddress> for I2cAsync<I2C, TX, RX>
+where
+    I2C: Instance,
+    TX: AsyncDma<I2C, MemoryToPeripheral>,
+    RX: AsyncDma<I2C, PeripheralToMemory>,
+{
+    async fn transaction(
+        &mut self,
+        addr: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transaction(addr, operations).await
+    }
+}
@@ -28,6 +28,8 @@ pub enum Error {
     Bus,
     Crc,
     ArbitrationLoss,
+    /// DMA transfer error
+    Dma,
 }
 
 impl Error {
@@ -57,7 +59,7 @@ impl embedded_hal::i2c::Error for Error {
             Self::Bus => ErrorKind::Bus,
             Self::ArbitrationLoss => ErrorKind::ArbitrationLoss,
             Self::NoAcknowledge(nack) => ErrorKind::NoAcknowledge(nack),
-            Self::Crc | Self::Timeout => ErrorKind::Other,
+            Self::Crc | Self::Timeout | Self::Dma => ErrorKind::Other,
         }
     }
 }
@@ -4,6 +4,8 @@
 #![doc = document_features::document_features!()]
 #![no_std]
 #![allow(non_camel_case_types)]
+// The `async` feature requires rust 1.75, see `Cargo.toml`
+#![cfg_attr(feature = "async", allow(clippy::incompatible_msrv))]
 
 use enumflags2::{BitFlag, BitFlags};
 
@@ -88,17 +90,39 @@ use stm32f4::Periph;
 pub use crate::pac::interrupt;
 
 pub mod adc;
+#[cfg(feature = "aes")]
+pub mod aes;
+pub mod backup;
 pub mod bb;
 #[cfg(all(feature = "can", any(feature = "can1", feature = "can2")))]
 pub mod can;
+#[cfg(feature = "cec")]
+pub mod cec;
 pub mod crc32;
+#[cfg(feature = "cryp")]
+pub mod cryp;
+#[cfg(any(feature = "aes", feature = "cryp"))]
+pub mod crypto;
 #[cfg(feature = "dac")]
 pub mod dac;
+#[cfg(feature = "dcmi")]
+pub mod dcmi;
+#[cfg(feature = "dfsdm")]
+pub mod dfsdm;
+#[cfg(all(
+    feature = "eth",
+    not(any(feature = "stm32f405", feature = "stm32f415"))
+))]
+pub mod eth;
 #[cfg(feature = "fmpi2c1")]
 pub mod fmpi2c;
 pub mod gpio;
+#[cfg(feature = "hash")]
+pub mod hash;
 pub mod i2c;
 pub mod i2s;
+#[cfg(feature = "lptim1")]
+pub mod lptim;
 #[cfg(all(feature = "usb_fs", feature = "otg-fs"))]
 pub mod otg_fs;
 #[cfg(all(any(feature = "usb_hs", docsrs), feature = "otg-hs"))]
@@ -120,6 +144,7 @@ pub mod fsmc_lcd;
 #[cfg(all(feature = "dma2d", feature = "ltdc"))]
 pub mod ltdc;
 pub mod prelude;
+pub mod pwr;
 pub mod qei;
 #[cfg(feature = "quadspi")]
 pub mod qspi;
@@ -131,6 +156,8 @@ pub mod sai;
 pub mod sdio;
 pub mod serial;
 pub mod signature;
+#[cfg(feature = "spdifrx")]
+pub mod spdifrx;
 pub mod spi;
 pub mod syscfg;
 pub mod time;
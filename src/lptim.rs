@@ -0,0 +1,448 @@
+//! Low-power timer (LPTIM1)
+//!
+//! 16-bit timer which keeps counting in Stop mode when it is clocked by the LSE, the LSI or
+//! the HSI. It counts continuously or once up to the autoreload value, generates a PWM on
+//! its output, or counts the edges of a quadrature encoder.
+//!
+//! The compare and autoreload interrupts wake the CPU from Stop mode through EXTI line 23,
+//! see [`Lptim::enable_wakeup`]:
+//!
+//! ```ignore
+//! let mut lptim = Lptim::new(dp.LPTIM1, ClockSource::Lse, Prescaler::Div32, &mut rcc);
+//! lptim.listen(Event::AutoReloadMatch);
+//! lptim.enable_wakeup(&mut dp.EXTI);
+//! // Wakes up every 10 s
+//! lptim.start(10 * 1024 - 1);
+//! ```
+//!
+//! [`Lptim`] implements [`DelayNs`] with one-shot runs of the timer, the CPU sleeps until
+//! they end. Clocked by the LSE, the LSI or the HSI the delay also runs in Stop mode.
+
+use core::convert::Infallible;
+
+use cortex_m::peripheral::{NVIC, SCB};
+use embedded_hal::delay::DelayNs;
+use enumflags2::BitFlags;
+
+use crate::gpio::alt::lptim1 as alt;
+#[cfg(feature = "gpio-f413")]
+use crate::pac::LPTIM as LPTIM1;
+#[cfg(feature = "gpio-f410")]
+use crate::pac::LPTIM1;
+use crate::pac::{Interrupt, EXTI, RCC};
+use crate::rcc::{Enable, Rcc, Reset};
+use crate::time::Hertz;
+
+/// EXTI line of the LPTIM1 wakeup, missing from the PACs
+const EXTI_LINE: u32 = 1 << 23;
+
+#[cfg(feature = "gpio-f410")]
+const INTERRUPT: Interrupt = Interrupt::LPTIM1;
+#[cfg(feature = "gpio-f413")]
+const INTERRUPT: Interrupt = Interrupt::LPTIM1_OR_IT_EIT_23;
+
+/// `SEVONPEND` bit of `SCB_SCR`, a pending interrupt wakes `wfe` even when it is masked
+const SEVONPEND: u32 = 1 << 4;
+
+/// Kernel clock of the timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockSource {
+    /// PCLK1, stopped in Stop mode
+    Apb1,
+    /// LSI, enabled if it does not run
+    Lsi,
+    /// HSI, enabled if it does not run
+    Hsi,
+    /// LSE, which must already run, like after [`Rtc::new`](crate::rtc::Rtc::new)
+    Lse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Prescaler {
+    Div1 = 0,
+    Div2 = 1,
+    Div4 = 2,
+    Div8 = 3,
+    Div16 = 4,
+    Div32 = 5,
+    Div64 = 6,
+    Div128 = 7,
+}
+
+/// Interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    /// The counter reached the compare value
+    CompareMatch = 1 << 0,
+    /// The counter reached the autoreload value
+    AutoReloadMatch = 1 << 1,
+    ExternalTrigger = 1 << 2,
+    /// The compare value was written
+    CompareOk = 1 << 3,
+    /// The autoreload value was written
+    AutoReloadOk = 1 << 4,
+    /// The encoder started counting up
+    Up = 1 << 5,
+    /// The encoder started counting down
+    Down = 1 << 6,
+}
+
+/// Low-power timer
+pub struct Lptim {
+    lptim: LPTIM1,
+    clock: Hertz,
+}
+
+impl Lptim {
+    /// Enables the clock of the timer, resets it and selects the kernel clock
+    ///
+    /// # Panics
+    ///
+    /// If the LSE is selected but does not run.
+    pub fn new(lptim: LPTIM1, source: ClockSource, prescaler: Prescaler, rcc: &mut Rcc) -> Self {
+        LPTIM1::enable(rcc);
+        LPTIM1::reset(rcc);
+
+        let clock = match source {
+            ClockSource::Apb1 => rcc.clocks.pclk1(),
+            ClockSource::Lsi => {
+                if rcc.csr().read().lsirdy().bit_is_clear() {
+                    rcc.csr().modify(|_, w| w.lsion().set_bit());
+                    while rcc.csr().read().lsirdy().bit_is_clear() {}
+                }
+                Hertz::Hz(32_000)
+            }
+            ClockSource::Hsi => {
+                if rcc.cr().read().hsirdy().bit_is_clear() {
+                    rcc.cr().modify(|_, w| w.hsion().set_bit());
+                    while rcc.cr().read().hsirdy().bit_is_clear() {}
+                }
+                Hertz::MHz(16)
+            }
+            ClockSource::Lse => {
+                assert!(rcc.bdcr().read().lserdy().bit_is_set(), "LSE does not run");
+                Hertz::Hz(32_768)
+            }
+        };
+        select_clock(rcc, source);
+
+        lptim.cfgr().write(|w| w.presc().set(prescaler as u8));
+        Self {
+            lptim,
+            clock: clock / (1 << prescaler as u32),
+        }
+    }
+
+    /// Stops the timer and releases it
+    pub fn release(self) -> LPTIM1 {
+        self.lptim.cr().write(|w| w.enable().clear_bit());
+        self.lptim
+    }
+
+    /// Frequency of the counter
+    pub fn frequency(&self) -> Hertz {
+        self.clock
+    }
+
+    /// Starts counting continuously from 0 to `period` included
+    ///
+    /// # Panics
+    ///
+    /// If `period` is 0.
+    pub fn start(&mut self, period: u16) {
+        self.enable_with_period(period);
+        self.lptim.cr().modify(|_, w| w.cntstrt().set_bit());
+    }
+
+    /// Counts once from 0 to `period` included, then stops with
+    /// [`Event::AutoReloadMatch`]
+    ///
+    /// # Panics
+    ///
+    /// If `period` is 0.
+    pub fn start_one_shot(&mut self, period: u16) {
+        self.enable_with_period(period);
+        self.lptim.cr().modify(|_, w| w.sngstrt().set_bit());
+    }
+
+    /// Stops the timer and resets the counter
+    pub fn stop(&mut self) {
+        self.lptim.cr().write(|w| w.enable().clear_bit());
+    }
+
+    /// Current value of the counter
+    pub fn counter(&self) -> u16 {
+        // The counter runs on another clock, it is valid once two reads are equal
+        loop {
+            let cnt = self.lptim.cnt().read().bits();
+            if cnt == self.lptim.cnt().read().bits() {
+                return cnt as u16;
+            }
+        }
+    }
+
+    /// Sets the value of [`Event::CompareMatch`], which must be below the period. The
+    /// timer must be started.
+    pub fn set_compare(&mut self, value: u16) {
+        self.lptim.cmp().write(|w| unsafe { w.bits(value as u32) });
+        while self.lptim.isr().read().cmpok().bit_is_clear() {}
+        self.lptim.icr().write(|w| w.cmpokcf().set_bit());
+    }
+
+    /// Generates a PWM of `period + 1` ticks on the `out` pin
+    ///
+    /// # Panics
+    ///
+    /// If `period` is 0.
+    pub fn pwm(mut self, out: impl Into<alt::Out>, period: u16) -> Pwm {
+        self.stop();
+        self.lptim.cfgr().modify(|_, w| {
+            w.wave().clear_bit();
+            w.wavpol().clear_bit()
+        });
+        let mut pwm = Pwm {
+            timer: self,
+            out: out.into(),
+            period,
+        };
+        pwm.timer.enable_with_period(period);
+        pwm.set_duty(0);
+        pwm.timer.lptim.cr().modify(|_, w| w.cntstrt().set_bit());
+        pwm
+    }
+
+    /// Counts the edges of a quadrature encoder on `in1` and `in2`, the counter wraps at
+    /// `period`
+    ///
+    /// The prescaler is bypassed, the kernel clock must be at least 4 times faster than the
+    /// encoder signals.
+    ///
+    /// # Panics
+    ///
+    /// If `period` is 0.
+    pub fn encoder(
+        mut self,
+        in1: impl Into<alt::In1>,
+        in2: impl Into<alt::In2>,
+        period: u16,
+    ) -> Encoder {
+        self.stop();
+        self.lptim.cfgr().modify(|_, w| {
+            w.cksel().clear_bit();
+            w.presc().set(0);
+            // Counts both edges of both inputs
+            unsafe { w.ckpol().bits(0b10) };
+            w.enc().set_bit()
+        });
+        self.start(period);
+        Encoder {
+            timer: self,
+            pins: (in1.into(), in2.into()),
+        }
+    }
+
+    /// Routes the enabled interrupts to EXTI line 23, so they wake the CPU up from Stop
+    /// mode
+    pub fn enable_wakeup(&mut self, exti: &mut EXTI) {
+        exti.rtsr()
+            .modify(|r, w| unsafe { w.bits(r.bits() | EXTI_LINE) });
+        exti.imr()
+            .modify(|r, w| unsafe { w.bits(r.bits() | EXTI_LINE) });
+    }
+
+    pub fn disable_wakeup(&mut self, exti: &mut EXTI) {
+        exti.imr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !EXTI_LINE) });
+        exti.rtsr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !EXTI_LINE) });
+    }
+
+    /// Clears the pending bit of EXTI line 23, the interrupt flags of the timer are
+    /// cleared with [`clear_flags`](crate::ClearFlags::clear_flags)
+    pub fn clear_wakeup(&mut self, exti: &mut EXTI) {
+        exti.pr().write(|w| unsafe { w.bits(EXTI_LINE) });
+    }
+
+    fn enable_with_period(&mut self, period: u16) {
+        assert!(period > 0);
+        self.lptim.cr().write(|w| w.enable().set_bit());
+        self.lptim.arr().write(|w| unsafe { w.bits(period as u32) });
+        while self.lptim.isr().read().arrok().bit_is_clear() {}
+        self.lptim.icr().write(|w| w.arrokcf().set_bit());
+    }
+}
+
+fn select_clock(rcc: &mut RCC, source: ClockSource) {
+    rcc.dckcfgr2().modify(|_, w| {
+        let sel = w.lptim1sel();
+        match source {
+            ClockSource::Apb1 => sel.apb1(),
+            ClockSource::Lsi => sel.lsi(),
+            ClockSource::Hsi => sel.hsi(),
+            ClockSource::Lse => sel.lse(),
+        }
+    });
+}
+
+impl crate::Listen for Lptim {
+    type Event = Event;
+
+    /// Stops the timer, the interrupts can only be changed while it is stopped
+    fn listen(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.stop();
+        self.lptim
+            .ier()
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.into().bits()) });
+    }
+
+    /// Stops the timer, see [`listen`](crate::Listen::listen)
+    fn listen_only(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.stop();
+        self.lptim
+            .ier()
+            .write(|w| unsafe { w.bits(event.into().bits()) });
+    }
+
+    /// Stops the timer, see [`listen`](crate::Listen::listen)
+    fn unlisten(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.stop();
+        self.lptim
+            .ier()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.into().bits()) });
+    }
+}
+
+impl crate::ReadFlags for Lptim {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Event> {
+        BitFlags::from_bits_truncate(self.lptim.isr().read().bits())
+    }
+}
+
+impl crate::ClearFlags for Lptim {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Event>>) {
+        self.lptim
+            .icr()
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
+
+impl DelayNs for Lptim {
+    /// Sleeps with `wfe` until the end of one-shot runs of the timer
+    ///
+    /// The autoreload match interrupt is enabled during the delay, it wakes the CPU even
+    /// when it is masked in the NVIC. If it is unmasked, its handler must not clear the
+    /// autoreload match flag.
+    ///
+    /// With `SLEEPDEEP` set the CPU enters Stop mode, then the timer must be clocked by
+    /// the LSE, the LSI or the HSI, which keep running in Stop mode, and the wakeup must
+    /// be enabled with [`enable_wakeup`](Lptim::enable_wakeup).
+    fn delay_ns(&mut self, ns: u32) {
+        let freq = self.clock.raw() as u64;
+        let mut ticks = (ns as u64 * freq + 999_999_999) / 1_000_000_000;
+
+        // The interrupts can only be enabled while the timer is stopped
+        self.stop();
+        let ier = self.lptim.ier().read().bits();
+        self.lptim.ier().modify(|_, w| w.arrmie().set_bit());
+        // NOTE(unsafe) the previous value is restored before returning
+        let scb = unsafe { &*SCB::PTR };
+        let scr = scb.scr.read();
+        unsafe { scb.scr.write(scr | SEVONPEND) };
+
+        while ticks > 0 {
+            let run = ticks.min(u16::MAX as u64);
+            self.lptim.icr().write(|w| w.arrmcf().set_bit());
+            // Only a new pending interrupt sends the event which wakes `wfe`
+            NVIC::unpend(INTERRUPT);
+            self.start_one_shot(run as u16);
+            while self.lptim.isr().read().arrm().bit_is_clear() {
+                cortex_m::asm::wfe();
+            }
+            ticks -= run;
+        }
+
+        self.stop();
+        self.lptim.ier().write(|w| unsafe { w.bits(ier) });
+        unsafe { scb.scr.write(scr) };
+        if ier & Event::AutoReloadMatch as u32 == 0 {
+            NVIC::unpend(INTERRUPT);
+        }
+    }
+}
+
+/// PWM on the output of [`Lptim`]
+pub struct Pwm {
+    timer: Lptim,
+    out: alt::Out,
+    period: u16,
+}
+
+impl Pwm {
+    /// Stops the PWM and releases the timer and the pin
+    pub fn release(mut self) -> (Lptim, alt::Out) {
+        self.timer.stop();
+        (self.timer, self.out)
+    }
+
+    /// Sets the ticks of the period the output is high, up to
+    /// [`get_max_duty`](Self::get_max_duty)
+    pub fn set_duty(&mut self, duty: u16) {
+        // The output is high from the compare match to the end of the period
+        self.timer.set_compare(self.period - duty.min(self.period));
+    }
+
+    pub fn get_duty(&self) -> u16 {
+        self.period - self.timer.lptim.cmp().read().bits() as u16
+    }
+
+    pub fn get_max_duty(&self) -> u16 {
+        self.period
+    }
+}
+
+impl embedded_hal::pwm::ErrorType for Pwm {
+    type Error = Infallible;
+}
+
+impl embedded_hal::pwm::SetDutyCycle for Pwm {
+    fn max_duty_cycle(&self) -> u16 {
+        self.get_max_duty()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.set_duty(duty);
+        Ok(())
+    }
+}
+
+/// Quadrature encoder counter on the inputs of [`Lptim`]
+pub struct Encoder {
+    timer: Lptim,
+    pins: (alt::In1, alt::In2),
+}
+
+impl Encoder {
+    /// Stops counting and releases the timer and the pins
+    pub fn release(mut self) -> (Lptim, (alt::In1, alt::In2)) {
+        self.timer.stop();
+        self.timer.lptim.cfgr().modify(|_, w| {
+            w.enc().clear_bit();
+            unsafe { w.ckpol().bits(0) }
+        });
+        (self.timer, self.pins)
+    }
+
+    /// Position of the encoder
+    pub fn count(&self) -> u16 {
+        self.timer.counter()
+    }
+}
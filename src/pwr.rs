@@ -0,0 +1,296 @@
+//! Power control and low-power modes
+//!
+//! The MCU has three low-power modes, from the lightest to the deepest:
+//!
+//! - Sleep: the core clock is stopped, the peripherals keep running and any interrupt
+//!   wakes the core up.
+//! - Stop: all the clocks of the 1.2 V domain are stopped, the PLLs, HSI and HSE are
+//!   disabled. SRAM and registers are kept and an EXTI line (EXTI pin, RTC, PVD, USB
+//!   wakeup...) wakes the core up. [`Pwr::stop`] restores the frozen clocks on wakeup.
+//! - Standby: the 1.2 V domain is powered off, only the backup domain is kept. A WKUP pin
+//!   rising edge, an RTC event, an IWDG reset or an external reset wakes the MCU up,
+//!   which then restarts from reset.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use stm32f4xx_hal::{pac, prelude::*, pwr::{Pwr, StopConfig, WakeupPin}};
+//!
+//! let dp = pac::Peripherals::take().unwrap();
+//! let mut cp = cortex_m::Peripherals::take().unwrap();
+//! let mut rcc = dp.RCC.freeze(Default::default());
+//!
+//! let mut pwr = Pwr::new(dp.PWR, &mut rcc);
+//! if pwr.is_standby() {
+//!     // Woken up from Standby
+//!     pwr.clear_standby();
+//! }
+//!
+//! // Wait for an EXTI event with the flash powered down, the clocks are restored
+//! // on wakeup
+//! pwr.stop(&mut cp.SCB, &mut rcc, StopConfig::new().low_power().flash_power_down(true));
+//!
+//! // Sleep until the next rising edge on PA0
+//! pwr.enable_wakeup_pin(WakeupPin::Wkup1);
+//! pwr.standby(&mut cp.SCB);
+//! ```
+
+use crate::pac::PWR;
+use crate::rcc::{Enable, Rcc};
+use cortex_m::peripheral::SCB;
+
+/// Voltage regulator in Stop mode
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum Regulator {
+    /// Main regulator on, for the fastest wakeup
+    #[default]
+    Main,
+    /// Low-power regulator, for the lowest consumption
+    LowPower,
+}
+
+/// Stop mode configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub struct StopConfig {
+    regulator: Regulator,
+    flash_power_down: bool,
+    #[cfg(not(feature = "gpio-f417"))]
+    under_drive: bool,
+}
+
+impl StopConfig {
+    /// Main regulator on, flash powered
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the voltage regulator
+    pub fn regulator(mut self, regulator: Regulator) -> Self {
+        self.regulator = regulator;
+        self
+    }
+
+    /// Uses the low-power regulator
+    pub fn low_power(self) -> Self {
+        self.regulator(Regulator::LowPower)
+    }
+
+    /// Powers the flash down, which lowers the consumption but delays the wakeup
+    pub fn flash_power_down(mut self, power_down: bool) -> Self {
+        self.flash_power_down = power_down;
+        self
+    }
+
+    /// Lowers the regulator voltage further, which delays the wakeup
+    ///
+    /// This is the under-drive mode on STM32F42x/43x, STM32F446 and STM32F469/479, and
+    /// the low-voltage mode of the regulator on STM32F401 and STM32F41x.
+    #[cfg(not(feature = "gpio-f417"))]
+    pub fn under_drive(mut self, under_drive: bool) -> Self {
+        self.under_drive = under_drive;
+        self
+    }
+}
+
+/// Pin waking the MCU up from Standby on a rising edge
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum WakeupPin {
+    /// PA0
+    Wkup1,
+    /// PC0 on STM32F410/412/413/423, PC13 on STM32F446
+    #[cfg(any(
+        feature = "gpio-f410",
+        feature = "gpio-f412",
+        feature = "gpio-f413",
+        feature = "gpio-f446"
+    ))]
+    Wkup2,
+    /// PC1
+    #[cfg(any(feature = "gpio-f410", feature = "gpio-f412", feature = "gpio-f413"))]
+    Wkup3,
+}
+
+impl WakeupPin {
+    /// `EWUPx` bit of `CSR`, the PAC names it `EWUP` on the single pin MCUs
+    fn mask(self) -> u32 {
+        match self {
+            Self::Wkup1 => 1 << 8,
+            #[cfg(any(
+                feature = "gpio-f410",
+                feature = "gpio-f412",
+                feature = "gpio-f413",
+                feature = "gpio-f446"
+            ))]
+            Self::Wkup2 => 1 << 7,
+            #[cfg(any(feature = "gpio-f410", feature = "gpio-f412", feature = "gpio-f413"))]
+            Self::Wkup3 => 1 << 6,
+        }
+    }
+}
+
+/// `LPUDS` (`LPLVDS`) and `MRUDS` (`MRLVDS`) bits of `CR`
+#[cfg(not(feature = "gpio-f417"))]
+const LPUDS: u32 = 1 << 10;
+#[cfg(not(feature = "gpio-f417"))]
+const MRUDS: u32 = 1 << 11;
+
+/// Power controller
+pub struct Pwr {
+    pub(crate) pwr: PWR,
+}
+
+impl Pwr {
+    /// Enables the clock of the power controller
+    pub fn new(pwr: PWR, rcc: &mut Rcc) -> Self {
+        PWR::enable(rcc);
+        Self { pwr }
+    }
+
+    /// Releases the PWR peripheral
+    pub fn release(self) -> PWR {
+        self.pwr
+    }
+
+    /// Enters Sleep mode until the next interrupt
+    pub fn sleep(&mut self, scb: &mut SCB) {
+        scb.clear_sleepdeep();
+        cortex_m::asm::dsb();
+        cortex_m::asm::wfi();
+    }
+
+    /// Enters Stop mode until the next EXTI interrupt
+    ///
+    /// On wakeup the HSE and the PLLs are started again and the system clock switched back,
+    /// so the frozen [`Clocks`](crate::rcc::Clocks) stay valid.
+    pub fn stop(&mut self, scb: &mut SCB, rcc: &mut Rcc, config: StopConfig) {
+        self.pwr.cr().modify(|_, w| {
+            w.pdds().clear_bit();
+            w.lpds().bit(config.regulator == Regulator::LowPower);
+            w.fpds().bit(config.flash_power_down);
+            w.cwuf().set_bit()
+        });
+        #[cfg(not(feature = "gpio-f417"))]
+        self.set_under_drive(config.under_drive, config.regulator);
+
+        // The clock state to restore, the PLL configuration and prescalers are kept
+        let cr = rcc.cr().read();
+        let sw = rcc.cfgr().read().sw().bits();
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        let over_drive = self.pwr.cr().read().oden().bit_is_set();
+
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        cortex_m::asm::wfi();
+        scb.clear_sleepdeep();
+
+        #[cfg(not(feature = "gpio-f417"))]
+        self.set_under_drive(false, config.regulator);
+
+        if cr.hseon().bit_is_set() {
+            rcc.cr().modify(|_, w| w.hseon().set_bit());
+            while rcc.cr().read().hserdy().bit_is_clear() {}
+        }
+        if cr.pllon().bit_is_set() {
+            rcc.cr().modify(|_, w| w.pllon().set_bit());
+
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+            if over_drive {
+                self.pwr.cr().modify(|_, w| w.oden().set_bit());
+                while self.pwr.csr().read().odrdy().bit_is_clear() {}
+                self.pwr.cr().modify(|_, w| w.odswen().set_bit());
+                while self.pwr.csr().read().odswrdy().bit_is_clear() {}
+            }
+
+            while rcc.cr().read().pllrdy().bit_is_clear() {}
+        }
+        #[cfg(not(feature = "gpio-f410"))]
+        if cr.plli2son().bit_is_set() {
+            rcc.cr().modify(|_, w| w.plli2son().set_bit());
+            while rcc.cr().read().plli2srdy().bit_is_clear() {}
+        }
+        #[cfg(feature = "sai")]
+        #[cfg(not(feature = "gpio-f413"))]
+        if cr.pllsaion().bit_is_set() {
+            rcc.cr().modify(|_, w| w.pllsaion().set_bit());
+            while rcc.cr().read().pllsairdy().bit_is_clear() {}
+        }
+        rcc.cfgr().modify(|_, w| unsafe { w.sw().bits(sw) });
+        while rcc.cfgr().read().sws().bits() != sw {}
+    }
+
+    /// Enters Standby mode, the MCU restarts from reset on wakeup
+    ///
+    /// The wakeup flag is cleared first, otherwise the MCU would wake up at once.
+    pub fn standby(&mut self, scb: &mut SCB) -> ! {
+        self.pwr.cr().modify(|_, w| {
+            w.pdds().set_bit();
+            w.cwuf().set_bit();
+            w.csbf().set_bit()
+        });
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
+    #[cfg(not(feature = "gpio-f417"))]
+    fn set_under_drive(&mut self, enable: bool, regulator: Regulator) {
+        let bits = match (enable, regulator) {
+            (false, _) => 0,
+            (true, Regulator::Main) => MRUDS,
+            (true, Regulator::LowPower) => LPUDS,
+        };
+        self.pwr
+            .cr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(LPUDS | MRUDS) | bits) });
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        if enable {
+            self.pwr.cr().modify(|_, w| unsafe { w.uden().bits(0b11) });
+        } else {
+            self.pwr.cr().modify(|_, w| unsafe { w.uden().bits(0) });
+            // Clears the under-drive ready flags
+            self.pwr
+                .csr()
+                .modify(|_, w| unsafe { w.udrdy().bits(0b11) });
+        }
+    }
+
+    /// Enables a WKUP pin, which then is in input pull-down mode whatever its GPIO
+    /// configuration
+    pub fn enable_wakeup_pin(&mut self, pin: WakeupPin) {
+        self.pwr
+            .csr()
+            .modify(|r, w| unsafe { w.bits(r.bits() | pin.mask()) });
+    }
+
+    /// Disables a WKUP pin
+    pub fn disable_wakeup_pin(&mut self, pin: WakeupPin) {
+        self.pwr
+            .csr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !pin.mask()) });
+    }
+
+    /// Returns `true` if a wakeup event happened (WKUP pin or RTC)
+    pub fn is_wakeup(&self) -> bool {
+        self.pwr.csr().read().wuf().bit_is_set()
+    }
+
+    /// Clears the wakeup flag
+    pub fn clear_wakeup(&mut self) {
+        self.pwr.cr().modify(|_, w| w.cwuf().set_bit());
+    }
+
+    /// Returns `true` if the MCU has been woken up from Standby
+    pub fn is_standby(&self) -> bool {
+        self.pwr.csr().read().sbf().bit_is_set()
+    }
+
+    /// Clears the standby flag
+    pub fn clear_standby(&mut self) {
+        self.pwr.cr().modify(|_, w| w.csbf().set_bit());
+    }
+}
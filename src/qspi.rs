@@ -4,8 +4,8 @@
 //! communication with external flash memory.
 //!
 //! Limitations:
-//!     - Interrupts are not supported.
-//!     - Status polling mode is not supported.
+//!     - Interrupts and status polling mode are only used by the async API
+//!       (`async` feature).
 
 // Based on work by Unizippro <madas@astrupa.dk> in stm32l4xx-hal.
 
@@ -17,6 +17,11 @@ use crate::{
 };
 pub use alt::{Bank1, Bank2};
 
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_interrupt, PollMatchMode, QspiAsync, QspiPollCommand};
+
 pub trait QspiPins {
     const FSEL: bool = false;
     const DFM: bool = false;
@@ -201,11 +206,14 @@ pub enum ClockMode {
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum QspiError {
     Busy,
     Address,
     Unknown,
     IllegalArgument,
+    /// DMA transfer error
+    Dma,
 }
 
 impl Qspi<Bank1> {
@@ -416,76 +424,27 @@ impl<BANK: QspiPins> Qspi<BANK> {
             return Err(QspiError::Busy);
         }
 
-        // If double data rate change shift
-        if command.double_data_rate {
-            self.qspi.cr().modify(|_, w| w.sshift().bit(false));
-        }
-        while self.is_busy() {}
-
         // Clear the transfer complete flag.
         self.qspi.fcr().modify(|_, w| w.ctcf().set_bit());
 
-        let dmode: u8 = command.data.1 as u8;
-        let mut instruction: u8 = 0;
-        let mut imode: u8 = 0;
-        let mut admode: u8 = 0;
-        let mut adsize: u8 = 0;
-        let mut abmode: u8 = 0;
-        let mut absize: u8 = 0;
-
         // Write the length and format of data
         self.qspi
             .dlr()
             .write(|w| unsafe { w.dl().bits(buffer.len() as u32 - 1) });
 
-        // Write instruction mode
-        if let Some((inst, mode)) = command.instruction {
-            imode = mode as u8;
-            instruction = inst;
-        }
-
-        // Note Address mode
-        if let Some((_, mode)) = command.address {
-            admode = mode as u8;
-            adsize = self.config.address_size as u8;
-        }
-
-        // Write alternate bytes
-        if let Some((a_bytes, mode)) = command.alternate_bytes {
-            abmode = mode as u8;
-            absize = a_bytes.len() as u8 - 1;
-
-            self.qspi.abr().write(|w| {
-                let mut reg_byte: u32 = 0;
-                for (i, element) in a_bytes.iter().rev().enumerate() {
-                    reg_byte |= (*element as u32) << (i * 8);
-                }
-                unsafe { w.alternate().bits(reg_byte) }
-            });
-        }
-
-        // Write CCR register with instruction etc.
-        self.qspi.ccr().modify(|_, w| unsafe {
-            w.fmode().bits(0b01 /* Indirect read */);
-            w.admode().bits(admode);
-            w.adsize().bits(adsize);
-            w.abmode().bits(abmode);
-            w.absize().bits(absize);
-            w.ddrm().bit(command.double_data_rate);
-            w.dcyc().bits(command.dummy_cycles);
-            w.dmode().bits(dmode);
-            w.imode().bits(imode);
-            w.instruction().bits(instruction)
-        });
-
-        // Write address, triggers send
-        if let Some((addr, _)) = command.address {
-            self.qspi.ar().write(|w| unsafe { w.address().bits(addr) });
-
-            // Transfer error
-            if self.qspi.sr().read().tef().bit_is_set() {
-                return Err(QspiError::Address);
-            }
+        self.write_command(Command {
+            fmode: 0b01, /* Indirect read */
+            instruction: command.instruction,
+            address: command.address,
+            alternate_bytes: command.alternate_bytes,
+            dummy_cycles: command.dummy_cycles,
+            dmode: command.data.1 as u8,
+            double_data_rate: command.double_data_rate,
+        })?;
+
+        // Address transfer error
+        if command.address.is_some() && self.qspi.sr().read().tef().bit_is_set() {
+            return Err(QspiError::Address);
         }
 
         // Transfer error
@@ -544,12 +503,6 @@ impl<BANK: QspiPins> Qspi<BANK> {
         self.qspi.fcr().modify(|_, w| w.ctcf().set_bit());
 
         let mut dmode: u8 = 0;
-        let mut instruction: u8 = 0;
-        let mut imode: u8 = 0;
-        let mut admode: u8 = 0;
-        let mut adsize: u8 = 0;
-        let mut abmode: u8 = 0;
-        let mut absize: u8 = 0;
 
         // Write the length and format of data
         if let Some((data, mode)) = command.data {
@@ -559,55 +512,15 @@ impl<BANK: QspiPins> Qspi<BANK> {
             dmode = mode as u8;
         }
 
-        // Write instruction mode
-        if let Some((inst, mode)) = command.instruction {
-            imode = mode as u8;
-            instruction = inst;
-        }
-
-        // Note Address mode
-        if let Some((_, mode)) = command.address {
-            admode = mode as u8;
-            adsize = self.config.address_size as u8;
-        }
-
-        // Write alternate bytes
-        if let Some((a_bytes, mode)) = command.alternate_bytes {
-            abmode = mode as u8;
-
-            absize = a_bytes.len() as u8 - 1;
-
-            self.qspi.abr().write(|w| {
-                let mut reg_byte: u32 = 0;
-                for (i, element) in a_bytes.iter().rev().enumerate() {
-                    reg_byte |= (*element as u32) << (i * 8);
-                }
-                unsafe { w.alternate().bits(reg_byte) }
-            });
-        }
-
-        if command.double_data_rate {
-            self.qspi.cr().modify(|_, w| w.sshift().bit(false));
-        }
-
-        // Write CCR register with instruction etc.
-        self.qspi.ccr().modify(|_, w| unsafe {
-            w.fmode().bits(0b00 /* Indirect write mode */);
-            w.admode().bits(admode);
-            w.adsize().bits(adsize);
-            w.abmode().bits(abmode);
-            w.absize().bits(absize);
-            w.ddrm().bit(command.double_data_rate);
-            w.dcyc().bits(command.dummy_cycles);
-            w.dmode().bits(dmode);
-            w.imode().bits(imode);
-            w.instruction().bits(instruction)
-        });
-
-        // Write address, triggers send
-        if let Some((addr, _)) = command.address {
-            self.qspi.ar().write(|w| unsafe { w.address().bits(addr) });
-        }
+        self.write_command(Command {
+            fmode: 0b00, /* Indirect write mode */
+            instruction: command.instruction,
+            address: command.address,
+            alternate_bytes: command.alternate_bytes,
+            dummy_cycles: command.dummy_cycles,
+            dmode,
+            double_data_rate: command.double_data_rate,
+        })?;
 
         // Transfer error
         if self.qspi.sr().read().tef().bit_is_set() {
@@ -647,6 +560,10 @@ impl<BANK: QspiPins> Qspi<BANK> {
         if self.is_busy() {
             return Err(QspiError::Busy);
         }
+        if matches!(command.alternate_bytes, Some((a_bytes, _)) if !(1..=4).contains(&a_bytes.len()))
+        {
+            return Err(QspiError::IllegalArgument);
+        }
 
         // If double data rate change shift
         if command.double_data_rate {
@@ -670,15 +587,7 @@ impl<BANK: QspiPins> Qspi<BANK> {
         // Write alternate bytes
         if let Some((a_bytes, mode)) = command.alternate_bytes {
             abmode = mode as u8;
-            absize = a_bytes.len() as u8 - 1;
-
-            self.qspi.abr().write(|w| {
-                let mut reg_byte: u32 = 0;
-                for (i, element) in a_bytes.iter().rev().enumerate() {
-                    reg_byte |= (*element as u32) << (i * 8);
-                }
-                unsafe { w.alternate().bits(reg_byte) }
-            });
+            absize = self.write_alternate_bytes(a_bytes)?;
         }
 
         self.qspi.ccr().modify(|_, w| unsafe {
@@ -704,11 +613,90 @@ impl<BANK: QspiPins> Qspi<BANK> {
         Ok(MemoryMapped { qspi: self, buffer })
     }
 
+    /// Sets the sample shift for DDR and writes the command, which is
+    /// started by the write to CCR or AR
+    fn write_command(&mut self, command: Command<'_>) -> Result<(), QspiError> {
+        let mut instruction: u8 = 0;
+        let mut imode: u8 = 0;
+        let mut admode: u8 = 0;
+        let mut adsize: u8 = 0;
+        let mut abmode: u8 = 0;
+        let mut absize: u8 = 0;
+
+        // Write alternate bytes
+        if let Some((a_bytes, mode)) = command.alternate_bytes {
+            abmode = mode as u8;
+            absize = self.write_alternate_bytes(a_bytes)?;
+        }
+
+        // If double data rate change shift
+        if command.double_data_rate {
+            self.qspi.cr().modify(|_, w| w.sshift().bit(false));
+        }
+
+        if let Some((inst, mode)) = command.instruction {
+            imode = mode as u8;
+            instruction = inst;
+        }
+
+        // Note Address mode
+        if let Some((_, mode)) = command.address {
+            admode = mode as u8;
+            adsize = self.config.address_size as u8;
+        }
+
+        // Write CCR register with instruction etc.
+        self.qspi.ccr().modify(|_, w| unsafe {
+            w.fmode().bits(command.fmode);
+            w.admode().bits(admode);
+            w.adsize().bits(adsize);
+            w.abmode().bits(abmode);
+            w.absize().bits(absize);
+            w.ddrm().bit(command.double_data_rate);
+            w.dcyc().bits(command.dummy_cycles);
+            w.dmode().bits(command.dmode);
+            w.imode().bits(imode);
+            w.instruction().bits(instruction)
+        });
+
+        // Write address, triggers send
+        if let Some((addr, _)) = command.address {
+            self.qspi.ar().write(|w| unsafe { w.address().bits(addr) });
+        }
+        Ok(())
+    }
+
+    /// Writes 1 to 4 alternate bytes to ABR and returns their size for CCR
+    fn write_alternate_bytes(&mut self, a_bytes: &[u8]) -> Result<u8, QspiError> {
+        if !(1..=4).contains(&a_bytes.len()) {
+            return Err(QspiError::IllegalArgument);
+        }
+        self.qspi.abr().write(|w| {
+            let mut reg_byte: u32 = 0;
+            for (i, element) in a_bytes.iter().rev().enumerate() {
+                reg_byte |= (*element as u32) << (i * 8);
+            }
+            unsafe { w.alternate().bits(reg_byte) }
+        });
+        Ok(a_bytes.len() as u8 - 1)
+    }
+
     pub fn release(self) -> (QUADSPI, BANK::Pins) {
         (self.qspi, self.pins)
     }
 }
 
+/// Phases of a command written to CCR
+struct Command<'a> {
+    fmode: u8,
+    instruction: Option<(u8, QspiMode)>,
+    address: Option<(u32, QspiMode)>,
+    alternate_bytes: Option<(&'a [u8], QspiMode)>,
+    dummy_cycles: u8,
+    dmode: u8,
+    double_data_rate: bool,
+}
+
 /// This struct is used to configure a write command for the QSPI peripheral.
 /// Specify None for any phase to skip it in the transaction.
 /// Each phase requires a mode to be specified.
@@ -0,0 +1,452 @@
+//! Async indirect transfers with DMA and async automatic status polling.
+//!
+//! Commands are awaited with the QUADSPI interrupt, which must call [`on_interrupt`],
+//! and DMA transfers with the interrupt of DMA2 stream 7, which must call
+//! [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt):
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn QUADSPI() {
+//!     qspi::on_interrupt();
+//! }
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM7() {
+//!     dma::Stream7::<pac::DMA2>::on_interrupt();
+//! }
+//! ```
+
+use core::{
+    future::poll_fn,
+    ops::{Deref, DerefMut},
+    task::Poll,
+};
+
+use atomic_waker::AtomicWaker;
+
+use super::{
+    Command, Qspi, QspiError, QspiMode, QspiPins, QspiReadCommand, QspiWriteCommand, SampleShift,
+};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use crate::pac::QUADSPI;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Wakes the task waiting for the QUADSPI.
+///
+/// Must be called from the `QUADSPI` interrupt handler when [`QspiAsync`] or
+/// [`Qspi::wait_status`] are used. Interrupts are disabled, the woken task enables them again
+/// if it still has to wait.
+pub fn on_interrupt() {
+    let qspi = unsafe { &*QUADSPI::ptr() };
+    qspi.cr().modify(|_, w| {
+        w.tcie().clear_bit();
+        w.teie().clear_bit();
+        w.smie().clear_bit()
+    });
+    WAKER.wake();
+}
+
+/// Match condition of [`QspiPollCommand`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum PollMatchMode {
+    /// All unmasked bits must match
+    #[default]
+    And,
+    /// Any unmasked bit must match
+    Or,
+}
+
+/// This struct is used to configure an automatic status polling command.
+/// The status is read until its bits selected by `mask` are equal to `matches`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QspiPollCommand<'a> {
+    instruction: Option<(u8, QspiMode)>,
+    address: Option<(u32, QspiMode)>,
+    alternate_bytes: Option<(&'a [u8], QspiMode)>,
+    dummy_cycles: u8,
+    data_mode: QspiMode,
+    status_bytes: u8,
+    mask: u32,
+    matches: u32,
+    match_mode: PollMatchMode,
+    interval: u16,
+    double_data_rate: bool,
+}
+
+impl<'a> QspiPollCommand<'a> {
+    /// Polls a 1 byte status register until `status & mask == matches`
+    pub fn new(mask: u32, matches: u32, data_mode: QspiMode) -> Self {
+        Self {
+            instruction: None,
+            address: None,
+            alternate_bytes: None,
+            dummy_cycles: 0,
+            data_mode,
+            status_bytes: 1,
+            mask,
+            matches,
+            match_mode: PollMatchMode::And,
+            interval: 16,
+            double_data_rate: false,
+        }
+    }
+
+    pub fn instruction(mut self, instruction: u8, instruction_mode: QspiMode) -> Self {
+        self.instruction = Some((instruction, instruction_mode));
+        self
+    }
+
+    pub fn address(mut self, address: u32, address_mode: QspiMode) -> Self {
+        self.address = Some((address, address_mode));
+        self
+    }
+
+    pub fn alternate_bytes(
+        mut self,
+        alternate_bytes: &'a [u8],
+        alternate_bytes_mode: QspiMode,
+    ) -> Self {
+        self.alternate_bytes = Some((alternate_bytes, alternate_bytes_mode));
+        self
+    }
+
+    pub fn dummy_cycles(mut self, dummy_cycles: u8) -> Self {
+        self.dummy_cycles = dummy_cycles;
+        self
+    }
+
+    /// Size of the status register in bytes, 1 to 4
+    pub fn status_bytes(mut self, status_bytes: u8) -> Self {
+        self.status_bytes = status_bytes;
+        self
+    }
+
+    pub fn match_mode(mut self, match_mode: PollMatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Number of CLK cycles between two reads of the status
+    pub fn interval(mut self, interval: u16) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn double_data_rate(mut self, double_data_rate: bool) -> Self {
+        self.double_data_rate = double_data_rate;
+        self
+    }
+}
+
+impl<BANK: QspiPins> Qspi<BANK> {
+    /// Converts [`Qspi`] to [`QspiAsync`] that uses `stream` for indirect transfers
+    pub fn into_async<STREAM, const CH: u8>(self, stream: STREAM) -> QspiAsync<BANK, STREAM, CH>
+    where
+        STREAM: Stream,
+        ChannelX<CH>: Channel,
+        QUADSPI: DMASet<STREAM, CH, PeripheralToMemory> + DMASet<STREAM, CH, MemoryToPeripheral>,
+    {
+        QspiAsync { qspi: self, stream }
+    }
+
+    /// Reads the status with automatic polling mode until it matches and returns it.
+    ///
+    /// Polling is stopped on the first match. Typically used to wait for the end of a flash
+    /// program or erase, e.g. with `QspiPollCommand::new(0x01, 0x00, QspiMode::SingleChannel)
+    /// .instruction(0x05, QspiMode::SingleChannel)` for the WIP bit.
+    pub async fn wait_status(&mut self, command: QspiPollCommand<'_>) -> Result<u32, QspiError> {
+        if !(1..=4).contains(&command.status_bytes) {
+            return Err(QspiError::IllegalArgument);
+        }
+        if self.is_busy() {
+            return Err(QspiError::Busy);
+        }
+
+        self.qspi.fcr().write(|w| {
+            w.ctcf().set_bit();
+            w.ctef().set_bit();
+            w.csmf().set_bit()
+        });
+        self.qspi
+            .psmkr()
+            .write(|w| unsafe { w.mask().bits(command.mask) });
+        self.qspi
+            .psmar()
+            .write(|w| unsafe { w.match_().bits(command.matches) });
+        self.qspi
+            .pir()
+            .write(|w| unsafe { w.interval().bits(command.interval) });
+        self.qspi.cr().modify(|_, w| {
+            w.pmm().bit(command.match_mode == PollMatchMode::Or);
+            w.apms().set_bit()
+        });
+        self.qspi
+            .dlr()
+            .write(|w| unsafe { w.dl().bits(u32::from(command.status_bytes) - 1) });
+        if let Err(error) = self.write_command(Command {
+            fmode: 0b10, /* Automatic polling mode */
+            instruction: command.instruction,
+            address: command.address,
+            alternate_bytes: command.alternate_bytes,
+            dummy_cycles: command.dummy_cycles,
+            dmode: command.data_mode as u8,
+            double_data_rate: command.double_data_rate,
+        }) {
+            self.finish_command(command.double_data_rate, true);
+            return Err(error);
+        }
+
+        let qspi = &self.qspi;
+        let result = poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            let sr = qspi.sr().read();
+            if sr.tef().bit_is_set() {
+                Poll::Ready(Err(QspiError::Unknown))
+            } else if sr.smf().bit_is_set() {
+                Poll::Ready(Ok(qspi.dr().read().bits()))
+            } else {
+                qspi.cr().modify(|_, w| {
+                    w.smie().set_bit();
+                    w.teie().set_bit()
+                });
+                Poll::Pending
+            }
+        })
+        .await;
+        self.finish_command(command.double_data_rate, result.is_err());
+        result
+    }
+
+    /// Disables interrupts and DMA, aborts a failed command and restores the sample shift
+    fn finish_command(&mut self, double_data_rate: bool, failed: bool) {
+        self.qspi.cr().modify(|_, w| {
+            w.tcie().clear_bit();
+            w.teie().clear_bit();
+            w.smie().clear_bit();
+            w.dmaen().clear_bit()
+        });
+        if failed || (double_data_rate && self.is_busy()) {
+            self.abort_transmission();
+        }
+        while self.is_busy() {}
+        self.qspi.fcr().write(|w| {
+            w.ctcf().set_bit();
+            w.ctef().set_bit();
+            w.csmf().set_bit()
+        });
+        if double_data_rate {
+            self.qspi.cr().modify(|_, w| {
+                w.sshift()
+                    .bit(self.config.sample_shift == SampleShift::HalfACycle)
+            });
+        }
+    }
+}
+
+/// Qspi that performs indirect reads and writes with DMA
+///
+/// Derefs to [`Qspi`], so the blocking API and [`Qspi::wait_status`] stay available.
+/// A client must:
+/// * Enable the QUADSPI interrupt and call [`on_interrupt`] in it.
+/// * Enable the interrupt of the DMA stream and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in it.
+///
+/// Dropping a pending transfer leaves the command running.
+pub struct QspiAsync<BANK: QspiPins, STREAM, const CH: u8> {
+    qspi: Qspi<BANK>,
+    stream: STREAM,
+}
+
+impl<BANK, STREAM, const CH: u8> QspiAsync<BANK, STREAM, CH>
+where
+    BANK: QspiPins,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Returns the [`Qspi`] and the DMA stream
+    pub fn release(self) -> (Qspi<BANK>, STREAM) {
+        (self.qspi, self.stream)
+    }
+
+    /// Performs an indirect read of up to 65535 bytes with the given command.
+    pub async fn read(&mut self, command: QspiReadCommand<'_>) -> Result<(), QspiError> {
+        let buffer = command.data.0;
+        if buffer.is_empty() || buffer.len() > u16::MAX as usize {
+            return Err(QspiError::IllegalArgument);
+        }
+        if self.qspi.is_busy() {
+            return Err(QspiError::Busy);
+        }
+
+        let qspi = &self.qspi.qspi;
+        qspi.fcr().write(|w| {
+            w.ctcf().set_bit();
+            w.ctef().set_bit()
+        });
+        qspi.dlr()
+            .write(|w| unsafe { w.dl().bits(buffer.len() as u32 - 1) });
+        qspi.cr().modify(|_, w| w.dmaen().set_bit());
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.stream,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::PeripheralToMemory,
+                qspi.dr().as_ptr() as u32,
+                buffer.as_mut_ptr() as *const u8,
+                buffer.len() as u16,
+                true,
+            )
+        };
+        let started = self.qspi.write_command(Command {
+            fmode: 0b01, /* Indirect read */
+            instruction: command.instruction,
+            address: command.address,
+            alternate_bytes: command.alternate_bytes,
+            dummy_cycles: command.dummy_cycles,
+            dmode: command.data.1 as u8,
+            double_data_rate: command.double_data_rate,
+        });
+
+        let result = match started {
+            Ok(()) => wait_transfer(&self.qspi.qspi, &mut transfer).await,
+            Err(error) => Err(error),
+        };
+        drop(transfer);
+        self.qspi
+            .finish_command(command.double_data_rate, result.is_err());
+        result
+    }
+
+    /// Performs an indirect write with the given command, data is limited to 65535 bytes.
+    pub async fn write(&mut self, command: QspiWriteCommand<'_>) -> Result<(), QspiError> {
+        if matches!(command.data, Some((data, _)) if data.is_empty() || data.len() > u16::MAX as usize)
+        {
+            return Err(QspiError::IllegalArgument);
+        }
+        if self.qspi.is_busy() {
+            return Err(QspiError::Busy);
+        }
+
+        let qspi = &self.qspi.qspi;
+        qspi.fcr().write(|w| {
+            w.ctcf().set_bit();
+            w.ctef().set_bit()
+        });
+        let mut dmode = 0;
+        if let Some((data, mode)) = command.data {
+            qspi.dlr()
+                .write(|w| unsafe { w.dl().bits(data.len() as u32 - 1) });
+            qspi.cr().modify(|_, w| w.dmaen().set_bit());
+            dmode = mode as u8;
+        }
+        let mut transfer = command.data.map(|(data, _)| {
+            // NOTE(unsafe) the data outlives the transfer which is stopped on drop
+            unsafe {
+                OneShot::start(
+                    &mut self.stream,
+                    ChannelX::<CH>::VALUE,
+                    DmaDirection::MemoryToPeripheral,
+                    qspi.dr().as_ptr() as u32,
+                    data.as_ptr(),
+                    data.len() as u16,
+                    true,
+                )
+            }
+        });
+        let started = self.qspi.write_command(Command {
+            fmode: 0b00, /* Indirect write mode */
+            instruction: command.instruction,
+            address: command.address,
+            alternate_bytes: command.alternate_bytes,
+            dummy_cycles: command.dummy_cycles,
+            dmode,
+            double_data_rate: command.double_data_rate,
+        });
+
+        let result = match (started, transfer.as_mut()) {
+            (Err(error), _) => Err(error),
+            (Ok(()), Some(transfer)) => wait_transfer(&self.qspi.qspi, transfer).await,
+            (Ok(()), None) => wait_complete(&self.qspi.qspi).await,
+        };
+        drop(transfer);
+        self.qspi
+            .finish_command(command.double_data_rate, result.is_err());
+        result
+    }
+}
+
+/// Waits for the end of both the DMA `transfer` and the command
+async fn wait_transfer<STREAM: Stream>(
+    qspi: &QUADSPI,
+    transfer: &mut OneShot<'_, STREAM>,
+) -> Result<(), QspiError> {
+    let mut dma_done = false;
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        let sr = qspi.sr().read();
+        if sr.tef().bit_is_set() {
+            return Poll::Ready(Err(QspiError::Unknown));
+        }
+        if !dma_done {
+            match transfer.poll(cx) {
+                Poll::Ready(Ok(())) => dma_done = true,
+                Poll::Ready(Err(_)) => return Poll::Ready(Err(QspiError::Dma)),
+                Poll::Pending => {}
+            }
+        }
+        // Data left in the FIFO is still read by DMA after the command completes
+        if sr.tcf().bit_is_set() {
+            if dma_done {
+                return Poll::Ready(Ok(()));
+            }
+            qspi.cr().modify(|_, w| w.teie().set_bit());
+        } else {
+            qspi.cr().modify(|_, w| {
+                w.tcie().set_bit();
+                w.teie().set_bit()
+            });
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Waits for the end of a command without data
+async fn wait_complete(qspi: &QUADSPI) -> Result<(), QspiError> {
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        let sr = qspi.sr().read();
+        if sr.tef().bit_is_set() {
+            Poll::Ready(Err(QspiError::Unknown))
+        } else if sr.tcf().bit_is_set() {
+            Poll::Ready(Ok(()))
+        } else {
+            qspi.cr().modify(|_, w| {
+                w.tcie().set_bit();
+                w.teie().set_bit()
+            });
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+impl<BANK: QspiPins, STREAM, const CH: u8> Deref for QspiAsync<BANK, STREAM, CH> {
+    type Target = Qspi<BANK>;
+
+    fn deref(&self) -> &Qspi<BANK> {
+        &self.qspi
+    }
+}
+
+impl<BANK: QspiPins, STREAM, const CH: u8> DerefMut for QspiAsync<BANK, STREAM, CH> {
+    fn deref_mut(&mut self) -> &mut Qspi<BANK> {
+        &mut self.qspi
+    }
+}
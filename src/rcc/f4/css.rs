@@ -0,0 +1,98 @@
+//! HSE failure handling with the Clock Security System
+//!
+//! ```ignore
+//! let mut rcc = dp.RCC.freeze(
+//!     Config::hse(8.MHz())
+//!         .sysclk(168.MHz())
+//!         .enable_css(Config::hsi().sysclk(48.MHz())),
+//! );
+//!
+//! #[exception]
+//! fn NonMaskableInt() {
+//!     rcc::on_nmi();
+//! }
+//!
+//! // In the application
+//! if let Some(clocks) = rcc.css_clocks() {
+//!     // Running from the HSI, reconfigure the peripherals from `clocks`
+//! }
+//! ```
+
+use super::*;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+
+/// Value shared with the NMI
+struct NmiCell<T>(UnsafeCell<T>);
+
+unsafe impl<T> Sync for NmiCell<T> {}
+
+/// Fallback of [`Config::enable_css`], written only while the CSS is off
+static CSS_FALLBACK: NmiCell<Option<Plan>> = NmiCell(UnsafeCell::new(None));
+/// Clocks set up by [`on_nmi`], written only from the NMI
+static CSS_CLOCKS: NmiCell<Option<Clocks>> = NmiCell(UnsafeCell::new(None));
+/// Odd while the NMI writes `CSS_CLOCKS`
+static CSS_SEQ: AtomicU32 = AtomicU32::new(0);
+
+/// Handles an HSE failure detected by the Clock Security System, must be called from the
+/// `NonMaskableInt` exception handler when [`Config::enable_css`] is used
+///
+/// The hardware has already switched the system clock to HSI and stopped the HSE and the
+/// PLL. The clock security flag is cleared and the clocks are set up from the fallback
+/// configuration given to [`Config::enable_css`], which was checked then. The new clocks
+/// are returned and published for [`Rcc::css_clocks`].
+///
+/// Returns `None` if the NMI has another source.
+pub fn on_nmi() -> Option<Clocks> {
+    let rcc = unsafe { &*RCC::ptr() };
+    if rcc.cir().read().cssf().bit_is_clear() {
+        return None;
+    }
+    rcc.cir().write(|w| w.cssc().set_bit());
+
+    let plan = unsafe { CSS_FALLBACK.0.get().read_volatile() }?;
+    Rcc::switch_to_hsi();
+    Rcc::apply(&plan);
+    let clocks = plan.clocks;
+
+    // The NMI can not be preempted, the readers retry if they have been
+    CSS_SEQ.fetch_add(1, Ordering::Relaxed);
+    compiler_fence(Ordering::SeqCst);
+    unsafe { CSS_CLOCKS.0.get().write_volatile(Some(clocks)) };
+    compiler_fence(Ordering::SeqCst);
+    CSS_SEQ.fetch_add(1, Ordering::Release);
+
+    Some(clocks)
+}
+
+/// Stores the fallback of [`Config::enable_css`], the CSS must be off
+pub(super) fn set_fallback(plan: Plan) {
+    unsafe { CSS_FALLBACK.0.get().write_volatile(Some(plan)) };
+}
+
+/// Forgets the published clocks, the CSS must be off
+pub(super) fn clear_css_clocks() {
+    unsafe { CSS_CLOCKS.0.get().write_volatile(None) };
+}
+
+impl Rcc {
+    /// Returns the clocks set up by [`on_nmi`] after an HSE failure, which then become the
+    /// current [`clocks`](Self::clocks)
+    ///
+    /// Returns `None` while the HSE works, and after [`reconfigure`](Self::reconfigure).
+    pub fn css_clocks(&mut self) -> Option<Clocks> {
+        let clocks = loop {
+            let seq = CSS_SEQ.load(Ordering::Acquire);
+            compiler_fence(Ordering::SeqCst);
+            let clocks = unsafe { CSS_CLOCKS.0.get().read_volatile() };
+            compiler_fence(Ordering::SeqCst);
+            if seq % 2 == 0 && seq == CSS_SEQ.load(Ordering::Acquire) {
+                break clocks;
+            }
+        };
+        if let Some(clocks) = clocks {
+            self.clocks = clocks;
+        }
+        clocks
+    }
+}
@@ -124,6 +124,34 @@ bus! {
     GPIOK => (AHB1, 10),
 }
 
+#[cfg(all(
+    feature = "eth",
+    not(any(feature = "stm32f405", feature = "stm32f415"))
+))]
+bus! {
+    ETHERNET_MAC => (AHB1, 25),
+}
+
+#[cfg(feature = "dcmi")]
+bus! {
+    DCMI => (AHB2, 0),
+}
+
+#[cfg(feature = "aes")]
+bus! {
+    AES => (AHB2, 4),
+}
+
+#[cfg(feature = "cryp")]
+bus! {
+    CRYP => (AHB2, 4),
+}
+
+#[cfg(feature = "hash")]
+bus! {
+    HASH => (AHB2, 5),
+}
+
 #[cfg(feature = "rng")]
 bus! {
     RNG => (AHB2, 6),
@@ -158,6 +186,10 @@ bus! {
     PWR => (APB1, 28),
 }
 
+bus! {
+    WWDG => (APB1, 11),
+}
+
 bus! {
     SPI1 => (APB2, 12),
     SPI2 => (APB1, 14),
@@ -194,6 +226,22 @@ bus! {
 bus! {
     FMPI2C1 => (APB1, 24),
 }
+#[cfg(feature = "gpio-f410")]
+bus! {
+    LPTIM1 => (APB1, 9),
+}
+#[cfg(feature = "gpio-f413")]
+bus! {
+    LPTIM => (APB1, 9),
+}
+#[cfg(feature = "spdifrx")]
+bus! {
+    SPDIFRX => (APB1, 16),
+}
+#[cfg(feature = "cec")]
+bus! {
+    HDMI_CEC => (APB1, 27),
+}
 
 bus! {
     USART1 => (APB2, 4),
@@ -288,6 +336,21 @@ bus! {
     SAI2 => (APB2, 23),
 }
 
+#[cfg(feature = "gpio-f412")]
+bus! {
+    DFSDM => (APB2, 24),
+}
+
+#[cfg(feature = "gpio-f413")]
+bus! {
+    DFSDM1 => (APB2, 24),
+}
+
+#[cfg(feature = "dfsdm2")]
+bus! {
+    DFSDM2 => (APB2, 25),
+}
+
 #[cfg(feature = "sdio")]
 bus! {
     SDIO => (APB2, 11),
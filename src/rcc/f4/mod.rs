@@ -10,6 +10,12 @@ mod pll;
 
 mod enable;
 
+mod reset;
+pub use reset::*;
+
+mod css;
+pub use css::*;
+
 /// Built-in high speed clock frequency
 pub const HSI: u32 = 16_000_000; // Hz
 
@@ -78,6 +84,8 @@ pub const PCLK1_MAX: u32 = PCLK2_MAX / 2;
 pub struct Config {
     hse: Option<u32>,
     hse_bypass: bool,
+    /// Fallback clocks of the CSS
+    css: Option<Plan>,
     hclk: Option<u32>,
     pclk1: Option<u32>,
     pclk2: Option<u32>,
@@ -109,6 +117,7 @@ impl Config {
     pub const DEFAULT: Self = Self {
         hse: None,
         hse_bypass: false,
+        css: None,
         hclk: None,
         pclk1: None,
         pclk2: None,
@@ -159,6 +168,29 @@ impl Config {
         }
     }
 
+    /// Enables the Clock Security System, which switches the system clock to HSI, stops the
+    /// HSE and the PLL and triggers the NMI when the HSE fails. [`on_nmi`] then sets up the
+    /// clocks of `fallback`, without HSE.
+    ///
+    /// This function has no effect unless use_hse() is also called.
+    ///
+    /// # Panics
+    ///
+    /// If the clocks of `fallback` are out of range. They are checked here, so that
+    /// [`on_nmi`] can't panic.
+    pub fn enable_css(self, fallback: Config) -> Self {
+        let fallback = Config {
+            hse: None,
+            hse_bypass: false,
+            css: None,
+            ..fallback
+        };
+        Self {
+            css: Some(Rcc::plan(&fallback, false)),
+            ..self
+        }
+    }
+
     pub fn hclk(mut self, freq: Hertz) -> Self {
         self.hclk = Some(freq.raw());
         self
@@ -342,8 +374,9 @@ impl Rcc {
     }
 
     /// Apply clock configuration
-    pub fn freeze(self, rcc_cfg: Config) -> Self {
-        self.freeze_internal(rcc_cfg, false)
+    pub fn freeze(mut self, rcc_cfg: Config) -> Self {
+        self.clocks = Self::freeze_internal(rcc_cfg, false);
+        self
     }
 
     /// Initialises the hardware according to Config state returning a Clocks instance.
@@ -353,18 +386,121 @@ impl Rcc {
     ///
     /// This method does not check if the clocks are bigger or smaller than the officially
     /// recommended.
-    pub unsafe fn freeze_unchecked(self, rcc_cfg: Config) -> Self {
-        self.freeze_internal(rcc_cfg, true)
+    pub unsafe fn freeze_unchecked(mut self, rcc_cfg: Config) -> Self {
+        self.clocks = Self::freeze_internal(rcc_cfg, true);
+        self
+    }
+
+    /// Switches the clocks to `rcc_cfg` at runtime, for example to run from the HSI during
+    /// the low-power phases and back from the PLL afterwards, and returns the new [`Clocks`]
+    ///
+    /// The system clock first falls back to the HSI, then the over-drive, the PLLs and the
+    /// HSE are stopped. The clocks are then set up as by [`freeze`](Self::freeze), with the
+    /// flash wait states, the voltage scale and the bus prescalers programmed while running
+    /// from the HSI. The I2S and SAI clocks are interrupted meanwhile.
+    ///
+    /// The peripherals keep the timings computed from the previous `Clocks`: call
+    /// `configure` on the timers and delays, `Serial::set_baudrate` on the serial ports, or
+    /// create them again.
+    pub fn reconfigure(&mut self, rcc_cfg: Config) -> Clocks {
+        Self::switch_to_hsi();
+        css::clear_css_clocks();
+        self.clocks = Self::freeze_internal(rcc_cfg, false);
+        self.clocks
+    }
+
+    /// Runs from the HSI and stops the over-drive, the PLLs and the HSE
+    fn switch_to_hsi() {
+        let rcc = unsafe { &*RCC::ptr() };
+
+        rcc.cr().modify(|_, w| w.hsion().set_bit());
+        while rcc.cr().read().hsirdy().bit_is_clear() {}
+        rcc.cfgr().modify(|_, w| w.sw().variant(SW::Hsi));
+        while !rcc.cfgr().read().sws().is_hsi() {}
+
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        if rcc.apb1enr().read().pwren().bit_is_set() {
+            let pwr = unsafe { &*crate::pac::PWR::ptr() };
+            pwr.cr().modify(|_, w| w.odswen().clear_bit());
+            while pwr.csr().read().odswrdy().bit_is_set() {}
+            pwr.cr().modify(|_, w| w.oden().clear_bit());
+        }
+
+        rcc.cr().modify(|_, w| {
+            #[cfg(not(feature = "gpio-f410"))]
+            w.plli2son().clear_bit();
+            #[cfg(feature = "sai")]
+            #[cfg(not(feature = "gpio-f413"))]
+            w.pllsaion().clear_bit();
+            w.pllon().clear_bit();
+            w.csson().clear_bit();
+            w.hseon().clear_bit()
+        });
+        while rcc.cr().read().pllrdy().bit_is_set() {}
+        // HSEBYP can only be written while the HSE is stopped
+        while rcc.cr().read().hserdy().bit_is_set() {}
+        rcc.cr().modify(|_, w| w.hsebyp().clear_bit());
     }
 
-    fn freeze_internal(self, rcc_cfg: Config, unchecked: bool) -> Self {
+    /// Selects the lowest voltage scale supporting `hclk`, the PLL must be stopped
+    fn voltage_scale_setup(hclk: u32) {
         let rcc = unsafe { &*RCC::ptr() };
+        rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
+        // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
+        cortex_m::asm::dsb();
+
+        let pwr = unsafe { &*crate::pac::PWR::ptr() };
+        #[cfg(feature = "gpio-f401")]
+        pwr.cr().modify(|_, w| match hclk {
+            0..=60_000_000 => w.vos().scale3(),
+            _ => w.vos().scale2(),
+        });
+        #[cfg(any(
+            feature = "gpio-f410",
+            feature = "gpio-f411",
+            feature = "gpio-f412",
+            feature = "gpio-f413"
+        ))]
+        pwr.cr().modify(|_, w| match hclk {
+            0..=64_000_000 => w.vos().scale3(),
+            64_000_001..=84_000_000 => w.vos().scale2(),
+            _ => w.vos().scale1(),
+        });
+        #[cfg(feature = "gpio-f417")]
+        pwr.cr().modify(|_, w| match hclk {
+            0..=144_000_000 => w.vos().scale2(),
+            _ => w.vos().scale1(),
+        });
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        pwr.cr().modify(|_, w| match hclk {
+            0..=120_000_000 => w.vos().scale3(),
+            120_000_001..=144_000_000 => w.vos().scale2(),
+            _ => w.vos().scale1(),
+        });
+    }
+
+    fn freeze_internal(rcc_cfg: Config, unchecked: bool) -> Clocks {
+        let plan = Self::plan(&rcc_cfg, unchecked);
+        if let Some(fallback) = rcc_cfg.css {
+            // Stored while the CSS is still off
+            css::set_fallback(fallback);
+        }
+        Self::apply(&plan);
+        plan.clocks
+    }
 
+    /// Computes the clocks of `rcc_cfg` and the register values setting them up, without
+    /// touching the hardware
+    ///
+    /// # Panics
+    ///
+    /// If the clocks are out of range, unless `unchecked`, or can't be generated.
+    fn plan(rcc_cfg: &Config, unchecked: bool) -> Plan {
         let pllsrcclk = rcc_cfg.hse.unwrap_or(HSI);
         let sysclk = rcc_cfg.sysclk.unwrap_or(pllsrcclk);
         let sysclk_on_pll = sysclk != pllsrcclk;
 
-        let plls = pll::PllSetup::from_cfgr(&rcc_cfg, pllsrcclk, sysclk_on_pll.then_some(sysclk));
+        let plls = pll::PllSetup::from_cfgr(rcc_cfg, pllsrcclk, sysclk_on_pll.then_some(sysclk));
         let sysclk = if sysclk_on_pll {
             plls.pllsysclk.unwrap()
         } else {
@@ -424,26 +560,88 @@ impl Rcc {
 
         assert!(unchecked || pclk2 <= PCLK2_MAX);
 
-        Self::flash_setup(sysclk);
+        let pclk_mul = if ppre1 == 1 { 1 } else { 2 };
+        let timclk1 = Hertz::from_raw(pclk1 * pclk_mul);
+
+        let pclk_mul = if ppre2 == 1 { 1 } else { 2 };
+        let timclk2 = Hertz::from_raw(pclk2 * pclk_mul);
+
+        let clocks = Clocks {
+            hclk: hclk.Hz(),
+            pclk1: pclk1.Hz(),
+            pclk2: pclk2.Hz(),
+            timclk1,
+            timclk2,
+            sysclk: sysclk.Hz(),
+            hse: rcc_cfg.hse.map(Hertz::from_raw),
+            pll48clk: plls.pll48clk.map(Hertz::from_raw),
+
+            #[cfg(not(feature = "rcc_i2s_apb"))]
+            i2s_clk: plls.i2s.i2s_clk.map(Hertz::from_raw),
+            #[cfg(feature = "rcc_i2s_apb")]
+            i2s_apb1_clk: plls.i2s.apb1.i2s_clk.map(Hertz::from_raw),
+            #[cfg(feature = "rcc_i2s_apb")]
+            i2s_apb2_clk: plls.i2s.apb2.i2s_clk.map(Hertz::from_raw),
+
+            #[cfg(feature = "sai")]
+            #[cfg(not(feature = "sai2"))]
+            saia_clk: plls.sai.sai1_clk.map(Hertz::from_raw),
+            #[cfg(feature = "sai")]
+            #[cfg(not(feature = "sai2"))]
+            saib_clk: plls.sai.sai2_clk.map(Hertz::from_raw),
+            #[cfg(feature = "sai2")]
+            sai1_clk: plls.sai.sai1_clk.map(Hertz::from_raw),
+            #[cfg(feature = "sai2")]
+            sai2_clk: plls.sai.sai2_clk.map(Hertz::from_raw),
+        };
+
+        if rcc_cfg.pll48clk {
+            assert!(clocks.is_pll48clk_valid());
+        }
+
+        Plan {
+            hse: rcc_cfg.hse.is_some(),
+            hse_bypass: rcc_cfg.hse_bypass,
+            css: rcc_cfg.css.is_some(),
+            plls,
+            sysclk_on_pll,
+            hpre_bits,
+            ppre1_bits,
+            ppre2_bits,
+            clocks,
+        }
+    }
+
+    /// Sets up the clocks computed by [`plan`](Self::plan), the PLLs must be stopped
+    fn apply(plan: &Plan) {
+        let rcc = unsafe { &*RCC::ptr() };
+
+        plan.plls.write();
+        Self::flash_setup(plan.clocks.sysclk.raw());
+        Self::voltage_scale_setup(plan.clocks.hclk.raw());
 
-        if rcc_cfg.hse.is_some() {
+        if plan.hse {
             // enable HSE and wait for it to be ready
             rcc.cr().modify(|_, w| {
-                if rcc_cfg.hse_bypass {
+                if plan.hse_bypass {
                     w.hsebyp().bypassed();
                 }
                 w.hseon().set_bit()
             });
             while rcc.cr().read().hserdy().bit_is_clear() {}
+
+            if plan.css {
+                rcc.cr().modify(|_, w| w.csson().set_bit());
+            }
         }
 
-        if plls.use_pll {
+        if plan.plls.use_pll {
             // Enable PLL
             rcc.cr().modify(|_, w| w.pllon().set_bit());
 
             // Enable voltage regulator overdrive if HCLK is above the limit
             #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
-            if hclk > 168_000_000 {
+            if plan.clocks.hclk.raw() > 168_000_000 {
                 // Enable clock for PWR peripheral
                 rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
 
@@ -459,10 +657,14 @@ impl Rcc {
 
             // Wait for PLL to stabilise
             while rcc.cr().read().pllrdy().bit_is_clear() {}
+
+            // The voltage scale selected above is applied once the PLL is on
+            let pwr = unsafe { &*crate::pac::PWR::ptr() };
+            while pwr.csr().read().vosrdy().bit_is_clear() {}
         }
 
         #[cfg(not(feature = "gpio-f410"))]
-        if plls.use_i2spll {
+        if plan.plls.use_i2spll {
             // Enable PLL.
             rcc.cr().modify(|_, w| w.plli2son().set_bit());
 
@@ -472,7 +674,7 @@ impl Rcc {
 
         #[cfg(feature = "sai")]
         #[cfg(not(feature = "gpio-f413"))]
-        if plls.use_saipll {
+        if plan.plls.use_saipll {
             // Enable PLL.
             rcc.cr().modify(|_, w| w.pllsaion().set_bit());
 
@@ -481,15 +683,15 @@ impl Rcc {
         }
 
         // Select I2S and SAI clocks
-        plls.i2s.config_clocksel();
+        plan.plls.i2s.config_clocksel();
         #[cfg(feature = "sai")]
-        plls.sai.config_clocksel();
+        plan.plls.sai.config_clocksel();
 
         // Set scaling factors
         rcc.cfgr().modify(|_, w| unsafe {
-            w.ppre2().bits(ppre2_bits);
-            w.ppre1().bits(ppre1_bits);
-            w.hpre().variant(hpre_bits)
+            w.ppre2().bits(plan.ppre2_bits);
+            w.ppre1().bits(plan.ppre1_bits);
+            w.hpre().variant(plan.hpre_bits)
         });
 
         // Wait for the new prescalers to kick in
@@ -498,60 +700,31 @@ impl Rcc {
 
         // Select system clock source
         rcc.cfgr().modify(|_, w| {
-            w.sw().variant(if sysclk_on_pll {
+            w.sw().variant(if plan.sysclk_on_pll {
                 SW::Pll
-            } else if rcc_cfg.hse.is_some() {
+            } else if plan.hse {
                 SW::Hse
             } else {
                 SW::Hsi
             })
         });
-
-        let pclk_mul = if ppre1 == 1 { 1 } else { 2 };
-        let timclk1 = Hertz::from_raw(pclk1 * pclk_mul);
-
-        let pclk_mul = if ppre2 == 1 { 1 } else { 2 };
-        let timclk2 = Hertz::from_raw(pclk2 * pclk_mul);
-
-        let clocks = Clocks {
-            hclk: hclk.Hz(),
-            pclk1: pclk1.Hz(),
-            pclk2: pclk2.Hz(),
-            timclk1,
-            timclk2,
-            sysclk: sysclk.Hz(),
-            pll48clk: plls.pll48clk.map(Hertz::from_raw),
-
-            #[cfg(not(feature = "rcc_i2s_apb"))]
-            i2s_clk: plls.i2s.i2s_clk.map(Hertz::from_raw),
-            #[cfg(feature = "rcc_i2s_apb")]
-            i2s_apb1_clk: plls.i2s.apb1.i2s_clk.map(Hertz::from_raw),
-            #[cfg(feature = "rcc_i2s_apb")]
-            i2s_apb2_clk: plls.i2s.apb2.i2s_clk.map(Hertz::from_raw),
-
-            #[cfg(feature = "sai")]
-            #[cfg(not(feature = "sai2"))]
-            saia_clk: plls.sai.sai1_clk.map(Hertz::from_raw),
-            #[cfg(feature = "sai")]
-            #[cfg(not(feature = "sai2"))]
-            saib_clk: plls.sai.sai2_clk.map(Hertz::from_raw),
-            #[cfg(feature = "sai2")]
-            sai1_clk: plls.sai.sai1_clk.map(Hertz::from_raw),
-            #[cfg(feature = "sai2")]
-            sai2_clk: plls.sai.sai2_clk.map(Hertz::from_raw),
-        };
-
-        if rcc_cfg.pll48clk {
-            assert!(clocks.is_pll48clk_valid());
-        }
-
-        Self {
-            rb: self.rb,
-            clocks,
-        }
     }
 }
 
+/// Clocks computed from a [`Config`] and the register values setting them up
+#[derive(Clone, Copy, Debug)]
+struct Plan {
+    hse: bool,
+    hse_bypass: bool,
+    css: bool,
+    plls: pll::PllSetup,
+    sysclk_on_pll: bool,
+    hpre_bits: HPRE,
+    ppre1_bits: u8,
+    ppre2_bits: u8,
+    clocks: Clocks,
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 struct I2sClocks {
@@ -736,6 +909,7 @@ pub struct Clocks {
     pub(super) timclk1: Hertz,
     pub(super) timclk2: Hertz,
     pub(super) sysclk: Hertz,
+    pub(super) hse: Option<Hertz>,
     pub(super) pll48clk: Option<Hertz>,
 
     #[cfg(not(feature = "rcc_i2s_apb"))]
@@ -767,6 +941,7 @@ impl Default for Clocks {
             timclk1: freq,
             timclk2: freq,
             sysclk: freq,
+            hse: None,
             pll48clk: None,
             #[cfg(not(feature = "rcc_i2s_apb"))]
             i2s_clk: None,
@@ -820,6 +995,11 @@ impl Clocks {
         self.sysclk
     }
 
+    /// Returns the frequency of the external oscillator, `None` if it is not used
+    pub fn hse(&self) -> Option<Hertz> {
+        self.hse
+    }
+
     /// Returns the frequency of the PLL48 clock line
     pub fn pll48clk(&self) -> Option<Hertz> {
         self.pll48clk
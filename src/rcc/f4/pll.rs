@@ -17,6 +17,16 @@ pub struct PllSetup {
     pub use_saipll: bool,
     #[cfg(feature = "sai")]
     pub(super) sai: super::RealSaiClocks,
+
+    main_pll: MainPll,
+    #[cfg(not(feature = "gpio-f410"))]
+    i2s_pll: I2sPll,
+    #[cfg(feature = "sai")]
+    #[cfg(not(feature = "gpio-f413"))]
+    sai_pll: SaiPll,
+    /// Divider of the SAI clock from the I2S PLL
+    #[cfg(feature = "gpio-f413")]
+    plli2sdivr: Option<u8>,
 }
 
 impl PllSetup {
@@ -48,20 +58,20 @@ impl PllSetup {
             pll48clk: main_pll.pll48clk(),
 
             i2s: i2s_clocks.real(plli2sclk, cfgr.i2s_ckin),
+
+            main_pll,
         }
     }
 
     #[cfg(feature = "gpio-f413")]
     #[inline(always)]
     pub fn from_cfgr(cfgr: &Config, pllsrcclk: u32, pllsysclk: Option<u32>) -> Self {
-        let rcc = unsafe { &*RCC::ptr() };
-
         let i2s_clocks = cfgr.i2s_clocks();
         let sai_clocks = cfgr.sai_clocks();
 
         let main_pll = MainPll::fast_setup(pllsrcclk, cfgr.hse.is_some(), pllsysclk, cfgr.pll48clk);
 
-        let (i2s_pll, real_sai_clk) = if let Some(i2s_clk) = i2s_clocks.pll_i2s_clk {
+        let (i2s_pll, real_sai_clk, plli2sdivr) = if let Some(i2s_clk) = i2s_clocks.pll_i2s_clk {
             // Currently, we only support generating SAI/PLL clocks with the I2S PLL. This is only
             // really usable when the frequencies are identical or the I2S frequency is a multiple of
             // the SAI frequency. Therefore, we just optimize the PLL for the I2S frequency and then
@@ -77,11 +87,10 @@ impl PllSetup {
                     },
                     31,
                 );
-                rcc.dckcfgr().modify(|_, w| w.plli2sdivr().set(div as u8));
                 let real_sai_clk = sai_clk / div;
-                (i2s_pll, Some(real_sai_clk))
+                (i2s_pll, Some(real_sai_clk), Some(div as u8))
             } else {
-                (i2s_pll, None)
+                (i2s_pll, None, None)
             }
         } else if let Some(pll_sai_clk) = sai_clocks.pll_sai_clk {
             // We try all divider values to get the best approximation of the requested frequency.
@@ -98,10 +107,9 @@ impl PllSetup {
                 })
                 .min_by_key(|(_, real_clk, _)| (*real_clk as i32 - pll_sai_clk as i32).abs())
                 .unwrap();
-            rcc.dckcfgr().modify(|_, w| w.plli2sdivr().set(div as u8));
-            (i2s_pll, Some(real_sai_clk))
+            (i2s_pll, Some(real_sai_clk), Some(div as u8))
         } else {
-            (I2sPll::Unused, None)
+            (I2sPll::Unused, None, None)
         };
 
         Self {
@@ -113,6 +121,10 @@ impl PllSetup {
             i2s: i2s_clocks.real(i2s_pll.plli2sclk(), cfgr.i2s_ckin),
 
             sai: sai_clocks.real(real_sai_clk, cfgr.i2s_ckin),
+
+            main_pll,
+            i2s_pll,
+            plli2sdivr,
         }
     }
 
@@ -155,20 +167,50 @@ impl PllSetup {
             use_saipll: sai_pll.use_pll(),
             #[cfg(feature = "sai")]
             sai: sai_clocks.real(sai_pll.sai_clk(), cfgr.i2s_ckin),
+
+            main_pll,
+            i2s_pll,
+            #[cfg(feature = "sai")]
+            sai_pll,
         }
     }
+
+    /// Writes the configuration of the PLLs, which must be stopped
+    pub fn write(&self) {
+        self.main_pll.write();
+        #[cfg(not(feature = "gpio-f410"))]
+        self.i2s_pll.write();
+        #[cfg(feature = "gpio-f413")]
+        if let Some(div) = self.plli2sdivr {
+            let rcc = unsafe { &*RCC::ptr() };
+            rcc.dckcfgr().modify(|_, w| w.plli2sdivr().set(div));
+        }
+        #[cfg(feature = "sai")]
+        #[cfg(not(feature = "gpio-f413"))]
+        self.sai_pll.write();
+    }
 }
 
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum MainPll {
     Used {
         pllsysclk: Option<u32>,
         pll48clk: Option<u32>,
         /// "M" divisor, required for the other PLLs on some MCUs.
-        #[allow(unused)]
         m: u32,
+        n: u16,
+        /// `PLLP` field, the "P" divisor is `2 * (p + 1)`
+        p: Option<u8>,
+        q: Option<u8>,
+        #[cfg(feature = "gpio-f410")]
+        r: u8,
+        use_hse: bool,
+    },
+    Unused {
+        /// The source also applies to the I2S and SAI PLLs
+        use_hse: bool,
     },
-    Unused,
 }
 
 impl MainPll {
@@ -178,13 +220,13 @@ impl MainPll {
     fn pllsysclk(&self) -> Option<u32> {
         match self {
             Self::Used { pllsysclk, .. } => *pllsysclk,
-            Self::Unused => None,
+            Self::Unused { .. } => None,
         }
     }
     fn pll48clk(&self) -> Option<u32> {
         match self {
             Self::Used { pll48clk, .. } => *pll48clk,
-            Self::Unused => None,
+            Self::Unused { .. } => None,
         }
     }
     #[allow(unused)]
@@ -201,11 +243,7 @@ impl MainPll {
         if pllsysclk.is_none() && !pll48clk {
             // Even if we do not use the main PLL, we still need to set the PLL source as that setting
             // applies to the I2S and SAI PLLs as well.
-            unsafe { &*RCC::ptr() }
-                .pllcfgr()
-                .write(|w| w.pllsrc().bit(use_hse));
-
-            return Self::Unused;
+            return Self::Unused { use_hse };
         }
         // Input divisor from PLL source clock, must result to frequency in
         // the range from 1 to 2 MHz
@@ -256,22 +294,49 @@ impl MainPll {
         let pllq = (vco_in * plln + 47_999_999) / 48_000_000;
         let real_pll48clk = vco_in * plln / pllq;
 
-        unsafe { &*RCC::ptr() }.pllcfgr().write(|w| unsafe {
-            w.pllm().bits(pllm as u8);
-            w.plln().bits(plln as u16);
-            w.pllp().bits(pllp as u8);
-            w.pllq().bits(pllq as u8);
-            w.pllsrc().bit(use_hse)
-        });
-
         let real_pllsysclk = vco_in * plln / sysclk_div;
 
         Self::Used {
             pllsysclk: Some(real_pllsysclk),
             pll48clk: pll48clk.then_some(real_pll48clk),
             m: pllm,
+            n: plln as u16,
+            p: Some(pllp as u8),
+            q: Some(pllq as u8),
+            #[cfg(feature = "gpio-f410")]
+            r: 2,
+            use_hse,
         }
     }
+
+    fn write(&self) {
+        let rcc = unsafe { &*RCC::ptr() };
+        match *self {
+            Self::Used {
+                m,
+                n,
+                p,
+                q,
+                #[cfg(feature = "gpio-f410")]
+                r,
+                use_hse,
+                ..
+            } => rcc.pllcfgr().write(|w| unsafe {
+                w.pllm().bits(m as u8);
+                w.plln().bits(n);
+                if let Some(p) = p {
+                    w.pllp().bits(p);
+                }
+                if let Some(q) = q {
+                    w.pllq().bits(q);
+                }
+                #[cfg(feature = "gpio-f410")]
+                w.pllr().bits(r);
+                w.pllsrc().bit(use_hse)
+            }),
+            Self::Unused { use_hse } => rcc.pllcfgr().write(|w| w.pllsrc().bit(use_hse)),
+        };
+    }
 }
 
 #[cfg(feature = "gpio-f410")]
@@ -338,19 +403,6 @@ impl MainPll {
             .min_by_key(|(_, _, _, _, _, error)| *error)
             .expect("could not find a valid main PLL configuration");
 
-        unsafe { &*RCC::ptr() }.pllcfgr().write(|w| unsafe {
-            w.pllm().bits(pllm as u8);
-            w.plln().bits(plln as u16);
-            if let Some(pllp) = pllp {
-                w.pllp().bits(pllp as u8 / 2 - 1);
-            }
-            if let Some(pllq) = pllq {
-                w.pllq().bits(pllq as u8);
-            }
-            w.pllr().bits(pllr as u8);
-            w.pllsrc().bit(use_hse)
-        });
-
         let real_pllsysclk = pllp.map(|pllp| pllsrcclk / pllm * plln / pllp);
         let real_pll48clk = pllq.map(|pllq| pllsrcclk / pllm * plln / pllq);
 
@@ -359,6 +411,11 @@ impl MainPll {
                 pllsysclk: real_pllsysclk,
                 pll48clk: real_pll48clk,
                 m: pllm,
+                n: plln as u16,
+                p: pllp.map(|pllp| pllp as u8 / 2 - 1),
+                q: pllq.map(|pllq| pllq as u8),
+                r: pllr as u8,
+                use_hse,
             },
             // TODO: check this
             None,
@@ -394,13 +451,15 @@ impl MainPll {
 }
 
 #[cfg(not(feature = "gpio-f410"))]
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum I2sPll {
     Used {
         /// "M" divisor, required for the other PLLs on some MCUs.
         m: u32,
         /// PLL I2S clock output.
         plli2sclk: u32,
+        config: SingleOutputPll,
     },
     Unused,
 }
@@ -436,11 +495,10 @@ impl I2sPll {
         // the range from 1 to 2 MHz
         let pllm_min = (pllsrcclk + 1_999_999) / 2_000_000;
         let pllm_max = pllsrcclk / 1_000_000;
-        let (pll, config, _) = (pllm_min..=pllm_max)
+        let (pll, _) = (pllm_min..=pllm_max)
             .map(|m| Self::optimize_fixed_m(pllsrcclk, m, target))
-            .min_by_key(|(_, _, error)| *error)
+            .min_by_key(|(_, error)| *error)
             .expect("no suitable I2S PLL configuration found");
-        Self::apply_config(config);
         pll
     }
 
@@ -453,12 +511,10 @@ impl I2sPll {
         let Some(target) = plli2sclk else {
             return Self::Unused;
         };
-        let (pll, config, _) = Self::optimize_fixed_m(pllsrcclk, m, target);
-        Self::apply_config(config);
-        pll
+        Self::optimize_fixed_m(pllsrcclk, m, target).0
     }
 
-    fn optimize_fixed_m(pllsrcclk: u32, m: u32, plli2sclk: u32) -> (Self, SingleOutputPll, u32) {
+    fn optimize_fixed_m(pllsrcclk: u32, m: u32, plli2sclk: u32) -> (Self, u32) {
         let (config, real_plli2sclk, error) =
             SingleOutputPll::optimize(pllsrcclk, m, plli2sclk, 2, 7)
                 .expect("did not find any valid I2S PLL config");
@@ -466,13 +522,16 @@ impl I2sPll {
             Self::Used {
                 m: config.m as u32,
                 plli2sclk: real_plli2sclk,
+                config,
             },
-            config,
             error,
         )
     }
 
-    fn apply_config(config: SingleOutputPll) {
+    fn write(&self) {
+        let Self::Used { config, .. } = *self else {
+            return;
+        };
         let rcc = unsafe { &*RCC::ptr() };
         // "M" may have been written before, but the value is identical.
         #[cfg(feature = "rcc_shared_m")]
@@ -489,11 +548,14 @@ impl I2sPll {
 
 #[cfg(feature = "sai")]
 #[cfg(not(feature = "gpio-f413"))]
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SaiPll {
     Used {
         /// SAI clock (PLL output divided by the SAI clock divider).
         sai_clk: u32,
+        config: SingleOutputPll,
+        saidiv: u32,
     },
     Unused,
 }
@@ -506,7 +568,7 @@ impl SaiPll {
     }
 
     fn sai_clk(&self) -> Option<u32> {
-        if let Self::Used { sai_clk } = self {
+        if let Self::Used { sai_clk, .. } = self {
             Some(*sai_clk)
         } else {
             None
@@ -521,11 +583,10 @@ impl SaiPll {
         // the range from 1 to 2 MHz
         let pllm_min = (pllsrcclk + 1_999_999) / 2_000_000;
         let pllm_max = pllsrcclk / 1_000_000;
-        let (pll, config, saidiv, _) = (pllm_min..=pllm_max)
+        let (pll, _) = (pllm_min..=pllm_max)
             .map(|m| Self::optimize_fixed_m(pllsrcclk, m, target))
-            .min_by_key(|(_, _, _, error)| *error)
+            .min_by_key(|(_, error)| *error)
             .expect("no suitable SAI PLL configuration found");
-        Self::apply_config(config, saidiv);
         pll
     }
 
@@ -538,16 +599,10 @@ impl SaiPll {
         let Some(target) = sai_clk else {
             return Self::Unused;
         };
-        let (pll, config, saidiv, _) = Self::optimize_fixed_m(pllsrcclk, m, target);
-        Self::apply_config(config, saidiv);
-        pll
+        Self::optimize_fixed_m(pllsrcclk, m, target).0
     }
 
-    fn optimize_fixed_m(
-        pllsrcclk: u32,
-        m: u32,
-        sai_clk: u32,
-    ) -> (SaiPll, SingleOutputPll, u32, u32) {
+    fn optimize_fixed_m(pllsrcclk: u32, m: u32, sai_clk: u32) -> (SaiPll, u32) {
         // NOTE: This code tests lots of configurations due to the nested loops for the two
         // dividers. A smarter approach can probably speed up the search.
         let (config, saidiv, real_sai_clk, error) = (1..=32)
@@ -562,14 +617,17 @@ impl SaiPll {
         (
             Self::Used {
                 sai_clk: real_sai_clk,
+                config,
+                saidiv,
             },
-            config,
-            saidiv,
             error,
         )
     }
 
-    fn apply_config(config: SingleOutputPll, saidiv: u32) {
+    fn write(&self) {
+        let Self::Used { config, saidiv, .. } = *self else {
+            return;
+        };
         let rcc = unsafe { &*RCC::ptr() };
         rcc.dckcfgr()
             .modify(|_, w| w.pllsaidivq().set(saidiv as u8 - 1));
@@ -587,7 +645,9 @@ impl SaiPll {
 }
 
 #[cfg(not(feature = "gpio-f410"))]
-struct SingleOutputPll {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SingleOutputPll {
     m: u8,
     n: u16,
     outdiv: u8,
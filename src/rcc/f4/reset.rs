@@ -0,0 +1,84 @@
+//! Reset cause reporting from `RCC_CSR` and `PWR_CSR`
+
+use super::*;
+use crate::pac::PWR;
+
+/// Cause of the last reset
+///
+/// An internal reset also drives the NRST pin low, and a power-on reset also sets the
+/// brown-out flag, so only the most specific cause is reported.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ResetReason {
+    /// Low-power management reset, entering Stop or Standby while the option bytes
+    /// forbid it
+    LowPower,
+    /// Window watchdog reset
+    WindowWatchdog,
+    /// Independent watchdog reset
+    IndependentWatchdog,
+    /// Software reset, `SCB::sys_reset`
+    Software,
+    /// Power-on or power-down reset
+    PowerOn,
+    /// Brown-out reset, the supply went below the BOR threshold
+    BrownOut,
+    /// Reset by the NRST pin
+    Pin,
+    /// No flag set, they have been cleared since the reset
+    Unknown,
+}
+
+/// Reset and boot state, returned by [`Rcc::reset_status`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct ResetStatus {
+    /// Cause of the last reset
+    pub reason: ResetReason,
+    /// The device has woken up from Standby, the `SBF` flag of `PWR_CSR`
+    pub standby: bool,
+    /// A wakeup event happened, from a WKUP pin or the RTC, the `WUF` flag of `PWR_CSR`
+    pub wakeup: bool,
+}
+
+impl Rcc {
+    /// Reads the cause of the last reset and the Standby wakeup flags, then clears them
+    ///
+    /// The flags stay set across the resets until they are cleared, so call this early
+    /// and once after each reset. Enables the clock of the PWR peripheral.
+    pub fn reset_status(&mut self) -> ResetStatus {
+        let csr = self.csr().read();
+        let reason = if csr.lpwrrstf().bit_is_set() {
+            ResetReason::LowPower
+        } else if csr.wwdgrstf().bit_is_set() {
+            ResetReason::WindowWatchdog
+        } else if csr.wdgrstf().bit_is_set() {
+            ResetReason::IndependentWatchdog
+        } else if csr.sftrstf().bit_is_set() {
+            ResetReason::Software
+        } else if csr.porrstf().bit_is_set() {
+            ResetReason::PowerOn
+        } else if csr.borrstf().bit_is_set() {
+            ResetReason::BrownOut
+        } else if csr.padrstf().bit_is_set() {
+            ResetReason::Pin
+        } else {
+            ResetReason::Unknown
+        };
+        self.csr().modify(|_, w| w.rmvf().set_bit());
+
+        PWR::enable(self);
+        let pwr = unsafe { &*PWR::ptr() };
+        let pwr_csr = pwr.csr().read();
+        pwr.cr().modify(|_, w| {
+            w.csbf().set_bit();
+            w.cwuf().set_bit()
+        });
+
+        ResetStatus {
+            reason,
+            standby: pwr_csr.sbf().bit_is_set(),
+            wakeup: pwr_csr.wuf().bit_is_set(),
+        }
+    }
+}
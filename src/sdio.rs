@@ -13,6 +13,11 @@ pub use sdio_host::{
     sd_cmd, Cmd,
 };
 
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_interrupt, SdioAsync};
+
 pub trait Pins {
     const BUSWIDTH: Buswidth;
 
@@ -120,6 +125,7 @@ pub enum ClockFreq {
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[non_exhaustive]
 pub enum Error {
     Timeout,
     SoftwareTimeout,
@@ -131,6 +137,8 @@ pub enum Error {
     RxOverFlow,
     TxUnderErr,
     NoCard,
+    /// DMA transfer error
+    Dma,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -233,7 +241,7 @@ impl<P: SdioPeripheral> Sdio<P> {
             AddressMode::Block512 => blockaddr,
         };
         self.cmd(common_cmd::set_block_length(512))?;
-        self.start_datapath_transfer(512, 9, true);
+        self.start_datapath_transfer(512, 9, true, false);
         self.cmd(common_cmd::read_single_block(blockaddr))?;
 
         let mut i = 0;
@@ -277,7 +285,7 @@ impl<P: SdioPeripheral> Sdio<P> {
             AddressMode::Block512 => blockaddr,
         };
         self.cmd(common_cmd::set_block_length(512))?;
-        self.start_datapath_transfer(512, 9, false);
+        self.start_datapath_transfer(512, 9, false, false);
         self.cmd(common_cmd::write_single_block(blockaddr))?;
 
         let mut i = 0;
@@ -320,7 +328,13 @@ impl<P: SdioPeripheral> Sdio<P> {
         Ok(())
     }
 
-    fn start_datapath_transfer(&self, length_bytes: u32, block_size: u8, card_to_controller: bool) {
+    fn start_datapath_transfer(
+        &self,
+        length_bytes: u32,
+        block_size: u8,
+        card_to_controller: bool,
+        dma: bool,
+    ) {
         use crate::pac::sdio::dctrl::DTDIR;
 
         // Block Size up to 2^14 bytes
@@ -354,6 +368,7 @@ impl<P: SdioPeripheral> Sdio<P> {
                 w.dblocksize().bits(block_size);
             } // 2^n bytes block size
             w.dtdir().variant(dtdir);
+            w.dmaen().bit(dma);
             w.dten().enabled() // Enable transfer
         });
     }
@@ -557,7 +572,7 @@ impl Sdio<SdCard> {
     pub fn read_sd_status(&mut self) -> Result<SDStatus, Error> {
         let _card = self.card()?;
         self.cmd(common_cmd::set_block_length(64))?;
-        self.start_datapath_transfer(64, 6, true);
+        self.start_datapath_transfer(64, 6, true, false);
         self.app_cmd(sd_cmd::sd_status())?;
 
         let mut status = [0u32; 16];
@@ -589,7 +604,7 @@ impl Sdio<SdCard> {
     /// Get the Card configuration for card at `address`
     fn get_scr(&self, rca: u16) -> Result<SCR, Error> {
         self.cmd(common_cmd::set_block_length(8))?;
-        self.start_datapath_transfer(8, 3, true);
+        self.start_datapath_transfer(8, 3, true, false);
         self.cmd(common_cmd::app_cmd(rca))?;
         self.cmd(sd_cmd::send_scr())?;
 
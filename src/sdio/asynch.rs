@@ -0,0 +1,317 @@
+//! Async multi-block transfers with DMA.
+//!
+//! Data phases are awaited with the SDIO interrupt, which must call [`on_interrupt`],
+//! and the interrupt of the DMA2 stream, which must call
+//! [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt):
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn SDIO() {
+//!     sdio::on_interrupt();
+//! }
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM3() {
+//!     dma::Stream3::<pac::DMA2>::on_interrupt();
+//! }
+//! ```
+//!
+//! Commands are still sent by polling, they take a few microseconds. The card busy state
+//! after a transfer is polled with an increasing delay from an async
+//! [`DelayNs`](embedded_hal_async::delay::DelayNs).
+
+use core::{
+    future::poll_fn,
+    ops::{Deref, DerefMut},
+    task::Poll,
+};
+
+use atomic_waker::AtomicWaker;
+use embedded_hal_async::delay::DelayNs;
+
+use super::{common_cmd, status_to_error, AddressMode, Error, Sdio, SdioPeripheral};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use crate::pac::SDIO;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Most blocks of one transfer, limited by the 25-bit data length
+const MAX_BLOCKS: usize = 0xFFFF;
+
+/// First and longest delays between two card status polls, in microseconds
+const READY_POLL_MIN_US: u32 = 16;
+const READY_POLL_MAX_US: u32 = 1024;
+
+/// Wakes the task waiting for the end of a data transfer.
+///
+/// Must be called from the `SDIO` interrupt handler when [`SdioAsync`] is used.
+/// Interrupts are disabled, the woken task enables them again if it still has to wait.
+pub fn on_interrupt() {
+    let sdio = unsafe { &*SDIO::ptr() };
+    sdio.mask().reset();
+    WAKER.wake();
+}
+
+impl<P: SdioPeripheral> Sdio<P> {
+    /// Converts [`Sdio`] to [`SdioAsync`] that uses `stream` to move data and `delay` to
+    /// wait while the card is busy.
+    ///
+    /// The card should be initialized first, stream 3 and 6 of DMA2 can be used.
+    pub fn into_async<STREAM, const CH: u8, DELAY>(
+        self,
+        stream: STREAM,
+        delay: DELAY,
+    ) -> SdioAsync<P, STREAM, CH, DELAY>
+    where
+        STREAM: Stream,
+        ChannelX<CH>: Channel,
+        SDIO: DMASet<STREAM, CH, PeripheralToMemory> + DMASet<STREAM, CH, MemoryToPeripheral>,
+        DELAY: DelayNs,
+    {
+        SdioAsync {
+            sdio: self,
+            stream,
+            delay,
+        }
+    }
+
+    /// Stops the data path and ends a multiple block transfer with CMD12
+    fn finish_data(&self, result: Result<(), Error>, multiple: bool) -> Result<(), Error> {
+        if result.is_err() {
+            self.sdio.dctrl().reset();
+        }
+        if multiple {
+            let stop = self.cmd(common_cmd::stop_transmission());
+            result?;
+            stop
+        } else {
+            result
+        }
+    }
+}
+
+/// Sdio that reads and writes blocks with DMA
+///
+/// Derefs to [`Sdio`], so the blocking API stays available. A client must:
+/// * Enable the SDIO interrupt and call [`on_interrupt`] in it.
+/// * Enable the interrupt of the DMA stream and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in it.
+///
+/// Dropping a pending transfer leaves the card in an undefined state.
+pub struct SdioAsync<P: SdioPeripheral, STREAM, const CH: u8, DELAY> {
+    sdio: Sdio<P>,
+    stream: STREAM,
+    delay: DELAY,
+}
+
+impl<P, STREAM, const CH: u8, DELAY> SdioAsync<P, STREAM, CH, DELAY>
+where
+    P: SdioPeripheral,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    DELAY: DelayNs,
+{
+    /// Returns the [`Sdio`], the DMA stream and the delay
+    pub fn release(self) -> (Sdio<P>, STREAM, DELAY) {
+        (self.sdio, self.stream, self.delay)
+    }
+
+    /// Reads consecutive blocks from the card starting at `blockaddr`.
+    ///
+    /// More than one block are read with CMD18 followed by CMD12, in transfers of at most
+    /// 65535 blocks.
+    pub async fn read_blocks(
+        &mut self,
+        mut blockaddr: u32,
+        blocks: &mut [[u8; 512]],
+    ) -> Result<(), Error> {
+        for chunk in blocks.chunks_mut(MAX_BLOCKS) {
+            self.read_chunk(blockaddr, chunk).await?;
+            blockaddr += chunk.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// Reads up to [`MAX_BLOCKS`] blocks with one command
+    async fn read_chunk(&mut self, blockaddr: u32, blocks: &mut [[u8; 512]]) -> Result<(), Error> {
+        let blockaddr = self.address(blockaddr)?;
+        let multiple = blocks.len() > 1;
+        let fifo = self.sdio.sdio.fifo().as_ptr() as u32;
+        let buffer = blocks.as_mut_ptr() as *mut u8;
+
+        self.sdio.cmd(common_cmd::set_block_length(512))?;
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            start_dma::<_, CH>(
+                &mut self.stream,
+                DmaDirection::PeripheralToMemory,
+                fifo,
+                buffer,
+            )
+        };
+        self.sdio
+            .start_datapath_transfer(512 * blocks.len() as u32, 9, true, true);
+        let result = match if multiple {
+            self.sdio.cmd(common_cmd::read_multiple_blocks(blockaddr))
+        } else {
+            self.sdio.cmd(common_cmd::read_single_block(blockaddr))
+        } {
+            Ok(()) => wait_data_end(&self.sdio.sdio).await,
+            Err(e) => Err(e),
+        };
+        let result = self.sdio.finish_data(result, multiple);
+        // The stream drains its FIFO after the last data
+        let result = match result {
+            Ok(()) => transfer.wait().await.map_err(|_| Error::Dma),
+            Err(e) => Err(e),
+        };
+        drop(transfer);
+        result?;
+
+        self.wait_card_ready().await
+    }
+
+    /// Writes consecutive blocks to the card starting at `blockaddr`.
+    ///
+    /// More than one block are written with CMD25 followed by CMD12, in transfers of at
+    /// most 65535 blocks.
+    pub async fn write_blocks(
+        &mut self,
+        mut blockaddr: u32,
+        blocks: &[[u8; 512]],
+    ) -> Result<(), Error> {
+        for chunk in blocks.chunks(MAX_BLOCKS) {
+            self.write_chunk(blockaddr, chunk).await?;
+            blockaddr += chunk.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// Writes up to [`MAX_BLOCKS`] blocks with one command
+    async fn write_chunk(&mut self, blockaddr: u32, blocks: &[[u8; 512]]) -> Result<(), Error> {
+        let blockaddr = self.address(blockaddr)?;
+        let multiple = blocks.len() > 1;
+        let fifo = self.sdio.sdio.fifo().as_ptr() as u32;
+        let buffer = blocks.as_ptr() as *mut u8;
+
+        self.sdio.cmd(common_cmd::set_block_length(512))?;
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let transfer = unsafe {
+            start_dma::<_, CH>(
+                &mut self.stream,
+                DmaDirection::MemoryToPeripheral,
+                fifo,
+                buffer,
+            )
+        };
+        self.sdio
+            .start_datapath_transfer(512 * blocks.len() as u32, 9, false, true);
+        let result = match if multiple {
+            self.sdio.cmd(common_cmd::write_multiple_blocks(blockaddr))
+        } else {
+            self.sdio.cmd(common_cmd::write_single_block(blockaddr))
+        } {
+            Ok(()) => wait_data_end(&self.sdio.sdio).await,
+            Err(e) => Err(e),
+        };
+        drop(transfer);
+        self.sdio.finish_data(result, multiple)?;
+
+        // Wait for card to finish writing data
+        self.wait_card_ready().await
+    }
+
+    /// Converts the block address to the address mode of the card
+    fn address(&self, blockaddr: u32) -> Result<u32, Error> {
+        // SDSC cards are byte addressed hence the blockaddress is in multiples of 512 bytes
+        Ok(match self.sdio.card()?.get_address_mode() {
+            AddressMode::Byte => blockaddr * 512,
+            AddressMode::Block512 => blockaddr,
+        })
+    }
+
+    /// Polls the card state until it is back in transfer state, doubling the delay between
+    /// the polls up to about a millisecond
+    async fn wait_card_ready(&mut self) -> Result<(), Error> {
+        let mut delay_us = READY_POLL_MIN_US;
+        while !self.sdio.card_ready()? {
+            self.delay.delay_us(delay_us).await;
+            delay_us = (delay_us * 2).min(READY_POLL_MAX_US);
+        }
+        Ok(())
+    }
+}
+
+/// Starts the DMA stream, with word accesses if `buffer` is aligned
+unsafe fn start_dma<STREAM, const CH: u8>(
+    stream: &mut STREAM,
+    direction: DmaDirection,
+    fifo: u32,
+    buffer: *mut u8,
+) -> OneShot<'_, STREAM>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    if buffer as usize % 4 == 0 {
+        OneShot::start_peripheral_flow(
+            stream,
+            ChannelX::<CH>::VALUE,
+            direction,
+            fifo,
+            buffer as *const u32,
+        )
+    } else {
+        OneShot::start_peripheral_flow(
+            stream,
+            ChannelX::<CH>::VALUE,
+            direction,
+            fifo,
+            buffer as *const u8,
+        )
+    }
+}
+
+/// Waits until all data is sent or received, or a data error
+async fn wait_data_end(sdio: &SDIO) -> Result<(), Error> {
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        let sta = sdio.sta().read();
+        if sta.dataend().bit_is_set()
+            || sta.dcrcfail().bit_is_set()
+            || sta.dtimeout().bit_is_set()
+            || sta.rxoverr().bit_is_set()
+            || sta.txunderr().bit_is_set()
+        {
+            sdio.mask().reset();
+            Poll::Ready(status_to_error(sta))
+        } else {
+            sdio.mask().write(|w| {
+                w.dataendie().set_bit();
+                w.dcrcfailie().set_bit();
+                w.dtimeoutie().set_bit();
+                w.rxoverrie().set_bit();
+                w.txunderrie().set_bit()
+            });
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+impl<P: SdioPeripheral, STREAM, const CH: u8, DELAY> Deref for SdioAsync<P, STREAM, CH, DELAY> {
+    type Target = Sdio<P>;
+
+    fn deref(&self) -> &Sdio<P> {
+        &self.sdio
+    }
+}
+
+impl<P: SdioPeripheral, STREAM, const CH: u8, DELAY> DerefMut for SdioAsync<P, STREAM, CH, DELAY> {
+    fn deref_mut(&mut self) -> &mut Sdio<P> {
+        &mut self.sdio
+    }
+}
@@ -19,7 +19,7 @@ use core::marker::PhantomData;
 use enumflags2::BitFlags;
 
 #[allow(unused)]
-use crate::pacext::uart::UartRB;
+use crate::pacext::uart::{SrR, UartRB};
 mod hal_02;
 mod hal_1;
 
@@ -31,12 +31,17 @@ use crate::gpio::{self, PushPull};
 use crate::pac;
 
 use crate::rcc::{self, Rcc};
+use crate::time::Bps;
 
+#[cfg(feature = "async")]
+mod asynch;
 pub mod dma;
 use crate::dma::{
     traits::{DMASet, PeriAddress},
     MemoryToPeripheral, PeripheralToMemory,
 };
+#[cfg(feature = "async")]
+pub use asynch::{RxAsync, TxAsync};
 
 /// Serial error kind
 ///
@@ -141,6 +146,11 @@ pub trait Instance:
     fn peri_address() -> u32 {
         unsafe { &*Self::PTR }.peri_address()
     }
+
+    /// Wakers of the tasks waiting for [`Rx`] and [`Tx`]
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn wakers() -> &'static [atomic_waker::AtomicWaker; 2];
 }
 
 /// Trait for [`Rx`] interrupt handling.
@@ -315,6 +325,28 @@ impl<USART: Instance, WORD> Serial<USART, WORD> {
         serial.tx.usart.set_stopbits(config.stopbits);
         Ok(serial)
     }
+
+    /// Sets the baud rate from `clocks`, for example after
+    /// [`Rcc::reconfigure`](crate::rcc::Rcc::reconfigure)
+    ///
+    /// Waits for the end of the ongoing transmission.
+    pub fn set_baudrate(
+        &mut self,
+        baudrate: Bps,
+        clocks: &rcc::Clocks,
+    ) -> Result<(), config::InvalidConfig> {
+        let (over8, div) = calculate_brr(USART::clock(clocks).raw(), baudrate.0)?;
+
+        let uart = &self.tx.usart;
+        while uart.sr().read().tc().bit_is_clear() {}
+        uart.cr1().modify(|_, w| w.ue().clear_bit());
+        uart.brr().write(|w| unsafe { w.bits(div as u16) });
+        uart.cr1().modify(|_, w| {
+            w.over8().bit(over8);
+            w.ue().set_bit()
+        });
+        Ok(())
+    }
 }
 
 fn calculate_brr(pclk_freq: u32, baud: u32) -> Result<(bool, u32), config::InvalidConfig> {
@@ -390,7 +422,14 @@ macro_rules! halUsart {
         pub type $Tx<WORD = u8> = Tx<$USART, WORD>;
         pub type $Rx<WORD = u8> = Rx<$USART, WORD>;
 
-        impl Instance for $USART {}
+        impl Instance for $USART {
+            #[cfg(feature = "async")]
+            fn wakers() -> &'static [atomic_waker::AtomicWaker; 2] {
+                use atomic_waker::AtomicWaker;
+                static WAKERS: [AtomicWaker; 2] = [AtomicWaker::new(), AtomicWaker::new()];
+                &WAKERS
+            }
+        }
     };
 }
 pub(crate) use halUsart;
@@ -409,7 +448,14 @@ macro_rules! halUart {
         pub type $Tx<WORD = u8> = Tx<$UART, WORD>;
         pub type $Rx<WORD = u8> = Rx<$UART, WORD>;
 
-        impl Instance for $UART {}
+        impl Instance for $UART {
+            #[cfg(feature = "async")]
+            fn wakers() -> &'static [atomic_waker::AtomicWaker; 2] {
+                use atomic_waker::AtomicWaker;
+                static WAKERS: [AtomicWaker; 2] = [AtomicWaker::new(), AtomicWaker::new()];
+                &WAKERS
+            }
+        }
     };
 }
 
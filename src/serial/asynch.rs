@@ -0,0 +1,367 @@
+//! Async serial reads and writes implementing [`embedded_io_async`] traits.
+//!
+//! [`Rx`] and [`Tx`] move every byte from the interrupt, [`RxAsync`] and [`TxAsync`] use DMA
+//! for higher baud rates and long buffers.
+//!
+//! Tasks are woken by [`Serial::on_interrupt`], which must be called from the `USARTx`/`UARTx`
+//! interrupt handler:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn USART1() {
+//!     serial::Serial1::on_interrupt();
+//! }
+//! ```
+//!
+//! DMA based [`TxAsync`] and [`RxAsync`] also need
+//! [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in the interrupts of their streams.
+
+use core::{future::poll_fn, task::Poll};
+
+use enumflags2::{make_bitflags, BitFlags};
+
+use super::{CFlag, Error, Event, Flag, Instance, RegisterBlockImpl, Rx, Serial, Tx};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use crate::pacext::uart::{Cr3W, UartRB};
+
+const RX: usize = 0;
+const TX: usize = 1;
+
+const ERRORS: BitFlags<Flag> = make_bitflags!(Flag::{ParityError | FramingError | Noise | Overrun});
+
+impl<USART: Instance> Serial<USART> {
+    /// Wakes the tasks waiting for [`Rx`], [`Tx`], [`RxAsync`] or [`TxAsync`].
+    ///
+    /// Must be called from the `USARTx`/`UARTx` interrupt handler when the serial port is used
+    /// by async drivers. Interrupts are disabled, the woken tasks enable them again if they
+    /// still have to wait.
+    pub fn on_interrupt() {
+        let usart = unsafe { &*USART::ptr() };
+        usart.listen_event(
+            Some(
+                Event::Idle
+                    | Event::RxNotEmpty
+                    | Event::TxEmpty
+                    | Event::TransmissionComplete
+                    | Event::ParityError,
+            ),
+            None,
+        );
+        usart.disable_error_interrupt_generation();
+        for waker in USART::wakers() {
+            waker.wake();
+        }
+    }
+}
+
+impl<USART: Instance> Rx<USART> {
+    /// Converts [`Rx`] to [`RxAsync`] that uses `stream` to receive data
+    pub fn into_async<STREAM, const CH: u8>(self, stream: STREAM) -> RxAsync<USART, STREAM, CH>
+    where
+        STREAM: Stream,
+        ChannelX<CH>: Channel,
+        Rx<USART>: DMASet<STREAM, CH, PeripheralToMemory>,
+    {
+        self.usart.cr3().modify(|_, w| w.dmar().enabled());
+        RxAsync { rx: self, stream }
+    }
+
+    /// Reads at least one byte into `buffer` and returns early when the line becomes idle.
+    ///
+    /// Every byte is moved from the interrupt, so the task must be woken before the next
+    /// byte arrives. Use [`RxAsync`] for higher baud rates.
+    pub async fn read_async(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let usart = &self.usart;
+        let mut n = 0;
+        poll_fn(|cx| {
+            USART::wakers()[RX].register(cx.waker());
+            loop {
+                let flags = usart.flags();
+                if flags.intersects(ERRORS) {
+                    usart.unlisten_rxne();
+                    usart.unlisten_idle();
+                    return Poll::Ready(usart.check_and_clear_error_flags().map(|_| n));
+                }
+                if flags.contains(Flag::RxNotEmpty) {
+                    buffer[n] = usart.dr().read().dr().bits() as u8;
+                    n += 1;
+                    if n == buffer.len() {
+                        break;
+                    }
+                } else if flags.contains(Flag::Idle) {
+                    usart.clear_idle_interrupt();
+                    // Idle line before the first byte belongs to a previous read
+                    if n > 0 {
+                        break;
+                    }
+                } else {
+                    usart.listen_event(None, Some(Event::RxNotEmpty | Event::Idle));
+                    return Poll::Pending;
+                }
+            }
+            usart.unlisten_rxne();
+            usart.unlisten_idle();
+            Poll::Ready(Ok(n))
+        })
+        .await
+    }
+}
+
+impl<USART: Instance> embedded_io_async::Read for Rx<USART> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read_async(buf).await
+    }
+}
+
+impl<USART: Instance> embedded_io_async::Write for Tx<USART> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write_async(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush_async().await
+    }
+}
+
+impl<USART: Instance> Tx<USART> {
+    /// Sends at least one byte of `bytes` and returns how many were sent.
+    /// The last byte may still be in transmission, see [`flush_async`](Self::flush_async).
+    ///
+    /// Every byte is moved from the interrupt, use [`TxAsync`] for long buffers.
+    pub async fn write_async(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        let usart = &self.usart;
+        let mut n = 0;
+        poll_fn(|cx| {
+            USART::wakers()[TX].register(cx.waker());
+            while n < bytes.len() {
+                if usart.write_u8(bytes[n]).is_err() {
+                    if n > 0 {
+                        break;
+                    }
+                    usart.listen_event(None, Some(Event::TxEmpty.into()));
+                    return Poll::Pending;
+                }
+                n += 1;
+            }
+            usart.listen_event(Some(Event::TxEmpty.into()), None);
+            Poll::Ready(Ok(n))
+        })
+        .await
+    }
+
+    /// Waits until the last byte is transmitted
+    pub async fn flush_async(&mut self) -> Result<(), Error> {
+        let usart = &self.usart;
+        poll_fn(|cx| {
+            USART::wakers()[TX].register(cx.waker());
+            if usart.flags().contains(Flag::TransmissionComplete) {
+                usart.listen_event(Some(Event::TransmissionComplete.into()), None);
+                Poll::Ready(Ok(()))
+            } else {
+                usart.listen_event(None, Some(Event::TransmissionComplete.into()));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Converts [`Tx`] to [`TxAsync`] that uses `stream` to send data
+    pub fn into_async<STREAM, const CH: u8>(self, stream: STREAM) -> TxAsync<USART, STREAM, CH>
+    where
+        STREAM: Stream,
+        ChannelX<CH>: Channel,
+        Tx<USART>: DMASet<STREAM, CH, MemoryToPeripheral>,
+    {
+        self.usart.cr3().modify(|_, w| w.dmat().enabled());
+        TxAsync { tx: self, stream }
+    }
+}
+
+/// Serial transmitter that sends data with DMA
+///
+/// Implements [`embedded_io_async::Write`]. A client must:
+/// * Enable the USARTx/UARTx interrupt and call [`Serial::on_interrupt`] in it.
+/// * Enable the interrupt of the DMA stream and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in it.
+pub struct TxAsync<USART: Instance, STREAM, const CH: u8> {
+    tx: Tx<USART>,
+    stream: STREAM,
+}
+
+impl<USART, STREAM, const CH: u8> TxAsync<USART, STREAM, CH>
+where
+    USART: Instance,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Disables DMA requests and returns [`Tx`] and the DMA stream
+    pub fn release(self) -> (Tx<USART>, STREAM) {
+        self.tx.usart.cr3().modify(|_, w| w.dmat().disabled());
+        (self.tx, self.stream)
+    }
+
+    /// Sends up to 65535 bytes of `bytes` and returns how many were sent.
+    /// The last byte may still be in transmission, see [`flush`](Self::flush).
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        let len = bytes.len().min(u16::MAX as usize);
+        let usart = &self.tx.usart;
+        usart.clear_flags(CFlag::TransmissionComplete.into());
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.stream,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::MemoryToPeripheral,
+                usart.peri_address(),
+                bytes.as_ptr(),
+                len as u16,
+                true,
+            )
+        };
+        transfer.wait().await.map_err(|_| Error::Other)?;
+        Ok(len)
+    }
+
+    /// Waits until the last byte is transmitted
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.tx.flush_async().await
+    }
+}
+
+impl<USART: Instance, STREAM, const CH: u8> embedded_io::ErrorType for TxAsync<USART, STREAM, CH> {
+    type Error = Error;
+}
+
+impl<USART, STREAM, const CH: u8> embedded_io_async::Write for TxAsync<USART, STREAM, CH>
+where
+    USART: Instance,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
+
+/// Serial receiver that receives data with DMA
+///
+/// Implements [`embedded_io_async::Read`], reads return early when the line becomes idle.
+/// A client must:
+/// * Enable the USARTx/UARTx interrupt and call [`Serial::on_interrupt`] in it.
+/// * Enable the interrupt of the DMA stream and call
+///   [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt) in it.
+///
+/// Bytes arriving between reads stay in the data register, so only one byte can be
+/// buffered while no read is pending.
+pub struct RxAsync<USART: Instance, STREAM, const CH: u8> {
+    rx: Rx<USART>,
+    stream: STREAM,
+}
+
+enum RxEnd {
+    Complete,
+    Idle,
+}
+
+impl<USART, STREAM, const CH: u8> RxAsync<USART, STREAM, CH>
+where
+    USART: Instance,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    /// Disables DMA requests and returns [`Rx`] and the DMA stream
+    pub fn release(self) -> (Rx<USART>, STREAM) {
+        self.rx.usart.cr3().modify(|_, w| w.dmar().disabled());
+        (self.rx, self.stream)
+    }
+
+    /// Receives up to 65535 bytes into `buffer` and returns how many were received.
+    /// Returns after the first bytes when the line becomes idle.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let len = buffer.len().min(u16::MAX as usize) as u16;
+        let usart = &self.rx.usart;
+        // Idle line before the first byte belongs to a previous read
+        let flags = usart.flags();
+        if flags.contains(Flag::Idle) && !flags.contains(Flag::RxNotEmpty) {
+            usart.clear_idle_interrupt();
+        }
+
+        // NOTE(unsafe) the buffer outlives the transfer which is stopped on drop
+        let mut transfer = unsafe {
+            OneShot::start(
+                &mut self.stream,
+                ChannelX::<CH>::VALUE,
+                DmaDirection::PeripheralToMemory,
+                usart.peri_address(),
+                buffer.as_mut_ptr() as *const u8,
+                len,
+                true,
+            )
+        };
+        let end = poll_fn(|cx| {
+            USART::wakers()[RX].register(cx.waker());
+            if usart.flags().intersects(ERRORS) {
+                return Poll::Ready(usart.check_and_clear_error_flags().map(|_| RxEnd::Idle));
+            }
+            match transfer.poll(cx) {
+                Poll::Ready(result) => {
+                    return Poll::Ready(result.map(|_| RxEnd::Complete).map_err(|_| Error::Other))
+                }
+                Poll::Pending => {}
+            }
+            if usart.is_idle() {
+                usart.clear_idle_interrupt();
+                if transfer.remaining() < len {
+                    return Poll::Ready(Ok(RxEnd::Idle));
+                }
+            }
+            usart.listen_event(None, Some(Event::Idle | Event::ParityError));
+            usart.enable_error_interrupt_generation();
+            Poll::Pending
+        })
+        .await;
+        drop(transfer);
+        usart.listen_event(Some(Event::Idle | Event::ParityError), None);
+        usart.disable_error_interrupt_generation();
+
+        match end? {
+            RxEnd::Complete => Ok(len as usize),
+            // Count after the stream is stopped, so no byte arrives later
+            RxEnd::Idle => Ok((len - self.stream.number_of_transfers()) as usize),
+        }
+    }
+}
+
+impl<USART: Instance, STREAM, const CH: u8> embedded_io::ErrorType for RxAsync<USART, STREAM, CH> {
+    type Error = Error;
+}
+
+impl<USART, STREAM, const CH: u8> embedded_io_async::Read for RxAsync<USART, STREAM, CH>
+where
+    USART: Instance,
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read(buf).await
+    }
+}
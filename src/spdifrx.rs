@@ -0,0 +1,519 @@
+//! SPDIF receiver (SPDIFRX)
+//!
+//! Receives an IEC 60958 stream on one of four inputs, selected by the pin given to
+//! [`Spdifrx::new`]. The receiver first synchronizes on the stream and measures its symbol
+//! rate, then decodes the audio samples, the channel status and the user bits.
+//!
+//! The SPDIFRX is clocked by the `R` output of the main PLL, which must be at least
+//! 704 times the sample rate of the stream (33.8 MHz for 48 kHz).
+//!
+//! # Sample format
+//!
+//! In stereo mode, the samples of the left (A) and the right (B) channels alternate.
+//! [`Spdifrx::try_read`] returns them as the `(left, right)` pair of words taken by
+//! [`SubBlock::try_send`](crate::sai::SubBlock::try_send) of the `sai` module, with the
+//! [`DataFormat`] matching the data size of the transmitter:
+//!
+//! - [`DataFormat::RightAligned`] for a 24-bit SAI
+//! - [`DataFormat::LeftAligned`] for a 32-bit SAI or I2S frame, with the sample in the upper
+//!   24 bits
+//! - [`DataFormat::Packed16`] for a 16-bit SAI or I2S frame
+//!
+//! # DMA
+//!
+//! Audio data is read with DMA1 stream 1, channel 0. [`Spdifrx`] is the peripheral of a
+//! [`Transfer`](crate::dma::Transfer) after [`Spdifrx::set_dma`], the buffers hold the
+//! words in the same order as [`Spdifrx::try_read`] returns them:
+//!
+//! ```ignore
+//! let mut spdifrx = Spdifrx::<0>::new(dp.SPDIFRX, gpiod.pd7, Config::default(), &mut rcc);
+//! spdifrx.set_dma(true);
+//!
+//! let streams = StreamsTuple::new(dp.DMA1, &mut rcc);
+//! let config = DmaConfig::default()
+//!     .memory_increment(true)
+//!     .double_buffer(true)
+//!     .transfer_complete_interrupt(true);
+//! let mut transfer =
+//!     Transfer::init_peripheral_to_memory(streams.1, spdifrx, first_half, Some(second_half), config);
+//! transfer.start(|spdifrx| spdifrx.start());
+//! ```
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::PeripheralToMemory;
+use crate::gpio::alt::SPdifIn;
+use crate::pac::{RCC, SPDIFRX};
+use crate::rcc::{Enable, Rcc, Reset, HSI};
+use crate::time::Hertz;
+use enumflags2::BitFlags;
+
+/// Framing, synchronization and timeout error bits of `SR`
+const SR_ERRORS: u32 = 0b111 << 6;
+
+/// Format of the audio data words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataFormat {
+    /// 24-bit sample in the lower bits of a word
+    RightAligned = 0,
+    /// 24-bit sample in the upper bits of a word
+    LeftAligned = 1,
+    /// Upper 16 bits of the samples of both channels in one word, the left channel in the
+    /// lower half word
+    Packed16 = 2,
+}
+
+/// Number of retries of the synchronization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Retries {
+    None = 0,
+    Three = 1,
+    Fifteen = 2,
+    SixtyThree = 3,
+}
+
+/// Channel of the channel status bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Channel {
+    /// Left channel
+    A = 0,
+    /// Right channel
+    B = 1,
+}
+
+/// Configuration of the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    format: DataFormat,
+    stereo: bool,
+    status_bits: bool,
+    channel: Channel,
+    retries: Retries,
+    wait_for_activity: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            format: DataFormat::RightAligned,
+            stereo: true,
+            status_bits: false,
+            channel: Channel::A,
+            retries: Retries::SixtyThree,
+            wait_for_activity: true,
+        }
+    }
+}
+
+impl Config {
+    /// Format of the audio data words
+    pub fn format(mut self, format: DataFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Keeps the left and right samples in order, which is the default. In mono mode the
+    /// receiver can't tell the channels apart.
+    pub fn stereo(mut self, stereo: bool) -> Self {
+        self.stereo = stereo;
+        self
+    }
+
+    /// Keeps the parity, validity, user and channel status bits and the preamble type in
+    /// the unused bits of the data words of [`DataFormat::RightAligned`] and
+    /// [`DataFormat::LeftAligned`]. By default they are cleared, so the words are samples.
+    pub fn status_bits(mut self, status_bits: bool) -> Self {
+        self.status_bits = status_bits;
+        self
+    }
+
+    /// Channel of [`Spdifrx::read_control`] and [`Spdifrx::read_channel_status`]
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Number of retries before a synchronization error
+    pub fn retries(mut self, retries: Retries) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Waits for transitions on the input before the synchronization, which is the
+    /// default. Otherwise the synchronization fails with [`Error::Timeout`] when no stream
+    /// is received.
+    pub fn wait_for_activity(mut self, wait_for_activity: bool) -> Self {
+        self.wait_for_activity = wait_for_activity;
+        self
+    }
+}
+
+/// Receive error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A sub-frame with a parity error was received
+    Parity,
+    /// Data was received while the data register was full
+    Overrun,
+    /// Wrong preamble or symbol, the receiver stopped
+    Framing,
+    /// The receiver did not synchronize within the retries, it stopped
+    Synchronization,
+    /// The stream has too long intervals between transitions, the receiver stopped
+    Timeout,
+}
+
+/// Interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    /// Audio data was received
+    RxNotEmpty = 1 << 0,
+    /// Control data was received
+    ControlNotEmpty = 1 << 1,
+    ParityError = 1 << 2,
+    Overrun = 1 << 3,
+    /// A channel status block starts
+    StartOfBlock = 1 << 4,
+    /// The synchronization completed
+    Synchronized = 1 << 5,
+    /// A framing, synchronization or timeout error stopped the receiver
+    Error = 1 << 6,
+}
+
+/// Control data of 8 frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Control {
+    /// User bits of both channels, alternating from bit 0
+    pub user: u16,
+    /// Channel status bits of the configured channel, from bit 0
+    pub channel_status: u8,
+    /// The first frame is the start of a channel status block
+    pub start_of_block: bool,
+}
+
+/// SPDIF receiver
+pub struct Spdifrx<const C: u8>
+where
+    SPDIFRX: SPdifIn<C>,
+{
+    spdifrx: SPDIFRX,
+    pin: <SPDIFRX as SPdifIn<C>>::In,
+    kernel_clock: Hertz,
+    /// Left sample received by [`try_read`](Self::try_read) without its right sample
+    pending: Option<u32>,
+}
+
+impl<const C: u8> Spdifrx<C>
+where
+    SPDIFRX: SPdifIn<C>,
+{
+    /// Configures the receiver on the input `C` of `pin`
+    ///
+    /// # Panics
+    ///
+    /// If the main PLL is not running, its `R` output clocks the SPDIFRX.
+    pub fn new(
+        spdifrx: SPDIFRX,
+        pin: impl Into<<SPDIFRX as SPdifIn<C>>::In>,
+        config: Config,
+        rcc: &mut Rcc,
+    ) -> Self {
+        SPDIFRX::enable(rcc);
+        SPDIFRX::reset(rcc);
+
+        let kernel_clock = pll_r_clock(rcc, rcc.clocks.hse());
+        rcc.dckcfgr2().modify(|_, w| w.spdifrxsel().pll());
+
+        spdifrx.cr().write(|w| unsafe {
+            w.insel().bits(C);
+            w.wfa().bit(config.wait_for_activity);
+            w.nbtr().bits(config.retries as u8);
+            w.chsel().bit(config.channel == Channel::B);
+            w.ptmsk().bit(!config.status_bits);
+            w.cumsk().bit(!config.status_bits);
+            w.vmsk().bit(!config.status_bits);
+            w.pmsk().bit(!config.status_bits);
+            w.drfmt().bits(config.format as u8);
+            w.rxsteo().bit(config.stereo)
+        });
+
+        Self {
+            spdifrx,
+            pin: pin.into(),
+            kernel_clock,
+            pending: None,
+        }
+    }
+
+    /// Stops the receiver and releases it with its pin
+    pub fn release(self) -> (SPDIFRX, <SPDIFRX as SPdifIn<C>>::In) {
+        self.stop();
+        (self.spdifrx, self.pin)
+    }
+
+    /// Synchronizes on the stream without receiving data, which measures the
+    /// [`sample_rate`](Self::sample_rate)
+    pub fn synchronize(&mut self) -> nb::Result<(), Error> {
+        if self.state() == 0b00 {
+            self.set_state(0b01);
+        }
+        self.check_errors()?;
+        if self.spdifrx.sr().read().syncd().bit_is_set() {
+            self.spdifrx.ifcr().write(|w| w.syncdcf().set_bit());
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Synchronizes if needed and receives the audio and control data
+    pub fn start(&mut self) {
+        self.pending = None;
+        self.set_state(0b11);
+    }
+
+    /// Stops the receiver, which clears the errors
+    pub fn stop(&self) {
+        self.set_state(0b00);
+    }
+
+    /// Receiver is synchronized on the stream
+    pub fn is_synchronized(&self) -> bool {
+        self.state() != 0b00 && self.spdifrx.sr().read().width5().bits() != 0
+    }
+
+    /// Sample rate of the stream measured by the synchronization
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        // WIDTH5 is the number of kernel clock cycles of 5 consecutive symbols, a frame
+        // has 64 symbols
+        let width5 = self.spdifrx.sr().read().width5().bits() as u32;
+        (self.state() != 0b00 && width5 != 0)
+            .then(|| Hertz::Hz(self.kernel_clock.raw() / width5 * 5 / 64))
+    }
+
+    /// Moves audio data with DMA
+    pub fn set_dma(&mut self, dma: bool) {
+        self.spdifrx.cr().modify(|_, w| w.rxdmaen().bit(dma));
+    }
+
+    /// Reads a word of audio data
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        self.check_errors()?;
+        if self.spdifrx.sr().read().rxne().bit_is_set() {
+            Ok(self.spdifrx.dr().read().bits())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Reads the samples of a stereo frame
+    ///
+    /// The left sample is kept until the right one is received, one sub-frame later.
+    pub fn try_read(&mut self) -> nb::Result<(u32, u32), Error> {
+        if self.format() == DataFormat::Packed16 {
+            let word = self.read()?;
+            return Ok((word & 0xffff, word >> 16));
+        }
+        let first = match self.pending.take() {
+            Some(first) => first,
+            None => self.read()?,
+        };
+        match self.read() {
+            Ok(second) => Ok((first, second)),
+            Err(nb::Error::WouldBlock) => {
+                self.pending = Some(first);
+                Err(nb::Error::WouldBlock)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Reads the control data of the next 8 frames
+    pub fn read_control(&mut self) -> nb::Result<Control, Error> {
+        self.check_errors()?;
+        let sr = self.spdifrx.sr().read();
+        if sr.csrne().bit_is_set() {
+            let csr = self.spdifrx.csr().read();
+            Ok(Control {
+                user: csr.usr().bits(),
+                channel_status: csr.cs().bits(),
+                start_of_block: csr.sob().bit_is_set(),
+            })
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Waits for the next channel status block of 192 frames, the first byte is byte 0 of
+    /// the block
+    pub fn read_channel_status(&mut self) -> Result<[u8; 24], Error> {
+        let mut block = [0; 24];
+        let mut len = 0;
+        while len < block.len() {
+            let control = nb::block!(self.read_control())?;
+            if control.start_of_block {
+                len = 0;
+            }
+            if len > 0 || control.start_of_block {
+                block[len] = control.channel_status;
+                len += 1;
+            }
+        }
+        Ok(block)
+    }
+
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let sr = self.spdifrx.sr().read();
+        let error = if sr.perr().bit_is_set() {
+            Error::Parity
+        } else if sr.ovr().bit_is_set() {
+            Error::Overrun
+        } else if sr.ferr().bit_is_set() {
+            Error::Framing
+        } else if sr.serr().bit_is_set() {
+            Error::Synchronization
+        } else if sr.terr().bit_is_set() {
+            Error::Timeout
+        } else {
+            return Ok(());
+        };
+        match error {
+            Error::Parity | Error::Overrun => {
+                self.spdifrx.ifcr().write(|w| {
+                    w.perrcf().set_bit();
+                    w.ovrcf().set_bit()
+                });
+            }
+            // The receiver is idle, stopping it clears the flags for the next start
+            _ => self.stop(),
+        }
+        Err(error)
+    }
+
+    fn format(&self) -> DataFormat {
+        match self.spdifrx.cr().read().drfmt().bits() {
+            0 => DataFormat::RightAligned,
+            1 => DataFormat::LeftAligned,
+            _ => DataFormat::Packed16,
+        }
+    }
+
+    fn state(&self) -> u8 {
+        self.spdifrx.cr().read().spdifen().bits()
+    }
+
+    fn set_state(&self, state: u8) {
+        self.spdifrx
+            .cr()
+            .modify(|_, w| unsafe { w.spdifen().bits(state) });
+    }
+}
+
+/// Frequency of the `R` output of the main PLL, computed from its input clock and its
+/// dividers
+fn pll_r_clock(rcc: &RCC, hse: Option<Hertz>) -> Hertz {
+    assert!(
+        rcc.cr().read().pllrdy().bit_is_set(),
+        "the main PLL must be running"
+    );
+    let pllcfgr = rcc.pllcfgr().read();
+    let input = if pllcfgr.pllsrc().is_hse() {
+        hse.expect("the HSE frequency must be known").raw()
+    } else {
+        HSI
+    };
+    let m = pllcfgr.pllm().bits() as u64;
+    let n = pllcfgr.plln().bits() as u64;
+    let r = pllcfgr.pllr().bits() as u64;
+    Hertz::Hz((input as u64 * n / (m * r)) as u32)
+}
+
+impl<const C: u8> crate::Listen for Spdifrx<C>
+where
+    SPDIFRX: SPdifIn<C>,
+{
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.spdifrx
+            .imr()
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.into().bits()) });
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.spdifrx
+            .imr()
+            .write(|w| unsafe { w.bits(event.into().bits()) });
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Event>>) {
+        self.spdifrx
+            .imr()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.into().bits()) });
+    }
+}
+
+impl<const C: u8> crate::ReadFlags for Spdifrx<C>
+where
+    SPDIFRX: SPdifIn<C>,
+{
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Event> {
+        let sr = self.spdifrx.sr().read().bits();
+        let error = if sr & SR_ERRORS != 0 {
+            Event::Error as u32
+        } else {
+            0
+        };
+        BitFlags::from_bits_truncate(sr & 0x3f | error)
+    }
+}
+
+impl<const C: u8> crate::ClearFlags for Spdifrx<C>
+where
+    SPDIFRX: SPdifIn<C>,
+{
+    type Flag = Event;
+
+    /// Clears [`Event::ParityError`], [`Event::Overrun`], [`Event::StartOfBlock`] and
+    /// [`Event::Synchronized`]. [`Event::Error`] is cleared by [`stop`](Spdifrx::stop),
+    /// the others by reading the data.
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Event>>) {
+        let flags = flags.into();
+        self.spdifrx.ifcr().write(|w| {
+            w.perrcf().bit(flags.contains(Event::ParityError));
+            w.ovrcf().bit(flags.contains(Event::Overrun));
+            w.sbdcf().bit(flags.contains(Event::StartOfBlock));
+            w.syncdcf().bit(flags.contains(Event::Synchronized))
+        });
+    }
+}
+
+unsafe impl<const C: u8> PeriAddress for Spdifrx<C>
+where
+    SPDIFRX: SPdifIn<C>,
+{
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        self.spdifrx.dr().as_ptr() as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<STREAM, const CHANNEL: u8, const C: u8> DMASet<STREAM, CHANNEL, PeripheralToMemory>
+    for Spdifrx<C>
+where
+    SPDIFRX: SPdifIn<C> + DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+{
+}
@@ -33,8 +33,12 @@ pub struct Mode {
     pub phase: Phase,
 }
 
+#[cfg(feature = "async")]
+mod asynch;
 mod hal_02;
 mod hal_1;
+#[cfg(feature = "async")]
+pub use asynch::SpiAsync;
 
 use crate::pac::{spi1, RCC};
 use crate::rcc::{self, Rcc};
@@ -53,6 +57,8 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
+    /// DMA transfer error
+    Dma,
 }
 
 /// SPI interrupt events
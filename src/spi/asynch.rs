@@ -0,0 +1,239 @@
+use super::{Error, FrameSize, Instance, Rx, Spi, Tx};
+use crate::dma::{
+    traits::{Channel, DMASet, Stream},
+    ChannelX, DmaDirection, MemoryToPeripheral, OneShot, PeripheralToMemory,
+};
+use embedded_hal::spi::ErrorType;
+
+impl<SPI: Instance, W: FrameSize> Spi<SPI, false, W> {
+    /// Converts blocking [`Spi`] to [`SpiAsync`] that uses `tx_stream` and `rx_stream` to send/receive data
+    pub fn into_async<TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8>(
+        mut self,
+        tx_stream: TX_STREAM,
+        rx_stream: RX_STREAM,
+    ) -> SpiAsync<SPI, TX_STREAM, TX_CH, RX_STREAM, RX_CH, W>
+    where
+        TX_STREAM: Stream,
+        ChannelX<TX_CH>: Channel,
+        Tx<SPI>: DMASet<TX_STREAM, TX_CH, MemoryToPeripheral>,
+
+        RX_STREAM: Stream,
+        ChannelX<RX_CH>: Channel,
+        Rx<SPI>: DMASet<RX_STREAM, RX_CH, PeripheralToMemory>,
+    {
+        // Drop a word which could be left from blocking transfers
+        if self.is_rx_not_empty() {
+            let _: W = self.read_data_reg();
+        }
+        self.spi.cr2().modify(|_, w| {
+            w.txdmaen().enabled();
+            w.rxdmaen().enabled()
+        });
+
+        SpiAsync {
+            spi: self,
+            tx_stream,
+            rx_stream,
+        }
+    }
+}
+
+/// Spi in Master mode that sends/receives data with DMA
+///
+/// Implements [`embedded_hal_async::spi::SpiBus`]. Every transfer completes from the
+/// DMA interrupts, so a client must:
+/// * Enable interrupts DMAx_STREAMy used for transmit and another DMAq_STREAMp used for receive.
+/// * In these interrupts call [`StreamX::on_interrupt`](crate::dma::StreamX::on_interrupt).
+pub struct SpiAsync<SPI, TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8, W = u8>
+where
+    SPI: Instance,
+{
+    spi: Spi<SPI, false, W>,
+    tx_stream: TX_STREAM,
+    rx_stream: RX_STREAM,
+}
+
+impl<SPI, TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8, W>
+    SpiAsync<SPI, TX_STREAM, TX_CH, RX_STREAM, RX_CH, W>
+where
+    SPI: Instance,
+    W: FrameSize,
+    TX_STREAM: Stream,
+    ChannelX<TX_CH>: Channel,
+    RX_STREAM: Stream,
+    ChannelX<RX_CH>: Channel,
+{
+    /// Disables DMA requests and returns the blocking [`Spi`] and the DMA streams
+    pub fn release(self) -> (Spi<SPI, false, W>, TX_STREAM, RX_STREAM) {
+        self.spi.spi.cr2().modify(|_, w| {
+            w.txdmaen().disabled();
+            w.rxdmaen().disabled()
+        });
+        (self.spi, self.tx_stream, self.rx_stream)
+    }
+
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let spi = &self.spi.spi;
+        let sr = spi.sr().read();
+
+        if sr.ovr().bit_is_set() {
+            // Read from the DR and SR to clear the OVR bit
+            let _ = spi.dr().read();
+            let _ = spi.sr().read();
+            Err(Error::Overrun)
+        } else if sr.modf().bit_is_set() {
+            // Write to CR1 to clear MODF
+            spi.cr1().modify(|_r, w| w);
+            Err(Error::ModeFault)
+        } else if sr.crcerr().bit_is_set() {
+            // Clear the CRCERR bit
+            spi.sr().modify(|_r, w| w.crcerr().clear_bit());
+            Err(Error::Crc)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Transfers `len` words. A missing `read` or `write` buffer is replaced with a single
+    /// dummy word which isn't incremented.
+    async fn transfer_dma(
+        &mut self,
+        read: Option<*mut W>,
+        write: Option<*const W>,
+        len: usize,
+    ) -> Result<(), Error> {
+        let mut rx_dummy = W::default();
+        let tx_dummy = W::default();
+        let (rx_ptr, rx_inc) = match read {
+            Some(ptr) => (ptr, true),
+            None => (&mut rx_dummy as *mut W, false),
+        };
+        let (tx_ptr, tx_inc) = match write {
+            Some(ptr) => (ptr, true),
+            None => (&tx_dummy as *const W, false),
+        };
+        let dr = unsafe { (*SPI::PTR).dr().as_ptr() as u32 };
+
+        let mut done = 0;
+        while done < len {
+            let n = (len - done).min(u16::MAX as usize);
+            let rx_buf = if rx_inc {
+                rx_ptr.wrapping_add(done)
+            } else {
+                rx_ptr
+            };
+            let tx_buf = if tx_inc {
+                tx_ptr.wrapping_add(done)
+            } else {
+                tx_ptr
+            };
+            // NOTE(unsafe) buffers outlive the transfers which are stopped on drop,
+            // TX stream never overtakes RX stream when both use the same buffer
+            let mut rx = unsafe {
+                OneShot::start(
+                    &mut self.rx_stream,
+                    ChannelX::<RX_CH>::VALUE,
+                    DmaDirection::PeripheralToMemory,
+                    dr,
+                    rx_buf as *const W,
+                    n as u16,
+                    rx_inc,
+                )
+            };
+            let mut tx = unsafe {
+                OneShot::start(
+                    &mut self.tx_stream,
+                    ChannelX::<TX_CH>::VALUE,
+                    DmaDirection::MemoryToPeripheral,
+                    dr,
+                    tx_buf,
+                    n as u16,
+                    tx_inc,
+                )
+            };
+            tx.wait().await.map_err(|_| Error::Dma)?;
+            rx.wait().await.map_err(|_| Error::Dma)?;
+            drop(tx);
+            drop(rx);
+
+            self.check_errors()?;
+            done += n;
+        }
+
+        Ok(())
+    }
+
+    /// Reads `words`, sending a dummy word for each
+    pub async fn read(&mut self, words: &mut [W]) -> Result<(), Error> {
+        self.transfer_dma(Some(words.as_mut_ptr()), None, words.len())
+            .await
+    }
+
+    /// Writes `words`, the received words are dropped
+    pub async fn write(&mut self, words: &[W]) -> Result<(), Error> {
+        self.transfer_dma(None, Some(words.as_ptr()), words.len())
+            .await
+    }
+
+    /// Writes `data` while reading `buff`. The longer one goes on with dummy words sent or
+    /// the received words dropped.
+    pub async fn transfer(&mut self, buff: &mut [W], data: &[W]) -> Result<(), Error> {
+        let common = buff.len().min(data.len());
+        self.transfer_dma(Some(buff.as_mut_ptr()), Some(data.as_ptr()), common)
+            .await?;
+        if buff.len() > common {
+            self.read(&mut buff[common..]).await
+        } else if data.len() > common {
+            self.write(&data[common..]).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes `words` and replaces them with the received ones
+    pub async fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Error> {
+        let ptr = words.as_mut_ptr();
+        self.transfer_dma(Some(ptr), Some(ptr as *const W), words.len())
+            .await
+    }
+}
+
+impl<SPI, TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8, W> ErrorType
+    for SpiAsync<SPI, TX_STREAM, TX_CH, RX_STREAM, RX_CH, W>
+where
+    SPI: Instance,
+{
+    type Error = Error;
+}
+
+impl<SPI, TX_STREAM, const TX_CH: u8, RX_STREAM, const RX_CH: u8, W>
+    embedded_hal_async::spi::SpiBus<W> for SpiAsync<SPI, TX_STREAM, TX_CH, RX_STREAM, RX_CH, W>
+where
+    SPI: Instance,
+    W: FrameSize + 'static,
+    TX_STREAM: Stream,
+    ChannelX<TX_CH>: Channel,
+    RX_STREAM: Stream,
+    ChannelX<RX_CH>: Channel,
+{
+    async fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.read(words).await
+    }
+
+    async fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+        self.write(words).await
+    }
+
+    async fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.transfer(read, write).await
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.transfer_in_place(words).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        // Every transfer waits for the last received word
+        Ok(())
+    }
+}
@@ -175,10 +175,13 @@ pub enum Flag {
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Timer is disabled
     Disabled,
     WrongAutoReload,
+    /// A new capture overwrote the previous one before it was read
+    Overcapture,
 }
 
 pub trait TimerExt: Sized {
@@ -465,6 +468,8 @@ mod sealed {
         fn prescaler_capture(&mut self, c: u8, psc: CapturePrescaler);
         fn filter_capture(&mut self, c: u8, filter: CaptureFilter);
         fn start_capture(&mut self);
+        #[cfg(all(feature = "async", not(feature = "gpio-f410")))]
+        fn capture_state() -> &'static super::capture::CaptureState;
     }
 
     pub trait MasterTimer: General {
@@ -870,6 +875,12 @@ macro_rules! with_output {
             fn start_capture(&mut self) {
                 self.cr1().modify(|_, w| w.cen().set_bit());
             }
+
+            #[cfg(all(feature = "async", not(feature = "gpio-f410")))]
+            fn capture_state() -> &'static capture::CaptureState {
+                static STATE: capture::CaptureState = capture::CaptureState::new();
+                &STATE
+            }
         }
     };
     ($TIM:ty: 1) => {
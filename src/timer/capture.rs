@@ -27,6 +27,11 @@ use crate::rcc::Rcc;
 use core::ops::{Deref, DerefMut};
 use fugit::HertzU32 as Hertz;
 
+#[cfg(feature = "async")]
+mod asynch;
+#[cfg(feature = "async")]
+pub(crate) use asynch::CaptureState;
+
 pub trait CaptureExt
 where
     Self: Sized + Instance + WithCapture + SplitCapture,
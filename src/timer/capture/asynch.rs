@@ -0,0 +1,219 @@
+//! Async input capture completed from the timer interrupt.
+//!
+//! Captures are taken by [`CaptureHzManager::on_interrupt`], which must be called from every
+//! interrupt handler of the timer (both `TIMx_CC` and `TIMx_UP` of advanced timers):
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn TIM5() {
+//!     CaptureHzManager::<pac::TIM5>::on_interrupt();
+//! }
+//! ```
+//!
+//! Counter overflows are counted by the same handler after
+//! [`enable_timestamps`](CaptureHzManager::enable_timestamps), so captures can be extended
+//! to monotonic 32 or 64-bit timestamps.
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
+    task::Poll,
+};
+
+use atomic_waker::AtomicWaker;
+use enumflags2::BitFlags;
+
+use super::{CPin, CaptureChannel, CaptureErasedChannel, CaptureHzManager};
+use crate::timer::{Error, Event, Flag, Instance, WithCapture};
+
+/// Captures of one timer, written by [`CaptureHzManager::on_interrupt`]
+pub struct CaptureState {
+    wakers: [AtomicWaker; 4],
+    /// Counter overflows since [`CaptureHzManager::enable_timestamps`]
+    overflows: AtomicU32,
+    values: [AtomicU32; 4],
+    /// Overflow count of each capture
+    periods: [AtomicU32; 4],
+    /// Channels waiting for a capture
+    waiting: AtomicU8,
+    /// Channels with a capture not read yet
+    ready: AtomicU8,
+    /// Channels overcaptured while waiting
+    overcaptured: AtomicU8,
+}
+
+impl CaptureState {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW_WAKER: AtomicWaker = AtomicWaker::new();
+
+    pub(crate) const fn new() -> Self {
+        Self {
+            wakers: [Self::NEW_WAKER; 4],
+            overflows: AtomicU32::new(0),
+            values: [Self::ZERO; 4],
+            periods: [Self::ZERO; 4],
+            waiting: AtomicU8::new(0),
+            ready: AtomicU8::new(0),
+            overcaptured: AtomicU8::new(0),
+        }
+    }
+}
+
+const fn cc_flag(c: u8) -> u32 {
+    (Flag::C1 as u32) << c
+}
+
+const fn cc_event(c: u8) -> u32 {
+    (Event::C1 as u32) << c
+}
+
+const fn overcapture_flag(c: u8) -> u32 {
+    (Flag::C1Overcapture as u32) << c
+}
+
+impl<TIM> CaptureHzManager<TIM>
+where
+    TIM: Instance + WithCapture + crate::Steal,
+{
+    /// Takes the captures of the waiting channels and counts counter overflows.
+    ///
+    /// Must be called from the interrupt handlers of the timer when its channels are used
+    /// asynchronously. The capture interrupt of a channel is disabled once its capture is
+    /// taken, the woken task enables it again for the next one.
+    pub fn on_interrupt() {
+        let mut tim = unsafe { TIM::steal() };
+        let state = TIM::capture_state();
+        let flags = tim.get_interrupt_flag().bits();
+        let overflow = flags & Flag::Update as u32 != 0;
+        let overflows = state.overflows.load(Ordering::Relaxed);
+        let half = TIM::read_auto_reload() / 2;
+        let waiting = state.waiting.load(Ordering::Relaxed);
+
+        let mut done = 0;
+        let mut events = 0;
+        let mut overcaptures = 0;
+        for c in 0..TIM::CH_NUMBER {
+            if waiting & (1 << c) != 0 && flags & cc_flag(c) != 0 {
+                // Reading CCR clears the capture flag
+                let value = TIM::read_cc_value(c);
+                // A small value with a pending overflow was captured after the overflow
+                let period = overflows.wrapping_add((overflow && value < half) as u32);
+                state.values[c as usize].store(value, Ordering::Relaxed);
+                state.periods[c as usize].store(period, Ordering::Relaxed);
+                if flags & overcapture_flag(c) != 0 {
+                    state.overcaptured.fetch_or(1 << c, Ordering::Relaxed);
+                }
+                done |= 1 << c;
+                events |= cc_event(c);
+                overcaptures |= overcapture_flag(c);
+            }
+        }
+        if done != 0 {
+            tim.listen_event(Some(BitFlags::from_bits_truncate(events)), None);
+            tim.clear_interrupt_flag(BitFlags::from_bits_truncate(overcaptures));
+            state.waiting.fetch_and(!done, Ordering::Relaxed);
+            state.ready.fetch_or(done, Ordering::Release);
+            for c in 0..TIM::CH_NUMBER {
+                if done & (1 << c) != 0 {
+                    state.wakers[c as usize].wake();
+                }
+            }
+        }
+        if overflow {
+            tim.clear_interrupt_flag(Flag::Update.into());
+            state
+                .overflows
+                .store(overflows.wrapping_add(1), Ordering::Relaxed);
+        }
+    }
+
+    /// Starts counting counter overflows from zero with the update interrupt,
+    /// which is needed by `wait_timestamp` and `wait_timestamp32` of the channels
+    pub fn enable_timestamps(&mut self) {
+        TIM::capture_state().overflows.store(0, Ordering::Relaxed);
+        self.tim.clear_interrupt_flag(Flag::Update.into());
+        cortex_m::interrupt::free(|_| self.tim.listen_event(None, Some(Event::Update.into())));
+    }
+
+    /// Stops counting counter overflows
+    pub fn disable_timestamps(&mut self) {
+        cortex_m::interrupt::free(|_| self.tim.listen_event(Some(Event::Update.into()), None));
+    }
+}
+
+/// Waits for the next capture on channel `c`, returns the captured value and its overflow count
+async fn wait<TIM>(c: u8) -> Result<(u32, u32), Error>
+where
+    TIM: Instance + WithCapture + crate::Steal,
+{
+    let mut tim = unsafe { TIM::steal() };
+    let state = TIM::capture_state();
+    let bit = 1 << c;
+
+    // Only captures made after the call are returned
+    state.ready.fetch_and(!bit, Ordering::Relaxed);
+    state.overcaptured.fetch_and(!bit, Ordering::Relaxed);
+    tim.clear_interrupt_flag(BitFlags::from_bits_truncate(
+        cc_flag(c) | overcapture_flag(c),
+    ));
+    state.waiting.fetch_or(bit, Ordering::Relaxed);
+    cortex_m::interrupt::free(|_| {
+        tim.listen_event(None, Some(BitFlags::from_bits_truncate(cc_event(c))))
+    });
+
+    poll_fn(|cx| {
+        state.wakers[c as usize].register(cx.waker());
+        if state.ready.load(Ordering::Acquire) & bit == 0 {
+            return Poll::Pending;
+        }
+        state.ready.fetch_and(!bit, Ordering::Relaxed);
+        if state.overcaptured.fetch_and(!bit, Ordering::Relaxed) & bit != 0 {
+            return Poll::Ready(Err(Error::Overcapture));
+        }
+        Poll::Ready(Ok((
+            state.values[c as usize].load(Ordering::Relaxed),
+            state.periods[c as usize].load(Ordering::Relaxed),
+        )))
+    })
+    .await
+}
+
+/// Extends the captured `value` with its overflow count
+fn timestamp<TIM: WithCapture>((value, period): (u32, u32)) -> u64 {
+    u64::from(period) * (u64::from(TIM::read_auto_reload()) + 1) + u64::from(value)
+}
+
+macro_rules! async_ch_impl {
+    () => {
+        /// Waits for the next capture and returns the captured counter value.
+        ///
+        /// Returns [`Error::Overcapture`] when a capture was lost while the interrupt was pending.
+        pub async fn wait_capture(&mut self) -> Result<u32, Error> {
+            wait::<TIM>(self.channel()).await.map(|(value, _)| value)
+        }
+
+        /// Waits for the next capture and returns it as a 64-bit timestamp
+        /// in timer counter ticks since [`CaptureHzManager::enable_timestamps`]
+        pub async fn wait_timestamp(&mut self) -> Result<u64, Error> {
+            wait::<TIM>(self.channel()).await.map(timestamp::<TIM>)
+        }
+
+        /// Like [`wait_timestamp`](Self::wait_timestamp) but wraps after `u32::MAX` ticks
+        pub async fn wait_timestamp32(&mut self) -> Result<u32, Error> {
+            self.wait_timestamp().await.map(|t| t as u32)
+        }
+    };
+}
+
+impl<TIM, const C: u8, const COMP: bool, Otype> CaptureChannel<TIM, C, COMP, Otype>
+where
+    TIM: Instance + WithCapture + CPin<C> + crate::Steal,
+{
+    async_ch_impl!();
+}
+
+impl<TIM: Instance + WithCapture + crate::Steal> CaptureErasedChannel<TIM> {
+    async_ch_impl!();
+}
@@ -1,8 +1,11 @@
 //! Watchdog peripherals
 
-use crate::pac::{DBGMCU, IWDG};
+use crate::pac::{DBGMCU, IWDG, WWDG};
+use crate::rcc::{Enable, Rcc, Reset};
+use crate::time::Hertz;
 use core::fmt;
 use embedded_hal_02::watchdog::{Watchdog, WatchdogEnable};
+use fugit::MicrosDurationU32 as MicroSeconds;
 use fugit::MillisDurationU32 as MilliSeconds;
 
 /// Wraps the Independent Watchdog (IWDG) peripheral
@@ -131,3 +134,163 @@ impl Watchdog for IndependentWatchdog {
         self.feed()
     }
 }
+
+/// Wraps the Window Watchdog (WWDG) peripheral
+///
+/// The watchdog resets the MCU when it is not fed before the timeout, and also when it is
+/// fed too early, before the window opens. This catches the loops that run too fast as
+/// well as the stuck ones.
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    pclk1: Hertz,
+    /// Counter value written by the feeds, 0 until started
+    reload: u8,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for WindowWatchdog {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "WindowWatchdog");
+    }
+}
+
+impl fmt::Debug for WindowWatchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WindowWatchdog")
+    }
+}
+
+/// The feed was refused by [`WindowWatchdog::try_feed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TooEarly;
+
+/// Period of the counter clock: PCLK1 / 4096 / 2^WDGTB
+const WWDG_DIV: u64 = 4096;
+const WWDG_MAX_TB: u8 = 3;
+/// Ticks of the counter from the feed to the reset
+const WWDG_MAX_TICKS: u64 = 64;
+/// The reset happens when the counter goes below this value
+const WWDG_T_MIN: u8 = 0x40;
+
+impl WindowWatchdog {
+    /// Creates a new `WindowWatchdog` without starting it. Call `start` to start the watchdog.
+    pub fn new(wwdg: WWDG, rcc: &mut Rcc) -> Self {
+        WWDG::enable(rcc);
+        WWDG::reset(rcc);
+        WindowWatchdog {
+            wwdg,
+            pclk1: rcc.clocks.pclk1(),
+            reload: 0,
+        }
+    }
+
+    /// Debug window watchdog stopped when core is halted
+    pub fn stop_on_debug(&self, dbgmcu: &DBGMCU, stop: bool) {
+        dbgmcu.apb1_fz().modify(|_, w| w.dbg_wwdg_stop().bit(stop));
+    }
+
+    /// Starts the watchdog, which must then be fed between `min_feed` and `timeout` after
+    /// the previous feed. The watchdog can only be stopped by a reset.
+    ///
+    /// The window is rounded to ticks of the counter, `timeout` down and `min_feed` up.
+    ///
+    /// # Panics
+    ///
+    /// If `timeout` is out of range for PCLK1, or the window is empty.
+    pub fn start(&mut self, min_feed: MicroSeconds, timeout: MicroSeconds) {
+        let pclk1 = self.pclk1.raw() as u64;
+        let ticks = |us: u32, tb: u8| us as u64 * pclk1 / (1_000_000 * (WWDG_DIV << tb));
+        let tb = (0..=WWDG_MAX_TB)
+            .find(|&tb| ticks(timeout.ticks(), tb) <= WWDG_MAX_TICKS)
+            .expect("Watchdog timeout to high");
+        let timeout_ticks = ticks(timeout.ticks(), tb);
+        assert!(timeout_ticks > 0, "Watchdog timeout to low");
+        let period = 1_000_000 * (WWDG_DIV << tb);
+        let min_ticks = (min_feed.ticks() as u64 * pclk1 + period - 1) / period;
+        assert!(min_ticks < timeout_ticks, "Watchdog window is empty");
+
+        let t = WWDG_T_MIN - 1 + timeout_ticks as u8;
+        self.wwdg.cfr().modify(|_, w| {
+            w.wdgtb().set(tb);
+            w.w().set(t - min_ticks as u8)
+        });
+        self.reload = t;
+        self.wwdg.cr().write(|w| {
+            w.t().set(t);
+            w.wdga().set_bit()
+        });
+    }
+
+    /// Returns the timeout in µs
+    pub fn timeout(&self) -> MicroSeconds {
+        let tb = self.wwdg.cfr().read().wdgtb().bits();
+        self.ticks_to_us((self.reload + 1).saturating_sub(WWDG_T_MIN), tb)
+    }
+
+    /// Returns the time in µs after a feed before the window opens
+    pub fn min_feed(&self) -> MicroSeconds {
+        let cfr = self.wwdg.cfr().read();
+        self.ticks_to_us(
+            self.reload.saturating_sub(cfr.w().bits()),
+            cfr.wdgtb().bits(),
+        )
+    }
+
+    /// Feeds the watchdog, which resets the MCU if the window is not open yet. Does nothing
+    /// before [`start`](Self::start).
+    pub fn feed(&mut self) {
+        if self.reload != 0 {
+            self.wwdg.cr().write(|w| {
+                w.t().set(self.reload);
+                w.wdga().set_bit()
+            });
+        }
+    }
+
+    /// Feeds the watchdog if the window is open, otherwise does nothing and returns an error
+    pub fn try_feed(&mut self) -> Result<(), TooEarly> {
+        if self.wwdg.cr().read().t().bits() > self.wwdg.cfr().read().w().bits() {
+            Err(TooEarly)
+        } else {
+            self.feed();
+            Ok(())
+        }
+    }
+
+    /// Triggers the `WWDG` interrupt one tick of the counter before the reset, for last-gasp
+    /// logging. The interrupt can only be disabled by a reset.
+    pub fn enable_early_wakeup(&mut self) {
+        self.wwdg.cfr().modify(|_, w| w.ewi().enable());
+    }
+
+    /// Returns `true` if the early wakeup interrupt is pending
+    pub fn is_early_wakeup(&self) -> bool {
+        self.wwdg.sr().read().ewif().is_pending()
+    }
+
+    /// Clears the early wakeup interrupt
+    pub fn clear_early_wakeup(&mut self) {
+        self.wwdg.sr().write(|w| w.ewif().finished());
+    }
+
+    fn ticks_to_us(&self, ticks: u8, tb: u8) -> MicroSeconds {
+        let us = ticks as u64 * (WWDG_DIV << tb) * 1_000_000 / self.pclk1.raw() as u64;
+        MicroSeconds::from_ticks(us as u32)
+    }
+}
+
+impl WatchdogEnable for WindowWatchdog {
+    type Time = MicroSeconds;
+
+    /// Starts the watchdog without window, it can be fed any time before `period`
+    fn start<T: Into<Self::Time>>(&mut self, period: T) {
+        self.start(MicroSeconds::from_ticks(0), period.into())
+    }
+}
+
+impl Watchdog for WindowWatchdog {
+    fn feed(&mut self) {
+        self.feed()
+    }
+}